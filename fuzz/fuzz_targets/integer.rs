@@ -0,0 +1,52 @@
+//! Fuzz the hpack integer decoder
+//!
+//! cargo fuzz run integer
+//!
+//! http2 is a binary crate so the integer module is
+//! pulled in by path instead of as a dependency
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate core;
+
+#[path = "../../src/header/hpack/integers.rs"]
+#[allow(dead_code)]
+mod integers;
+
+use integers::{decode_integer, encode_integer, PrefixSize};
+
+fuzz_target!(|data: &[u8]| {
+    // first octet picks the prefix size, the rest is the integer
+    if data.len() < 2 {
+        return;
+    }
+    let prefix_size = data[0] % 8 + 1;
+    let prefix = PrefixSize::new(prefix_size).unwrap();
+    let input = &data[1..];
+
+    let mut bts = input.iter();
+    let result = decode_integer(&mut bts, prefix);
+    let used = input.len() - bts.len();
+
+    // the octet limit stops the decoder after the prefix and 5 continuation
+    // octets (plus the one that went over the limit when it fails)
+    assert!(used <= 7);
+
+    if let Ok(n) = result {
+        assert!(used <= 6);
+
+        // the minimal encoding is the only one accepted
+        // so it has to give back the same octets
+        let mut buf = [0u8; 6];
+        let written = encode_integer(n, &mut buf.iter_mut(), prefix).unwrap();
+        assert_eq!(written, used);
+
+        let mut expected = input[..used].to_vec();
+        if prefix_size < 8 {
+            expected[0] &= (1u8 << prefix_size) - 1;
+        }
+        assert_eq!(&buf[..written], &expected[..]);
+    }
+});
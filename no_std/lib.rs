@@ -0,0 +1,21 @@
+//! Build the hpack integer codec as a #![no_std] crate, so the
+//! no_std feature is checked to really only need core
+//!
+//! cargo build --manifest-path no_std/Cargo.toml
+//!
+//! or for a target without std at all (after rustup target add)
+//!
+//! cargo build --manifest-path no_std/Cargo.toml --target thumbv7em-none-eabihf
+//!
+//! http2 is a binary crate so the integer module is
+//! pulled in by path the same way fuzz/ does it
+
+#![no_std]
+
+#[path = "../src/header/hpack/integers.rs"]
+#[allow(dead_code)]
+mod integers;
+
+pub use integers::{decode_integer, decode_integer_u64, encode_integer, encode_integer_u64, encode_integer_into,
+    IntegerDecoder, IntegerPoll, IntegerError, PrefixSize, PREFIX_4, PREFIX_5, PREFIX_6, PREFIX_7, PREFIX_8,
+    MAX_INTEGER_LEN, MAX_INTEGER_LEN_U64};
@@ -0,0 +1,251 @@
+//! Errors found while reading frames
+//!
+//! Each one says which HTTP2 error code it
+//! should be reported with
+
+use std::fmt;
+use std::error::Error;
+
+use header::{HpackError, IntegerError, HuffmanError, ProtocolError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// the frame length is wrong for its type (FRAME_SIZE_ERROR)
+    FrameSize,
+    /// the frame breaks a rule of the protocol (PROTOCOL_ERROR)
+    /// eg. more padding than there is payload
+    Protocol,
+    /// a flow control window went past 2^31-1 or
+    /// more was sent than the window allowed (FLOW_CONTROL_ERROR)
+    FlowControl,
+    /// the fragments of a header block went over the limit
+    /// set on the HeaderBlock (COMPRESSION_ERROR)
+    HeaderBlockTooLarge,
+    /// the connection did not start with the client preface (PROTOCOL_ERROR)
+    InvalidPreface,
+}
+
+impl FrameError {
+    // the code to send in the GOAWAY or RST_STREAM
+    pub fn error_code(&self) -> ErrorCode {
+        match *self {
+            FrameError::FrameSize => ErrorCode::FrameSizeError,
+            FrameError::Protocol  => ErrorCode::ProtocolError,
+            FrameError::FlowControl => ErrorCode::FlowControlError,
+            FrameError::HeaderBlockTooLarge => ErrorCode::CompressionError,
+            FrameError::InvalidPreface => ErrorCode::ProtocolError,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match *self {
+            FrameError::FrameSize => "frame: invalid frame size",
+            FrameError::Protocol  => "frame: protocol error",
+            FrameError::FlowControl => "frame: flow control window exceeded",
+            FrameError::HeaderBlockTooLarge => "frame: header block fragments exceed the maximum size",
+            FrameError::InvalidPreface => "frame: invalid connection preface",
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Error for FrameError {
+    fn description(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Any error from reading frames or the header blocks in them
+///
+/// The errors of each part (hpack, huffman, frames) all convert
+/// into this with try! so code that deals with a whole connection
+/// only has one type to handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Http2Error {
+    Frame(FrameError),
+    Hpack(HpackError),
+}
+
+impl Http2Error {
+    // the code to send in the GOAWAY or RST_STREAM
+    pub fn error_code(&self) -> ErrorCode {
+        match *self {
+            Http2Error::Frame(ref e) => e.error_code(),
+            Http2Error::Hpack(ref e) => e.error_code(),
+        }
+    }
+}
+
+impl fmt::Display for Http2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Http2Error::Frame(ref e) => write!(f, "{}", e),
+            Http2Error::Hpack(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for Http2Error {
+    fn description(&self) -> &str {
+        "http2 error"
+    }
+}
+
+impl From<FrameError> for Http2Error {
+    fn from(e: FrameError) -> Http2Error {
+        Http2Error::Frame(e)
+    }
+}
+
+impl From<HpackError> for Http2Error {
+    fn from(e: HpackError) -> Http2Error {
+        Http2Error::Hpack(e)
+    }
+}
+
+impl From<IntegerError> for Http2Error {
+    fn from(e: IntegerError) -> Http2Error {
+        Http2Error::Hpack(e.into())
+    }
+}
+
+impl From<HuffmanError> for Http2Error {
+    fn from(e: HuffmanError) -> Http2Error {
+        Http2Error::Hpack(e.into())
+    }
+}
+
+impl From<ProtocolError> for Http2Error {
+    fn from(e: ProtocolError) -> Http2Error {
+        Http2Error::Hpack(e.into())
+    }
+}
+
+/// 7. Error Codes
+///
+/// Error codes are 32-bit fields that are used in RST_STREAM and GOAWAY frames to convey the
+/// reasons for the stream or connection error.
+///
+/// Unknown or unsupported error codes MUST NOT trigger any special behavior. These MAY be
+/// treated by an implementation as being equivalent to INTERNAL_ERROR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    NoError,
+    ProtocolError,
+    InternalError,
+    FlowControlError,
+    SettingsTimeout,
+    StreamClosed,
+    FrameSizeError,
+    RefusedStream,
+    Cancel,
+    CompressionError,
+    ConnectError,
+    EnhanceYourCalm,
+    InadequateSecurity,
+    Http11Required,
+    Unknown(u32),
+}
+
+impl ErrorCode {
+    pub fn from_u32(code: u32) -> ErrorCode {
+        use self::ErrorCode::*;
+        match code {
+            0x0 => NoError,
+            0x1 => ProtocolError,
+            0x2 => InternalError,
+            0x3 => FlowControlError,
+            0x4 => SettingsTimeout,
+            0x5 => StreamClosed,
+            0x6 => FrameSizeError,
+            0x7 => RefusedStream,
+            0x8 => Cancel,
+            0x9 => CompressionError,
+            0xa => ConnectError,
+            0xb => EnhanceYourCalm,
+            0xc => InadequateSecurity,
+            0xd => Http11Required,
+            c   => Unknown(c),
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        use self::ErrorCode::*;
+        match *self {
+            NoError             => 0x0,
+            ProtocolError       => 0x1,
+            InternalError       => 0x2,
+            FlowControlError    => 0x3,
+            SettingsTimeout     => 0x4,
+            StreamClosed        => 0x5,
+            FrameSizeError      => 0x6,
+            RefusedStream       => 0x7,
+            Cancel              => 0x8,
+            CompressionError    => 0x9,
+            ConnectError        => 0xa,
+            EnhanceYourCalm     => 0xb,
+            InadequateSecurity  => 0xc,
+            Http11Required      => 0xd,
+            Unknown(c)          => c,
+        }
+    }
+}
+
+impl From<u32> for ErrorCode {
+    fn from(code: u32) -> ErrorCode {
+        ErrorCode::from_u32(code)
+    }
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> u32 {
+        code.to_u32()
+    }
+}
+
+#[cfg(test)]
+mod error_code_tests {
+
+    use super::{ErrorCode, FrameError, Http2Error};
+    use header::{HpackError, IntegerError, HuffmanError, ProtocolError};
+
+    #[test]
+    fn error_code_values() {
+        for code in 0..0xe {
+            let e = ErrorCode::from_u32(code);
+            assert!(e != ErrorCode::Unknown(code));
+            assert_eq!(e.to_u32(), code);
+        }
+        assert_eq!(ErrorCode::CompressionError.to_u32(), 0x9);
+        assert_eq!(ErrorCode::from(0xff), ErrorCode::Unknown(0xff));
+        assert_eq!(u32::from(ErrorCode::Unknown(0xff)), 0xff);
+
+        assert_eq!(FrameError::FrameSize.error_code(), ErrorCode::FrameSizeError);
+    }
+
+    #[test]
+    fn http2_error_codes() {
+        let e: Http2Error = IntegerError::TooManyOctets.into();
+        assert_eq!(e, Http2Error::Hpack(HpackError::Integer(IntegerError::TooManyOctets)));
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+
+        let e: Http2Error = HuffmanError::InvalidPadding.into();
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+        let e: Http2Error = HpackError::ZeroIndex.into();
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+        // a bad field is a stream error instead
+        let e: Http2Error = ProtocolError::UppercaseName.into();
+        assert_eq!(e.error_code(), ErrorCode::ProtocolError);
+
+        let e: Http2Error = FrameError::FrameSize.into();
+        assert_eq!(e.error_code(), ErrorCode::FrameSizeError);
+        let e: Http2Error = FrameError::Protocol.into();
+        assert_eq!(e.error_code(), ErrorCode::ProtocolError);
+        assert_eq!(e.to_string(), "frame: protocol error");
+    }
+}
@@ -0,0 +1,102 @@
+//! 5.2 Flow Control
+//!
+//! Each stream and the connection as a whole have a window of
+//! how many octets of DATA the sender may still send.
+//!
+//! A sender MUST NOT allow a flow-control window to exceed 2^31-1 octets. If a sender receives a
+//! WINDOW_UPDATE that causes a flow-control window to exceed this maximum, it MUST terminate either
+//! the stream or the connection, as appropriate.
+
+use super::{FrameError, Http2Error};
+
+// the largest a window can be
+pub const MAX_WINDOW_SIZE: i32 = 0x7FFF_FFFF;
+
+// the window size before any SETTINGS_INITIAL_WINDOW_SIZE
+pub const DEFAULT_WINDOW_SIZE: u32 = 65535;
+
+/// A flow control window for one stream or the connection
+///
+/// The window is signed since a change to SETTINGS_INITIAL_WINDOW_SIZE
+/// can leave it below 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowControlWindow {
+    size: i32,
+}
+
+impl FlowControlWindow {
+    // initial windows can not be larger than MAX_WINDOW_SIZE
+    // (Settings::parse rejects a larger SETTINGS_INITIAL_WINDOW_SIZE)
+    pub fn new(initial_size: u32) -> Self {
+        debug_assert!(initial_size <= MAX_WINDOW_SIZE as u32);
+        FlowControlWindow { size: initial_size as i32 }
+    }
+
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    // use up n octets of the window for DATA
+    //
+    // more than what is left is a FLOW_CONTROL_ERROR
+    pub fn consume(&mut self, n: u32) -> Result<(), Http2Error> {
+        if self.size < 0 || n > self.size as u32 {
+            return Err(FrameError::FlowControl.into());
+        }
+        self.size -= n as i32;
+        Ok(())
+    }
+
+    // add a WINDOW_UPDATE increment to the window
+    //
+    // going past 2^31-1 is a FLOW_CONTROL_ERROR and the window is left as it was
+    pub fn increase(&mut self, n: u32) -> Result<(), Http2Error> {
+        let size = self.size as i64 + n as i64;
+        if size > MAX_WINDOW_SIZE as i64 {
+            return Err(FrameError::FlowControl.into());
+        }
+        self.size = size as i32;
+        Ok(())
+    }
+}
+
+impl Default for FlowControlWindow {
+    fn default() -> Self {
+        FlowControlWindow::new(DEFAULT_WINDOW_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod flow_control_tests {
+
+    use frame::FrameError;
+    use super::{FlowControlWindow, MAX_WINDOW_SIZE};
+
+    #[test]
+    fn consume_and_increase() {
+        let mut window = FlowControlWindow::default();
+        assert_eq!(window.size(), 65535);
+
+        window.consume(65000).unwrap();
+        assert_eq!(window.size(), 535);
+        assert_eq!(window.consume(536), Err(FrameError::FlowControl.into()));
+        window.consume(535).unwrap();
+        assert_eq!(window.size(), 0);
+
+        window.increase(100).unwrap();
+        assert_eq!(window.size(), 100);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut window = FlowControlWindow::new(MAX_WINDOW_SIZE as u32 - 10);
+
+        window.increase(10).unwrap();
+        assert_eq!(window.size(), MAX_WINDOW_SIZE);
+        assert_eq!(window.increase(1), Err(FrameError::FlowControl.into()));
+        assert_eq!(window.size(), MAX_WINDOW_SIZE);
+
+        let mut window = FlowControlWindow::new(1);
+        assert_eq!(window.increase(0x7FFF_FFFF), Err(FrameError::FlowControl.into()));
+    }
+}
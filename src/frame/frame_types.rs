@@ -7,7 +7,8 @@
 use std::mem;
 use std::fmt;
 use buf::Buf;
-use super::Http2Frame;
+use super::{Http2Frame, FrameHeader, FrameType, FrameError, ErrorCode, Http2Error};
+use super::flow_control::MAX_WINDOW_SIZE;
 
 use self::flags::*;
 
@@ -17,6 +18,7 @@ pub mod flags {
     pub const END_HEADERS : u8 = 0x4;
     pub const PADDED : u8 = 0x8;
     pub const PRIORITY : u8 = 0x20;
+    pub const ACK : u8 = 0x1;
 }
 
 /// Type used to read initial data from peer.
@@ -90,6 +92,36 @@ unsafe fn getu16_from_be(buf: &[u8]) -> u16 {
     u16::from_be(num)
 }
 
+// put a frame header on the front of payload
+fn build_frame(kind: FrameType, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+    let header = FrameHeader {
+        length: payload.len() as u32,
+        kind: kind,
+        flags: flags,
+        stream_id: stream_id,
+    };
+    let mut frame = Vec::with_capacity(FrameHeader::SIZE + payload.len());
+    frame.extend_from_slice(&header.serialize());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// remove the Pad Length octet and the padding from a padded payload
+//
+// there has to be a Pad Length octet (FRAME_SIZE_ERROR) and the padding
+// must be shorter than the rest of the payload (PROTOCOL_ERROR)
+fn strip_padding(payload: &[u8]) -> Result<&[u8], FrameError> {
+    let pad_length = match payload.first() {
+        Some(p) => *p as usize,
+        None    => return Err(FrameError::FrameSize),
+    };
+    let rest = &payload[1..];
+    if pad_length > rest.len() {
+        return Err(FrameError::Protocol);
+    }
+    Ok(&rest[..rest.len() - pad_length])
+}
+
 // ================================================
 // the major header types are defined as follows
 // ================================================
@@ -112,92 +144,65 @@ unsafe fn getu16_from_be(buf: &[u8]) -> u16 {
 /// Figure 7: HEADERS Frame Payload
 ///
 
-// helper for HeadersFrame to determine the state of PADDED and PRIORITY flags
-enum PadPrioState {
-    PaddedOnly,
-    PriorityOnly,
-    Both,
-    Neither,
-}
-
 // All the data that can be expected to be in a Header frame
+#[derive(Debug, PartialEq, Eq)]
 pub struct HeaderData<'obj> {
     pub padding: Option<u8>,
     pub priority_data: Option<(bool, u32, u8)>, // exclusive, stream dep, weight
     pub header_block_fragment: &'obj [u8],
 }
 
+// split a HEADERS payload up into its parts, the padding is
+// dropped from the header block fragment
+//
+// stream_id is the stream the frame is on, a stream can not
+// depend on itself (PROTOCOL_ERROR)
+pub fn parse_headers_payload(flags: u8, stream_id: u32, payload: &[u8]) -> Result<HeaderData, Http2Error> {
+    let mut buf = payload;
+
+    let padding = match flags & PADDED != 0 {
+        false => None,
+        true  => {
+            let p = buf.first().map(|p| *p);
+            buf = try!(strip_padding(buf));
+            p
+        },
+    };
+
+    let priority_data = match flags & PRIORITY != 0 {
+        false => None,
+        true  => {
+            if buf.len() < 5 {
+                return Err(FrameError::FrameSize.into());
+            }
+            let stream_dep = unsafe { getu32_from_be(&buf[0..4]) };
+            let exclusive = stream_dep & 0x80000000 != 0;
+            let stream_dep = stream_dep & 0x7FFFFFFF;
+            let weight = buf[4];
+            if stream_dep == stream_id {
+                return Err(FrameError::Protocol.into());
+            }
+            buf = &buf[5..];
+            Some((exclusive, stream_dep, weight))
+        },
+    };
+
+    Ok(HeaderData {
+        padding: padding,
+        priority_data: priority_data,
+        header_block_fragment: buf,
+    })
+}
+
 create_frame_type!{
     HeadersFrame {
 
-    // private utility functions
-    // =============================
-
-    // determine the specific combination of PADDED and PRIORITY flags present
-    // to determine the memory layout
-    fn pad_prio_flags(&'obj self) -> PadPrioState {
-        use self::PadPrioState::*;
-        const PAD_PRIO : u8 = PADDED | PRIORITY;
-        let flags = self.get_flags() & PAD_PRIO;
-        match flags {
-            0        => Neither,
-            PADDED   => PaddedOnly,
-            PRIORITY => PriorityOnly,
-            PAD_PRIO => Both,
-            _        => unreachable!(),
-        }
-    }
-
     // immutable functions
     // =============================
-    // Each of these functions first determines the memory layout then
-    // and then pulls the correct info
-
-    pub fn get_header_data(&'obj self) -> HeaderData<'obj> {
-        let buf = &self.payload();
-
-        use self::PadPrioState::*;
-        match self.pad_prio_flags() {
-
-            Neither      =>
-                HeaderData {
-                    padding: None,
-                    priority_data: None,
-                    header_block_fragment: &buf[0..],
-                },
+    // the PADDED and PRIORITY flags determine the memory layout
 
-            PaddedOnly   =>
-                HeaderData {
-                    padding: Some(buf[0]),
-                    priority_data: None,
-                    header_block_fragment: &buf[1..],
-                },
-
-            PriorityOnly => {
-                let stream_dep = unsafe { getu32_from_be(&buf[0..4]) };
-                let exclusive = stream_dep & 0x80000000 != 0;
-                let weight = buf[4];
-
-                HeaderData {
-                    padding: None,
-                    priority_data: Some((exclusive, stream_dep & 0x7FFFFFFF, weight)),
-                    header_block_fragment: &buf[5..],
-                }
-            },
-
-            Both         => {
-                let stream_dep = unsafe { getu32_from_be(&buf[1..5]) };
-                let exclusive = stream_dep & 0x80000000 != 0;
-                let weight = buf[5];
-
-                HeaderData {
-                    padding: Some(buf[0]),
-                    priority_data: Some((exclusive, stream_dep & 0x7FFFFFFF, weight)),
-                    header_block_fragment: &buf[6..],
-                }
-            },
-
-        }
+    pub fn get_header_data(&'obj self) -> Result<HeaderData<'obj>, Http2Error> {
+        parse_headers_payload(self.get_flags(), self.get_stream_id(), self.payload())
     }
 } }
 
@@ -216,21 +221,19 @@ create_frame_type!{
 /// Figure 6: DATA Frame Payload
 ///
 
+// the data in a DATA payload without any padding
+pub fn parse_data_payload(flags: u8, payload: &[u8]) -> Result<&[u8], Http2Error> {
+    match flags & PADDED != 0 {
+        false => Ok(payload),
+        true  => Ok(try!(strip_padding(payload))),
+    }
+}
+
 create_frame_type!{
     DataFrame {
 
-    fn padded(&'obj self) -> bool {
-        self.get_flags() & PADDED != 0
-    }
-
-    pub fn get_data(&'obj self) -> &[u8] {
-        match self.padded() {
-            false => &self.payload()[0..],
-            true  => {
-                let end = self.payload().len() - self.payload()[0] as usize;
-                &self.payload()[1..end]
-            }
-        }
+    pub fn get_data(&'obj self) -> Result<&[u8], Http2Error> {
+        parse_data_payload(self.get_flags(), self.payload())
     }
 
 } }
@@ -247,6 +250,59 @@ create_frame_type!{
 ///  +-+-------------+
 /// Figure 8: PRIORITY Frame Payload
 
+// the priority of a stream as sent in PRIORITY (or HEADERS)
+//
+// the weight is the real weight 1..=256, on the wire it is one less
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priority {
+    pub stream_dependency: u32,
+    pub exclusive: bool,
+    pub weight: u16,
+}
+
+// the priority of a stream that never got one (RFC 7540 5.3.5)
+impl Default for Priority {
+    fn default() -> Priority {
+        Priority { stream_dependency: 0, exclusive: false, weight: 16 }
+    }
+}
+
+impl Priority {
+    // a PRIORITY payload for stream_id
+    //
+    // it is never on stream 0 and a stream can not depend on
+    // itself (PROTOCOL_ERROR), the payload is always 5 octets
+    // (FRAME_SIZE_ERROR)
+    pub fn parse(stream_id: u32, payload: &[u8]) -> Result<Priority, Http2Error> {
+        if stream_id == 0 {
+            return Err(FrameError::Protocol.into());
+        }
+        if payload.len() != 5 {
+            return Err(FrameError::FrameSize.into());
+        }
+        let stream_dep = unsafe { getu32_from_be(&payload[0..4]) };
+        let priority = Priority {
+            stream_dependency: stream_dep & 0x7FFFFFFF,
+            exclusive: stream_dep & 0x80000000 != 0,
+            weight: payload[4] as u16 + 1,
+        };
+        if priority.stream_dependency == stream_id {
+            return Err(FrameError::Protocol.into());
+        }
+        Ok(priority)
+    }
+
+    pub fn serialize(&self) -> [u8; 5] {
+        debug_assert!(self.weight >= 1 && self.weight <= 256);
+        let dep = self.stream_dependency & 0x7FFFFFFF | if self.exclusive { 0x80000000 } else { 0 };
+        [(dep >> 24) as u8, (dep >> 16) as u8, (dep >> 8) as u8, dep as u8, (self.weight - 1) as u8]
+    }
+
+    pub fn to_frame(&self, stream_id: u32) -> Vec<u8> {
+        build_frame(FrameType::Priority, 0, stream_id, &self.serialize())
+    }
+}
+
 create_frame_type! {
     PriorityFrame {
 
@@ -257,6 +313,10 @@ create_frame_type! {
         let weight = buf[4];
         (exclusive, stream_dep & 0x7FFFFFFF, weight)
     }
+
+    pub fn get_priority(&'obj self) -> Result<Priority, Http2Error> {
+        Priority::parse(self.get_stream_id(), self.payload())
+    }
 } }
 
 /// ===============================
@@ -269,6 +329,35 @@ create_frame_type! {
 ///  +---------------------------------------------------------------+
 /// Figure 9: RST_STREAM Frame Payload
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RstStream {
+    pub stream_id: u32,
+    pub error_code: ErrorCode,
+}
+
+impl RstStream {
+    // RST_STREAM is never on stream 0 (PROTOCOL_ERROR) and the
+    // payload is exactly the error code (FRAME_SIZE_ERROR)
+    pub fn parse(stream_id: u32, payload: &[u8]) -> Result<RstStream, Http2Error> {
+        if stream_id == 0 {
+            return Err(FrameError::Protocol.into());
+        }
+        if payload.len() != 4 {
+            return Err(FrameError::FrameSize.into());
+        }
+        Ok(RstStream {
+            stream_id: stream_id,
+            error_code: ErrorCode::from_u32(unsafe { getu32_from_be(payload) }),
+        })
+    }
+
+    pub fn to_frame(&self) -> Vec<u8> {
+        let code = self.error_code.to_u32();
+        build_frame(FrameType::RstStream, 0, self.stream_id,
+            &[(code >> 24) as u8, (code >> 16) as u8, (code >> 8) as u8, code as u8])
+    }
+}
+
 create_frame_type! {
     RstStreamFrame {
 
@@ -276,6 +365,10 @@ create_frame_type! {
         let buf = &self.payload()[..];
         unsafe { getu32_from_be(&buf[0..4]) }
     }
+
+    pub fn get_rst_stream(&'obj self) -> Result<RstStream, Http2Error> {
+        RstStream::parse(self.get_stream_id(), self.payload())
+    }
 } }
 
 /// ===============================
@@ -294,16 +387,16 @@ create_frame_type! {
 ///  +---------------------------------------------------------------+
 /// Figure 10: Setting Format
 
-pub struct Settings<'obj> {
+pub struct SettingsIter<'obj> {
     s_buf: &'obj [u8],
 }
 
-impl<'obj> Iterator for Settings<'obj> {
+impl<'obj> Iterator for SettingsIter<'obj> {
     type Item = (u16, u32); // id / value
 
     fn next(&mut self) -> Option<Self::Item> {
         let buf : &[u8] = &self.s_buf;
-        if buf.len() == 0 {
+        if buf.len() < 6 {
             None
         }
         else {
@@ -315,15 +408,107 @@ impl<'obj> Iterator for Settings<'obj> {
     }
 }
 
+/// 6.5.2 Defined SETTINGS Parameters
+///
+/// The settings that were present in a SETTINGS frame,
+/// anything that was not sent is None. Unknown identifiers
+/// are ignored as the spec requires
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub header_table_size: Option<u32>,
+    pub enable_push: Option<u32>,
+    pub max_concurrent_streams: Option<u32>,
+    pub initial_window_size: Option<u32>,
+    pub max_frame_size: Option<u32>,
+    pub max_header_list_size: Option<u32>,
+}
+
+impl Settings {
+    pub const HEADER_TABLE_SIZE : u16 = 0x1;
+    pub const ENABLE_PUSH : u16 = 0x2;
+    pub const MAX_CONCURRENT_STREAMS : u16 = 0x3;
+    pub const INITIAL_WINDOW_SIZE : u16 = 0x4;
+    pub const MAX_FRAME_SIZE : u16 = 0x5;
+    pub const MAX_HEADER_LIST_SIZE : u16 = 0x6;
+
+    // read the settings out of a SETTINGS payload
+    //
+    // the payload must be a whole number of settings otherwise
+    // it is a FRAME_SIZE_ERROR, later values replace earlier ones
+    //
+    // an initial window size above 2^31-1 is a FLOW_CONTROL_ERROR
+    pub fn parse(payload: &[u8]) -> Result<Settings, Http2Error> {
+        if payload.len() % 6 != 0 {
+            return Err(FrameError::FrameSize.into());
+        }
+        let mut settings = Settings::default();
+        for (id, value) in (SettingsIter { s_buf: payload }) {
+            match id {
+                Self::HEADER_TABLE_SIZE         => settings.header_table_size = Some(value),
+                Self::ENABLE_PUSH               => settings.enable_push = Some(value),
+                Self::MAX_CONCURRENT_STREAMS    => settings.max_concurrent_streams = Some(value),
+                Self::INITIAL_WINDOW_SIZE       => {
+                    if value > MAX_WINDOW_SIZE as u32 {
+                        return Err(FrameError::FlowControl.into());
+                    }
+                    settings.initial_window_size = Some(value);
+                },
+                Self::MAX_FRAME_SIZE            => settings.max_frame_size = Some(value),
+                Self::MAX_HEADER_LIST_SIZE      => settings.max_header_list_size = Some(value),
+                _ => {},
+            }
+        }
+        Ok(settings)
+    }
+
+    // the payload with every setting that is Some, in identifier order
+    pub fn serialize(&self) -> Vec<u8> {
+        let params = [
+            (Self::HEADER_TABLE_SIZE, self.header_table_size),
+            (Self::ENABLE_PUSH, self.enable_push),
+            (Self::MAX_CONCURRENT_STREAMS, self.max_concurrent_streams),
+            (Self::INITIAL_WINDOW_SIZE, self.initial_window_size),
+            (Self::MAX_FRAME_SIZE, self.max_frame_size),
+            (Self::MAX_HEADER_LIST_SIZE, self.max_header_list_size),
+        ];
+        let mut payload = Vec::with_capacity(6 * params.len());
+        for &(id, value) in params.iter() {
+            if let Some(value) = value {
+                payload.push((id >> 8) as u8);
+                payload.push(id as u8);
+                payload.push((value >> 24) as u8);
+                payload.push((value >> 16) as u8);
+                payload.push((value >> 8) as u8);
+                payload.push(value as u8);
+            }
+        }
+        payload
+    }
+
+    // a complete SETTINGS frame (header included) ready to send
+    pub fn to_frame(&self) -> Vec<u8> {
+        build_frame(FrameType::Settings, 0, 0, &self.serialize())
+    }
+}
+
 create_frame_type! {
     SettingsFrame {
 
-    // return an array filled with the setting parameters from the frame
-    pub fn get_settings_paramaters(&'obj self) -> Settings {
-        let length = self.get_length();
-        debug_assert!(length % 6 == 0); // should probably make this a hard check and return an error
-        // actually just note here that a lot more error checking should be done
-        Settings { s_buf: &self.payload()[..] }
+    // iterate over the raw setting parameters in the frame
+    // a trailing partial setting is not returned
+    pub fn get_settings_paramaters(&'obj self) -> SettingsIter {
+        SettingsIter { s_buf: &self.payload()[..] }
+    }
+
+    // read the defined settings
+    //
+    // an ACK must not have a payload, and the payload must be a
+    // multiple of 6 octets (both FRAME_SIZE_ERROR)
+    pub fn get_settings(&'obj self) -> Result<Settings, Http2Error> {
+        if self.get_flags() & ACK != 0 && self.get_length() != 0 {
+            return Err(FrameError::FrameSize.into());
+        }
+        Settings::parse(self.payload())
     }
 } }
 
@@ -378,6 +563,42 @@ create_frame_type! {
 ///  +---------------------------------------------------------------+
 /// Figure 12: PING Payload Format
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ping {
+    pub data: [u8; 8],
+    pub ack: bool,
+}
+
+impl Ping {
+    pub fn new(data: [u8; 8]) -> Self {
+        Ping { data: data, ack: false }
+    }
+
+    // PING is only on stream 0 (PROTOCOL_ERROR) and
+    // the payload is 8 octets (FRAME_SIZE_ERROR)
+    pub fn parse(flags: u8, stream_id: u32, payload: &[u8]) -> Result<Ping, Http2Error> {
+        if stream_id != 0 {
+            return Err(FrameError::Protocol.into());
+        }
+        if payload.len() != 8 {
+            return Err(FrameError::FrameSize.into());
+        }
+        let mut data = [0u8; 8];
+        data.copy_from_slice(payload);
+        Ok(Ping { data: data, ack: flags & ACK != 0 })
+    }
+
+    // the response to this ping, same data with ACK set
+    pub fn to_ack(&self) -> Ping {
+        Ping { data: self.data, ack: true }
+    }
+
+    pub fn to_frame(&self) -> Vec<u8> {
+        let flags = if self.ack { ACK } else { 0 };
+        build_frame(FrameType::Ping, flags, 0, &self.data)
+    }
+}
+
 create_frame_type! {
     PingFrame {
 
@@ -387,6 +608,10 @@ create_frame_type! {
         debug_assert_eq!(buf.len(), 8);
         buf
     }
+
+    pub fn get_ping(&'obj self) -> Result<Ping, Http2Error> {
+        Ping::parse(self.get_flags(), self.get_stream_id(), self.payload())
+    }
 } }
 
 /// ===============================
@@ -403,6 +628,48 @@ create_frame_type! {
 ///  +---------------------------------------------------------------+
 /// Figure 13: GOAWAY Payload Format
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoAway {
+    pub last_stream_id: u32,
+    pub error_code: ErrorCode,
+    pub debug_data: Vec<u8>,
+}
+
+impl GoAway {
+    pub fn new(last_stream_id: u32, error_code: ErrorCode) -> Self {
+        GoAway { last_stream_id: last_stream_id, error_code: error_code, debug_data: Vec::new() }
+    }
+
+    // the payload is at least the last stream id and the
+    // error code (FRAME_SIZE_ERROR otherwise)
+    pub fn parse(payload: &[u8]) -> Result<GoAway, Http2Error> {
+        if payload.len() < 8 {
+            return Err(FrameError::FrameSize.into());
+        }
+        Ok(GoAway {
+            last_stream_id: unsafe { getu32_from_be(&payload[0..4]) & 0x7FFFFFFF },
+            error_code: ErrorCode::from_u32(unsafe { getu32_from_be(&payload[4..8]) }),
+            debug_data: payload[8..].to_vec(),
+        })
+    }
+
+    // the reserved bit of the last stream id is always unset
+    pub fn serialize(&self) -> Vec<u8> {
+        let id = self.last_stream_id & 0x7FFFFFFF;
+        let code = self.error_code.to_u32();
+        let mut payload = Vec::with_capacity(8 + self.debug_data.len());
+        payload.extend_from_slice(&[(id >> 24) as u8, (id >> 16) as u8, (id >> 8) as u8, id as u8]);
+        payload.extend_from_slice(&[(code >> 24) as u8, (code >> 16) as u8, (code >> 8) as u8, code as u8]);
+        payload.extend_from_slice(&self.debug_data);
+        payload
+    }
+
+    // a complete GOAWAY frame (always on stream 0)
+    pub fn to_frame(&self) -> Vec<u8> {
+        build_frame(FrameType::GoAway, 0, 0, &self.serialize())
+    }
+}
+
 create_frame_type! {
     GoAwayFrame {
 
@@ -412,6 +679,10 @@ create_frame_type! {
         let error_code = unsafe { getu32_from_be(&buf[4..8]) };
         (last_stread_id, error_code, &buf[8..])
     }
+
+    pub fn get_go_away(&'obj self) -> Result<GoAway, Http2Error> {
+        GoAway::parse(self.payload())
+    }
 } }
 
 /// ===============================
@@ -424,6 +695,21 @@ create_frame_type! {
 ///  +-+-------------------------------------------------------------+
 /// Figure 14: WINDOW_UPDATE Payload Format
 
+// read the Window Size Increment (the reserved bit is ignored)
+//
+// the payload is always 4 octets (FRAME_SIZE_ERROR) and an
+// increment of 0 is a PROTOCOL_ERROR
+pub fn parse_window_update(payload: &[u8]) -> Result<u32, Http2Error> {
+    if payload.len() != 4 {
+        return Err(FrameError::FrameSize.into());
+    }
+    let increment = unsafe { getu32_from_be(payload) } & 0x7FFFFFFF;
+    if increment == 0 {
+        return Err(FrameError::Protocol.into());
+    }
+    Ok(increment)
+}
+
 create_frame_type! {
     WindowUpdateFrame {
 
@@ -432,6 +718,10 @@ create_frame_type! {
         debug_assert_eq!(buf.len(), 4);
         unsafe { getu32_from_be(buf) }
     }
+
+    pub fn get_window_size_increment(&'obj self) -> Result<u32, Http2Error> {
+        parse_window_update(self.payload())
+    }
 } }
 
 /// ===============================
@@ -469,7 +759,7 @@ mod frame_type_tests {
 
         let headers : HeadersFrame = GenericFrame::point_to(&mut buf).into();
 
-        let h_data = headers.get_header_data();
+        let h_data = headers.get_header_data().unwrap();
 
         assert_eq!(None, h_data.padding);
         assert_eq!(None, h_data.priority_data);
@@ -484,11 +774,11 @@ mod frame_type_tests {
 
         let headers : HeadersFrame = GenericFrame::point_to(&mut buf).into();
 
-        let h_data = headers.get_header_data();
+        let h_data = headers.get_header_data().unwrap();
 
         assert_eq!(Some(15), h_data.padding);
         assert_eq!(None, h_data.priority_data);
-        assert_eq!(h_data.header_block_fragment[..], bc[10..]);
+        assert_eq!(h_data.header_block_fragment[..], bc[10..bc.len() - 15]);
 
         //================================
         // PriorityOnly
@@ -499,7 +789,7 @@ mod frame_type_tests {
 
         let headers : HeadersFrame = GenericFrame::point_to(&mut buf).into();
 
-        let h_data = headers.get_header_data();
+        let h_data = headers.get_header_data().unwrap();
 
         assert_eq!(None, h_data.padding);
         assert_eq!(Some((true, 31, 255)), h_data.priority_data);
@@ -514,11 +804,37 @@ mod frame_type_tests {
 
         let headers : HeadersFrame = GenericFrame::point_to(&mut buf).into();
 
-        let h_data = headers.get_header_data();
+        let h_data = headers.get_header_data().unwrap();
 
         assert_eq!(Some(15), h_data.padding);
         assert_eq!(Some((true, 31, 255)), h_data.priority_data);
-        assert_eq!(h_data.header_block_fragment[..], bc[15..]);
+        assert_eq!(h_data.header_block_fragment[..], bc[15..bc.len() - 15]);
+    }
+
+    #[test]
+    fn headers_payload_errors() {
+        // stream 1 depending on itself
+        let payload = [0x00, 0x00, 0x00, 0x01, 0x10, 0x82];
+        assert_eq!(parse_headers_payload(PRIORITY, 1, &payload), Err(FrameError::Protocol.into()));
+        assert_eq!(parse_headers_payload(PRIORITY, 3, &payload).unwrap(), HeaderData {
+            padding: None,
+            priority_data: Some((false, 1, 0x10)),
+            header_block_fragment: &[0x82],
+        });
+
+        // too short for the priority fields
+        assert_eq!(parse_headers_payload(PRIORITY, 1, &[0x00, 0x00, 0x01]), Err(FrameError::FrameSize.into()));
+        // the padding takes the priority fields
+        assert_eq!(parse_headers_payload(PADDED | PRIORITY, 1, &[0x03, 0x00, 0x00, 0x00, 0x03, 0x10, 0x00, 0x00]), Err(FrameError::FrameSize.into()));
+        // more padding than payload
+        assert_eq!(parse_headers_payload(PADDED, 1, &[0x04, 0x82, 0x00]), Err(FrameError::Protocol.into()));
+
+        // padding only
+        assert_eq!(parse_headers_payload(PADDED, 1, &[0x01, 0x82, 0x00]).unwrap(), HeaderData {
+            padding: Some(1),
+            priority_data: None,
+            header_block_fragment: &[0x82],
+        });
     }
 
     #[test]
@@ -529,7 +845,27 @@ mod frame_type_tests {
 
         let data : DataFrame = GenericFrame::point_to(&mut buf).into();
 
-        assert_eq!(data.get_data()[..], bc[10..12]);
+        assert_eq!(data.get_data().unwrap()[..], bc[10..12]);
+    }
+
+    #[test]
+    fn data_padding_tests() {
+        // not padded, everything is data
+        assert_eq!(parse_data_payload(0, &[0x01, 0x02]), Ok(&[0x01, 0x02][..]));
+
+        // 2 octets of padding are dropped
+        assert_eq!(parse_data_payload(PADDED, &[0x02, 0xAA, 0xBB, 0x00, 0x00]), Ok(&[0xAA, 0xBB][..]));
+        // all padding
+        assert_eq!(parse_data_payload(PADDED, &[0x02, 0x00, 0x00]), Ok(&[][..]));
+
+        // more padding than payload
+        assert_eq!(parse_data_payload(PADDED, &[0x03, 0xAA, 0x00]), Err(FrameError::Protocol.into()));
+        // no Pad Length octet
+        assert_eq!(parse_data_payload(PADDED, &[]), Err(FrameError::FrameSize.into()));
+
+        let mut buf = vec![0x00, 0x00, 0x03, 0x00, 0x08, 0x00, 0x00, 0x00, 0x01, 0x05, 0xAA, 0xBB];
+        let data : DataFrame = GenericFrame::point_to(&mut buf).into();
+        assert_eq!(data.get_data(), Err(FrameError::Protocol.into()));
     }
 
     #[test]
@@ -563,6 +899,150 @@ mod frame_type_tests {
         assert_eq!(params.next(), None);
     }
 
+    #[test]
+    fn settings_parse_tests() {
+        // header table size 8192, max concurrent streams 100, then an unknown id
+        let payload = [0x00, 0x01, 0x00, 0x00, 0x20, 0x00,
+                       0x00, 0x03, 0x00, 0x00, 0x00, 0x64,
+                       0x00, 0xF0, 0x00, 0x00, 0x00, 0x01];
+        let settings = Settings::parse(&payload).unwrap();
+
+        assert_eq!(settings.header_table_size, Some(8192));
+        assert_eq!(settings.max_concurrent_streams, Some(100));
+        assert_eq!(settings, Settings {
+            header_table_size: Some(8192),
+            max_concurrent_streams: Some(100),
+            .. Settings::default()
+        });
+
+        assert_eq!(Settings::parse(&payload[..7]), Err(FrameError::FrameSize.into()));
+        assert_eq!(Settings::parse(&[]), Ok(Settings::default()));
+
+        // initial window size up to 2^31-1
+        let max = [0x00, 0x04, 0x7F, 0xFF, 0xFF, 0xFF];
+        assert_eq!(Settings::parse(&max).unwrap().initial_window_size, Some(0x7FFF_FFFF));
+        let too_big = [0x00, 0x04, 0x80, 0x00, 0x00, 0x00];
+        assert_eq!(Settings::parse(&too_big), Err(FrameError::FlowControl.into()));
+        assert_eq!(Settings::parse(&too_big).unwrap_err().error_code(), ErrorCode::FlowControlError);
+    }
+
+    #[test]
+    fn settings_build_tests() {
+        let settings = Settings {
+            header_table_size: Some(8192),
+            max_concurrent_streams: Some(100),
+            .. Settings::default()
+        };
+
+        let mut buf = settings.to_frame();
+        assert_eq!(buf[..9], [0x00, 0x00, 0x0C, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let sframe : SettingsFrame = GenericFrame::point_to(&mut buf).into();
+        assert_eq!(sframe.get_settings(), Ok(settings));
+    }
+
+    #[test]
+    fn settings_ack_tests() {
+        let mut buf = vec![0x00, 0x00, 0x00, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00];
+        let sframe : SettingsFrame = GenericFrame::point_to(&mut buf).into();
+        assert_eq!(sframe.get_settings(), Ok(Settings::default()));
+
+        let mut buf = vec![0x00, 0x00, 0x06, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00];
+        let sframe : SettingsFrame = GenericFrame::point_to(&mut buf).into();
+        assert_eq!(sframe.get_settings(), Err(FrameError::FrameSize.into()));
+    }
+
+    #[test]
+    fn go_away_round_trip() {
+        let mut go_away = GoAway::new(0xFFFF_FFFF, ErrorCode::CompressionError);
+        go_away.debug_data = b"bad index".to_vec();
+
+        let mut buf = go_away.to_frame();
+        assert_eq!(buf[..9], [0x00, 0x00, 0x11, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        // reserved bit is masked
+        assert_eq!(buf[9..17], [0x7F, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x09]);
+
+        let frame : GoAwayFrame = GenericFrame::point_to(&mut buf).into();
+        let parsed = frame.get_go_away().unwrap();
+        assert_eq!(parsed.last_stream_id, 0x7FFF_FFFF);
+        assert_eq!(parsed.error_code, ErrorCode::CompressionError);
+        assert_eq!(parsed.debug_data, b"bad index".to_vec());
+
+        // reserved bit ignored when reading
+        let parsed = GoAway::parse(&[0x80, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(parsed, GoAway::new(5, ErrorCode::NoError));
+
+        assert_eq!(GoAway::parse(&[0x00; 7]), Err(FrameError::FrameSize.into()));
+    }
+
+    #[test]
+    fn window_update_parse_tests() {
+        assert_eq!(parse_window_update(&[0x00, 0x00, 0x01, 0x90]), Ok(400));
+        // reserved bit
+        assert_eq!(parse_window_update(&[0x80, 0x00, 0x00, 0x01]), Ok(1));
+
+        assert_eq!(parse_window_update(&[0x00, 0x00, 0x00, 0x00]), Err(FrameError::Protocol.into()));
+        assert_eq!(parse_window_update(&[0x80, 0x00, 0x00, 0x00]), Err(FrameError::Protocol.into()));
+        assert_eq!(parse_window_update(&[0x00, 0x00, 0x01]), Err(FrameError::FrameSize.into()));
+
+        let mut buf = vec![0x00, 0x00, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let frame : WindowUpdateFrame = GenericFrame::point_to(&mut buf).into();
+        assert_eq!(frame.get_window_size_increment(), Err(FrameError::Protocol.into()));
+    }
+
+    #[test]
+    fn priority_parse_tests() {
+        let mut buf = vec![0x00, 0x00, 0x05, 0x02, 0x00, 0x00, 0x00, 0x00, 0x03, 0x80, 0x00, 0x00, 0x01, 0xFF];
+        let frame : PriorityFrame = GenericFrame::point_to(&mut buf).into();
+        let priority = frame.get_priority().unwrap();
+        assert_eq!(priority, Priority { stream_dependency: 1, exclusive: true, weight: 256 });
+        assert_eq!(priority.to_frame(3), buf);
+
+        assert_eq!(Priority::parse(3, &[0x00, 0x00, 0x00, 0x01, 0x00]).unwrap().weight, 1);
+
+        // wrong payload sizes
+        assert_eq!(Priority::parse(3, &[0x00, 0x00, 0x00, 0x01]), Err(FrameError::FrameSize.into()));
+        assert_eq!(Priority::parse(3, &[0x00, 0x00, 0x00, 0x01, 0x0F, 0x00]), Err(FrameError::FrameSize.into()));
+        // stream 0 and depending on itself
+        assert_eq!(Priority::parse(0, &[0x00, 0x00, 0x00, 0x01, 0x0F]), Err(FrameError::Protocol.into()));
+        assert_eq!(Priority::parse(3, &[0x80, 0x00, 0x00, 0x03, 0x0F]), Err(FrameError::Protocol.into()));
+    }
+
+    #[test]
+    fn rst_stream_parse_tests() {
+        let rst = RstStream { stream_id: 3, error_code: ErrorCode::Cancel };
+        let mut buf = rst.to_frame();
+        assert_eq!(buf, vec![0x00, 0x00, 0x04, 0x03, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x08]);
+
+        let frame : RstStreamFrame = GenericFrame::point_to(&mut buf).into();
+        assert_eq!(frame.get_rst_stream(), Ok(rst));
+
+        assert_eq!(RstStream::parse(0, &[0x00, 0x00, 0x00, 0x08]), Err(FrameError::Protocol.into()));
+        assert_eq!(RstStream::parse(1, &[0x00, 0x00, 0x08]), Err(FrameError::FrameSize.into()));
+        assert_eq!(RstStream::parse(1, &[0x00, 0x00, 0x00, 0x00, 0x08]), Err(FrameError::FrameSize.into()));
+    }
+
+    #[test]
+    fn ping_parse_tests() {
+        let ping = Ping::new([1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut buf = ping.to_frame();
+        assert_eq!(buf[..9], [0x00, 0x00, 0x08, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let frame : PingFrame = GenericFrame::point_to(&mut buf).into();
+        let parsed = frame.get_ping().unwrap();
+        assert_eq!(parsed, ping);
+        assert!(!parsed.ack);
+
+        // the response has the same data with ACK
+        let buf = parsed.to_ack().to_frame();
+        assert_eq!(buf[4], ACK);
+        assert_eq!(Ping::parse(buf[4], 0, &buf[9..]), Ok(Ping { data: [1, 2, 3, 4, 5, 6, 7, 8], ack: true }));
+
+        assert_eq!(Ping::parse(0, 1, &[0; 8]), Err(FrameError::Protocol.into()));
+        assert_eq!(Ping::parse(0, 0, &[0; 7]), Err(FrameError::FrameSize.into()));
+        assert_eq!(Ping::parse(0, 0, &[0; 9]), Err(FrameError::FrameSize.into()));
+    }
+
     #[test]
     fn push_promise_frame_tests() {
         let mut buf = vec![0x00, 0x00, 0x0C, 0x05, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x07, 0x00, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x05];
@@ -0,0 +1,307 @@
+//! 6.10 CONTINUATION
+//!
+//! A header block can be split over a HEADERS frame and any number
+//! of CONTINUATION frames. The HPACK decoder has to see the whole
+//! block at once so the fragments are collected here first.
+//!
+//! Any number of CONTINUATION frames can be sent, as long as the preceding frame is on the same
+//! stream and is a HEADERS, PUSH_PROMISE, or CONTINUATION frame without the END_HEADERS flag set.
+//!
+//! A receiver MUST treat the receipt of any other type of frame or a frame on a different stream
+//! as a connection error (Section 5.4.1) of type PROTOCOL_ERROR.
+
+use header::{Decoder, HeaderEntry};
+
+use super::{FrameHeader, FrameType, FrameError, Http2Error};
+use super::frame_types::parse_headers_payload;
+use super::frame_types::flags::{END_HEADERS, END_STREAM};
+
+/// Collects the fragments of a header block for one connection
+///
+/// Every frame read from the connection should be passed to push,
+/// while a block is open only CONTINUATION frames on the same stream
+/// are allowed
+pub struct HeaderBlock {
+    // the stream of the open block
+    stream_id: Option<u32>,
+    fragments: Vec<u8>,
+    // limit on the encoded size of a block, so a peer can not
+    // keep sending CONTINUATION frames until memory runs out
+    max_block_size: usize,
+}
+
+impl HeaderBlock {
+    pub fn new() -> Self {
+        HeaderBlock { stream_id: None, fragments: Vec::new(), max_block_size: usize::max_value() }
+    }
+
+    // there is no limit until this is set
+    //
+    // this is the size of the fragments before they are decoded,
+    // the decoded size is limited with Decoder::set_max_header_list_size
+    pub fn set_max_block_size(&mut self, max_block_size: usize) {
+        self.max_block_size = max_block_size;
+    }
+
+    // waiting on CONTINUATION frames
+    pub fn is_open(&self) -> bool {
+        self.stream_id.is_some()
+    }
+
+    // give the next frame on the connection
+    //
+    // once END_HEADERS is seen the whole block is decoded and the headers
+    // are returned, otherwise None. Frames that have nothing to do with
+    // header blocks are ignored when no block is open
+    pub fn push(&mut self, header: &FrameHeader, payload: &[u8], decoder: &mut Decoder)
+        -> Result<Option<Vec<HeaderEntry>>, Http2Error> {
+
+        let end_headers = header.flags & END_HEADERS != 0;
+
+        if let Some(stream_id) = self.stream_id {
+            if header.kind != FrameType::Continuation || header.stream_id != stream_id {
+                return Err(FrameError::Protocol.into());
+            }
+            if self.fragments.len() + payload.len() > self.max_block_size {
+                self.stream_id = None;
+                self.fragments.clear();
+                return Err(FrameError::HeaderBlockTooLarge.into());
+            }
+            self.fragments.extend_from_slice(payload);
+            if !end_headers {
+                return Ok(None);
+            }
+            self.stream_id = None;
+            let entries = decoder.decode(&self.fragments);
+            self.fragments.clear();
+            return Ok(Some(try!(entries)));
+        }
+
+        match header.kind {
+            FrameType::Headers => {
+                let h_data = try!(parse_headers_payload(header.flags, header.stream_id, payload));
+                if h_data.header_block_fragment.len() > self.max_block_size {
+                    return Err(FrameError::HeaderBlockTooLarge.into());
+                }
+                if end_headers {
+                    // the common case, no copy needed
+                    return Ok(Some(try!(decoder.decode(h_data.header_block_fragment))));
+                }
+                self.stream_id = Some(header.stream_id);
+                self.fragments.extend_from_slice(h_data.header_block_fragment);
+                Ok(None)
+            },
+            // nothing to continue
+            FrameType::Continuation => Err(FrameError::Protocol.into()),
+            _ => Ok(None),
+        }
+    }
+}
+
+// the sending side, split an encoded block into a HEADERS frame
+// and as many CONTINUATION frames as it takes for no payload to be
+// over max_frame_size (the peer's SETTINGS_MAX_FRAME_SIZE)
+//
+// only the last frame has END_HEADERS, end_stream puts END_STREAM
+// on the HEADERS frame (CONTINUATION has no such flag). A block
+// that fits is a single HEADERS frame
+//
+// the payloads are slices of block, the HEADERS frame has no
+// padding or priority
+pub fn split_header_block(stream_id: u32, block: &[u8], max_frame_size: usize, end_stream: bool) -> Vec<(FrameHeader, &[u8])> {
+    assert!(max_frame_size > 0, "max_frame_size must be at least 1");
+
+    let mut frames = Vec::with_capacity(block.len() / max_frame_size + 1);
+    let mut chunks = block.chunks(max_frame_size).peekable();
+    let first = chunks.next().unwrap_or(&[]);
+    let mut kind = FrameType::Headers;
+    let mut flags = if end_stream { END_STREAM } else { 0 };
+    let mut payload = first;
+
+    loop {
+        let last = chunks.peek().is_none();
+        if last {
+            flags |= END_HEADERS;
+        }
+        let header = FrameHeader { length: payload.len() as u32, kind: kind, flags: flags, stream_id: stream_id };
+        frames.push((header, payload));
+        match chunks.next() {
+            Some(next) => payload = next,
+            None       => return frames,
+        }
+        kind = FrameType::Continuation;
+        flags = 0;
+    }
+}
+
+#[cfg(test)]
+mod header_block_tests {
+
+    use header::{Decoder, HeaderEntry};
+    use frame::{FrameHeader, FrameType, FrameError, Http2Error};
+    use frame::frame_types::flags::{END_HEADERS, END_STREAM};
+    use super::{HeaderBlock, split_header_block};
+
+    fn frame(kind: FrameType, flags: u8, stream_id: u32, payload: &[u8]) -> FrameHeader {
+        FrameHeader { length: payload.len() as u32, kind: kind, flags: flags, stream_id: stream_id }
+    }
+
+    // RFC 7541 C.3.1 split in two
+    static PART1: &'static [u8] = b"\x82\x86\x84\x41\x0fwww.";
+    static PART2: &'static [u8] = b"example.com";
+
+    fn expected() -> Vec<HeaderEntry> {
+        vec![
+            (":method", "GET").into(),
+            (":scheme", "http").into(),
+            (":path", "/").into(),
+            (":authority", "www.example.com").into(),
+        ]
+    }
+
+    #[test]
+    fn single_frame() {
+        let mut block = HeaderBlock::new();
+        let mut decoder = Decoder::new(4096, 10);
+
+        let mut payload = PART1.to_vec();
+        payload.extend_from_slice(PART2);
+        let h = frame(FrameType::Headers, END_HEADERS | END_STREAM, 1, &payload);
+        assert_eq!(block.push(&h, &payload, &mut decoder), Ok(Some(expected())));
+        assert!(!block.is_open());
+    }
+
+    #[test]
+    fn headers_and_continuation() {
+        let mut block = HeaderBlock::new();
+        let mut decoder = Decoder::new(4096, 10);
+
+        let h = frame(FrameType::Headers, END_STREAM, 1, PART1);
+        assert_eq!(block.push(&h, PART1, &mut decoder), Ok(None));
+        assert!(block.is_open());
+        // nothing is decoded until the end
+        assert!(decoder.decode(&[0xbe]).is_err());
+
+        let c = frame(FrameType::Continuation, 0, 1, &PART2[..4]);
+        assert_eq!(block.push(&c, &PART2[..4], &mut decoder), Ok(None));
+        let c = frame(FrameType::Continuation, END_HEADERS, 1, &PART2[4..]);
+        assert_eq!(block.push(&c, &PART2[4..], &mut decoder), Ok(Some(expected())));
+        assert!(!block.is_open());
+
+        // ready for the next one
+        let h = frame(FrameType::Headers, END_HEADERS, 3, &[0xbe]);
+        assert_eq!(block.push(&h, &[0xbe], &mut decoder), Ok(Some(vec![(":authority", "www.example.com").into()])));
+    }
+
+    #[test]
+    fn interleaved_frames() {
+        let mut decoder = Decoder::new(4096, 10);
+        let h = frame(FrameType::Headers, 0, 1, PART1);
+
+        // continuation on another stream
+        let mut block = HeaderBlock::new();
+        block.push(&h, PART1, &mut decoder).unwrap();
+        let c = frame(FrameType::Continuation, END_HEADERS, 3, PART2);
+        assert_eq!(block.push(&c, PART2, &mut decoder), Err(Http2Error::Frame(FrameError::Protocol)));
+
+        // some other frame on the same stream
+        let mut block = HeaderBlock::new();
+        block.push(&h, PART1, &mut decoder).unwrap();
+        let d = frame(FrameType::Data, 0, 1, &[]);
+        assert_eq!(block.push(&d, &[], &mut decoder), Err(Http2Error::Frame(FrameError::Protocol)));
+
+        // continuation that does not continue anything
+        let mut block = HeaderBlock::new();
+        let c = frame(FrameType::Continuation, END_HEADERS, 1, PART2);
+        assert_eq!(block.push(&c, PART2, &mut decoder), Err(Http2Error::Frame(FrameError::Protocol)));
+
+        // other frames are fine when no block is open
+        assert_eq!(block.push(&d, &[], &mut decoder), Ok(None));
+    }
+
+    #[test]
+    fn max_block_size() {
+        use frame::ErrorCode;
+
+        let mut block = HeaderBlock::new();
+        block.set_max_block_size(20);
+        let mut decoder = Decoder::new(4096, 10);
+
+        // a block that fits is fine
+        let mut payload = PART1.to_vec();
+        payload.extend_from_slice(PART2);
+        let h = frame(FrameType::Headers, END_HEADERS, 1, &payload);
+        assert_eq!(block.push(&h, &payload, &mut decoder), Ok(Some(expected())));
+
+        // one octet at a time until the limit is passed
+        let h = frame(FrameType::Headers, 0, 3, &[0x82]);
+        assert_eq!(block.push(&h, &[0x82], &mut decoder), Ok(None));
+        let c = frame(FrameType::Continuation, 0, 3, &[0x82]);
+        for _ in 0..19 {
+            assert_eq!(block.push(&c, &[0x82], &mut decoder), Ok(None));
+        }
+        let e = block.push(&c, &[0x82], &mut decoder).unwrap_err();
+        assert_eq!(e, Http2Error::Frame(FrameError::HeaderBlockTooLarge));
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+        assert!(!block.is_open());
+        // nothing was decoded
+        assert_eq!(decoder.dump_table(), "[ 62] (s = 57) :authority: www.example.com\n      Table size: 57/4096\n");
+
+        // a HEADERS frame that is too big on its own
+        let payload = [0x82; 21];
+        let h = frame(FrameType::Headers, END_HEADERS, 5, &payload);
+        assert_eq!(block.push(&h, &payload, &mut decoder), Err(Http2Error::Frame(FrameError::HeaderBlockTooLarge)));
+    }
+
+    #[test]
+    fn split_fits() {
+        let mut payload = PART1.to_vec();
+        payload.extend_from_slice(PART2);
+
+        let frames = split_header_block(1, &payload, 16384, true);
+        assert_eq!(frames, vec![(frame(FrameType::Headers, END_HEADERS | END_STREAM, 1, &payload), &payload[..])]);
+        // exactly the frame size
+        let frames = split_header_block(1, &payload, payload.len(), false);
+        assert_eq!(frames, vec![(frame(FrameType::Headers, END_HEADERS, 1, &payload), &payload[..])]);
+        // an empty block is still sent
+        let frames = split_header_block(3, &[], 16384, false);
+        assert_eq!(frames, vec![(frame(FrameType::Headers, END_HEADERS, 3, &[]), &[][..])]);
+    }
+
+    #[test]
+    fn split_continuation() {
+        let mut payload = PART1.to_vec();
+        payload.extend_from_slice(PART2);
+
+        // the last frame takes what is left over
+        let (first, rest) = payload.split_at(PART2.len());
+        let frames = split_header_block(1, &payload, PART2.len(), true);
+        assert_eq!(frames, vec![
+            (frame(FrameType::Headers, END_STREAM, 1, first), first),
+            (frame(FrameType::Continuation, END_HEADERS, 1, rest), rest),
+        ]);
+
+        // one octet at a time, only the last frame ends the headers
+        let frames = split_header_block(5, &payload, 1, false);
+        assert_eq!(frames.len(), payload.len());
+        assert_eq!(frames[0].0.kind, FrameType::Headers);
+        for (i, &(ref header, chunk)) in frames.iter().enumerate() {
+            assert_eq!(chunk, &payload[i..i + 1]);
+            assert_eq!(header.length, 1);
+            assert_eq!(header.flags & END_HEADERS != 0, i == payload.len() - 1);
+            assert_eq!(header.flags & END_STREAM, 0);
+            if i > 0 {
+                assert_eq!(header.kind, FrameType::Continuation);
+            }
+        }
+
+        // and it goes back together on the other side
+        let mut block = HeaderBlock::new();
+        let mut decoder = Decoder::new(4096, 10);
+        let mut result = None;
+        for &(ref header, chunk) in &frames {
+            result = block.push(header, chunk, &mut decoder).unwrap();
+        }
+        assert_eq!(result, Some(expected()));
+    }
+}
@@ -40,6 +40,17 @@ use std::mem;
 use buf::Buf;
 
 pub mod frame_types;
+mod error;
+mod header_block;
+pub mod flow_control;
+pub mod priority;
+mod preface;
+
+pub use self::error::{FrameError, ErrorCode, Http2Error};
+pub use self::header_block::{HeaderBlock, split_header_block};
+pub use self::flow_control::FlowControlWindow;
+pub use self::priority::PriorityTree;
+pub use self::preface::{PREFACE, parse_preface};
 
 /// The Basic methods defined for all types of HTTP2 Frames.
 /// The types that define more specific Frames all implement this
@@ -73,6 +84,12 @@ pub trait Http2Frame<'obj, 'buf> : Buf<'obj, 'buf, u8> {
         &self.buf()[9..]
     }
 
+    // copy the 9 octet header out of the buffer
+    fn get_frame_header(&'obj self) -> FrameHeader {
+        let buf = self.buf();
+        FrameHeader::parse(&[buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7], buf[8]])
+    }
+
     // mutable functions for Http2Frame
     // =============================
     fn set_length(&'obj mut self, len: u32) {
@@ -107,6 +124,119 @@ pub trait Http2Frame<'obj, 'buf> : Buf<'obj, 'buf, u8> {
     }
 }
 
+/// The frame types defined in RFC 7540 6
+///
+/// Unknown types must be ignored so they are kept
+/// with their code rather than being an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Data,
+    Headers,
+    Priority,
+    RstStream,
+    Settings,
+    PushPromise,
+    Ping,
+    GoAway,
+    WindowUpdate,
+    Continuation,
+    Unknown(u8),
+}
+
+impl FrameType {
+    pub fn from_u8(code: u8) -> FrameType {
+        use self::FrameType::*;
+        match code {
+            0x0 => Data,
+            0x1 => Headers,
+            0x2 => Priority,
+            0x3 => RstStream,
+            0x4 => Settings,
+            0x5 => PushPromise,
+            0x6 => Ping,
+            0x7 => GoAway,
+            0x8 => WindowUpdate,
+            0x9 => Continuation,
+            c   => Unknown(c),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        use self::FrameType::*;
+        match *self {
+            Data            => 0x0,
+            Headers         => 0x1,
+            Priority        => 0x2,
+            RstStream       => 0x3,
+            Settings        => 0x4,
+            PushPromise     => 0x5,
+            Ping            => 0x6,
+            GoAway          => 0x7,
+            WindowUpdate    => 0x8,
+            Continuation    => 0x9,
+            Unknown(c)      => c,
+        }
+    }
+}
+
+// every code is a FrameType so there is nothing that can fail
+impl From<u8> for FrameType {
+    fn from(code: u8) -> FrameType {
+        FrameType::from_u8(code)
+    }
+}
+
+impl From<FrameType> for u8 {
+    fn from(kind: FrameType) -> u8 {
+        kind.to_u8()
+    }
+}
+
+/// An owned copy of the 9 octet frame header
+///
+/// Useful for reading the header before the rest of the
+/// frame has been received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub length: u32,
+    pub kind: FrameType,
+    pub flags: u8,
+    pub stream_id: u32,
+}
+
+impl FrameHeader {
+    pub const SIZE: usize = 9;
+
+    // the reserved bit is ignored
+    pub fn parse(bytes: &[u8; 9]) -> FrameHeader {
+        FrameHeader {
+            length: (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32,
+            kind: FrameType::from_u8(bytes[3]),
+            flags: bytes[4],
+            stream_id: ((bytes[5] & 0x7F) as u32) << 24 | (bytes[6] as u32) << 16
+                | (bytes[7] as u32) << 8 | bytes[8] as u32,
+        }
+    }
+
+    // the reserved bit is always sent unset
+    //
+    // length must fit in 24 bits, the bits above that are dropped
+    pub fn serialize(&self) -> [u8; 9] {
+        debug_assert!(self.length <= 0xFF_FFFF);
+        [
+            (self.length >> 16) as u8,
+            (self.length >> 8) as u8,
+            self.length as u8,
+            self.kind.to_u8(),
+            self.flags,
+            (self.stream_id >> 24) as u8 & 0x7F,
+            (self.stream_id >> 16) as u8,
+            (self.stream_id >> 8) as u8,
+            self.stream_id as u8,
+        ]
+    }
+}
+
 /// convenience macro to impl Http2Frame for listed types
 /// automatically give Buf trait for u8 type and member name buf
 /// all types that use this macro must then have that member
@@ -123,7 +253,7 @@ pub trait Http2Frame<'obj, 'buf> : Buf<'obj, 'buf, u8> {
 mod http2_frame_tests {
 
     use buf::Buf;
-    use super::Http2Frame;
+    use super::{Http2Frame, FrameHeader, FrameType};
     use super::frame_types::GenericFrame;
 
     // test frame with invalid payload and length
@@ -147,6 +277,55 @@ mod http2_frame_tests {
         assert_eq!(frame.payload()[..], TST_FRAME[9..]);
     }
 
+    #[test]
+    fn frame_header_settings() {
+        // SETTINGS frame with 3 settings on stream 0
+        let bytes = [0x00, 0x00, 0x12, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let header = FrameHeader::parse(&bytes);
+
+        assert_eq!(header, FrameHeader { length: 18, kind: FrameType::Settings, flags: 0, stream_id: 0 });
+        assert_eq!(header.serialize(), bytes);
+    }
+
+    #[test]
+    fn frame_header_reserved_bit() {
+        let bytes = [0x01, 0x02, 0x03, 0x01, 0x25, 0xFF, 0xFF, 0xFF, 0xFF];
+        let header = FrameHeader::parse(&bytes);
+
+        assert_eq!(header.length, 0x010203);
+        assert_eq!(header.kind, FrameType::Headers);
+        assert_eq!(header.stream_id, 0x7FFF_FFFF);
+        assert_eq!(header.serialize(), [0x01, 0x02, 0x03, 0x01, 0x25, 0x7F, 0xFF, 0xFF, 0xFF]);
+
+        let header = FrameHeader { length: 0, kind: FrameType::Data, flags: 0, stream_id: 0xFFFF_FFFF };
+        assert_eq!(header.serialize()[5], 0x7F);
+    }
+
+    #[test]
+    fn frame_header_from_frame() {
+        let mut buf = TST_FRAME.to_vec();
+        let frame = GenericFrame::point_to(&mut buf);
+
+        assert_eq!(frame.get_frame_header(), FrameHeader { length: 238, kind: FrameType::Headers, flags: 0x25, stream_id: 1 });
+    }
+
+    #[test]
+    fn frame_type_codes() {
+        for code in 0..10u8 {
+            let kind = FrameType::from_u8(code);
+            assert!(kind != FrameType::Unknown(code));
+            assert_eq!(kind.to_u8(), code);
+            assert_eq!(u8::from(kind), code);
+        }
+        assert_eq!(FrameType::from(99), FrameType::Unknown(99));
+        assert_eq!(FrameType::Unknown(99).to_u8(), 99);
+
+        // unknown types still make it through the header
+        let header = FrameHeader::parse(&[0x00, 0x00, 0x00, 0x63, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(header.kind, FrameType::Unknown(99));
+        assert_eq!(header.serialize()[3], 99);
+    }
+
     #[test]
     fn write_frame_test(){
         let mut buf : Vec<u8> = vec![0;10];
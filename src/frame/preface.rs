@@ -0,0 +1,71 @@
+//! 3.5 HTTP/2 Connection Preface
+//!
+//! In HTTP/2, each endpoint is required to send a connection preface as a final confirmation of the
+//! protocol in use and to establish the initial settings for the HTTP/2 connection.
+//!
+//! The client connection preface starts with a sequence of 24 octets, which in hex notation is:
+//!
+//!   0x505249202a20485454502f322e300d0a0d0a534d0d0a0d0a
+//!
+//! That is, the connection preface starts with the string "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n". This
+//! sequence MUST be followed by a SETTINGS frame (Section 6.5), which MAY be empty.
+//!
+//! Clients and servers MUST treat an invalid connection preface as a connection error (Section 5.4.1)
+//! of type PROTOCOL_ERROR.
+
+use super::{FrameError, Http2Error};
+
+/// The octets a client starts every connection with
+pub const PREFACE: &'static [u8; 24] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// check that bytes start with the client preface, the
+// first frame (SETTINGS) starts right after what is consumed
+//
+// Ok(0) when bytes is too short but matches so far,
+// try again once more has been read
+pub fn parse_preface(bytes: &[u8]) -> Result<usize, Http2Error> {
+    let len = ::std::cmp::min(bytes.len(), PREFACE.len());
+    if bytes[..len] != PREFACE[..len] {
+        return Err(FrameError::InvalidPreface.into());
+    }
+    if len < PREFACE.len() {
+        return Ok(0);
+    }
+    Ok(PREFACE.len())
+}
+
+#[cfg(test)]
+mod preface_tests {
+
+    use frame::{FrameError, ErrorCode, Http2Error};
+    use super::{PREFACE, parse_preface};
+
+    #[test]
+    fn valid_preface() {
+        assert_eq!(parse_preface(PREFACE), Ok(24));
+
+        // followed by an empty SETTINGS frame
+        let mut bytes = PREFACE.to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 0x4, 0, 0, 0, 0, 0]);
+        assert_eq!(parse_preface(&bytes), Ok(24));
+        assert_eq!(bytes[24 + 3], 0x4);
+
+        // not all there yet
+        assert_eq!(parse_preface(&PREFACE[..10]), Ok(0));
+        assert_eq!(parse_preface(&[]), Ok(0));
+    }
+
+    #[test]
+    fn invalid_preface() {
+        let mut bytes = PREFACE.to_vec();
+        bytes[21] = b'X';
+        let e = parse_preface(&bytes).unwrap_err();
+        assert_eq!(e, Http2Error::Frame(FrameError::InvalidPreface));
+        assert_eq!(e.error_code(), ErrorCode::ProtocolError);
+
+        // an HTTP/1.1 request
+        assert!(parse_preface(b"GET / HTTP/1.1\r\n\r\n").is_err());
+        // wrong from the start even if short
+        assert!(parse_preface(b"PRX").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! 5.3 Stream Priority
+//!
+//! Each stream can be given an explicit dependency on another stream. Including a dependency
+//! expresses a preference to allocate resources to the identified stream rather than to the
+//! dependent stream.
+//!
+//! All dependent streams are allocated an integer weight between 1 and 256 (inclusive).
+//!
+//! Streams that are not in the tree depend on stream 0 (the root) with a weight of 16.
+
+use std::collections::HashMap;
+
+use super::frame_types::Priority;
+
+// the root everything depends on
+const ROOT: u32 = 0;
+
+struct Node {
+    parent: u32,
+    weight: u16,
+    children: Vec<u32>,
+}
+
+/// The dependency tree for the streams of a connection
+///
+/// This only keeps the shape of the tree, what to do with it
+/// (how to share out the sending) is up to the scheduler
+pub struct PriorityTree {
+    nodes: HashMap<u32, Node>,
+}
+
+impl PriorityTree {
+    pub fn new() -> Self {
+        let mut nodes = HashMap::new();
+        nodes.insert(ROOT, Node { parent: ROOT, weight: 16, children: Vec::new() });
+        PriorityTree { nodes: nodes }
+    }
+
+    // add stream_id with priority, or move it if it is already in the tree
+    //
+    // - a dependency on a stream not in the tree gets the default priority
+    // - exclusive makes stream_id the only child of its parent and the
+    //   other children move under stream_id
+    // - if the new parent depends on stream_id it is first moved up to
+    //   where stream_id was (RFC 7540 5.3.3)
+    //
+    // stream_id can not be 0, and can not depend on itself (Priority
+    // parsing already rejects both)
+    pub fn insert(&mut self, stream_id: u32, priority: &Priority) {
+        debug_assert!(stream_id != ROOT && stream_id != priority.stream_dependency);
+
+        let (parent, weight, exclusive) = match self.nodes.contains_key(&priority.stream_dependency) {
+            true  => (priority.stream_dependency, priority.weight, priority.exclusive),
+            false => {
+                let default = Priority::default();
+                (default.stream_dependency, default.weight, default.exclusive)
+            },
+        };
+
+        if self.nodes.contains_key(&stream_id) {
+            if self.depends_on(parent, stream_id) {
+                let old_parent = self.nodes[&stream_id].parent;
+                let parent_weight = self.nodes[&parent].weight;
+                self.link(parent, old_parent, parent_weight);
+            }
+            self.unlink(stream_id);
+        }
+        else {
+            self.nodes.insert(stream_id, Node { parent: ROOT, weight: weight, children: Vec::new() });
+        }
+
+        if exclusive {
+            let children = ::std::mem::replace(&mut self.nodes.get_mut(&parent).unwrap().children, Vec::new());
+            for child in children {
+                self.nodes.get_mut(&child).unwrap().parent = stream_id;
+                self.nodes.get_mut(&stream_id).unwrap().children.push(child);
+            }
+        }
+        self.link(stream_id, parent, weight);
+    }
+
+    // take stream_id out of the tree, its children move up to its parent
+    // (their weights are left as they are)
+    pub fn remove(&mut self, stream_id: u32) {
+        if stream_id == ROOT {
+            return;
+        }
+        if let Some(node) = self.nodes.remove(&stream_id) {
+            self.nodes.get_mut(&node.parent).unwrap().children.retain(|c| *c != stream_id);
+            for child in node.children {
+                self.nodes.get_mut(&child).unwrap().parent = node.parent;
+                self.nodes.get_mut(&node.parent).unwrap().children.push(child);
+            }
+        }
+    }
+
+    pub fn contains(&self, stream_id: u32) -> bool {
+        self.nodes.contains_key(&stream_id)
+    }
+
+    pub fn parent(&self, stream_id: u32) -> Option<u32> {
+        match stream_id {
+            ROOT => None,
+            id   => self.nodes.get(&id).map(|n| n.parent),
+        }
+    }
+
+    pub fn weight(&self, stream_id: u32) -> Option<u16> {
+        self.nodes.get(&stream_id).map(|n| n.weight)
+    }
+
+    // in the order they were added
+    pub fn children(&self, stream_id: u32) -> Option<&[u32]> {
+        self.nodes.get(&stream_id).map(|n| &n.children[..])
+    }
+
+    //=========================================
+    // private utility fn
+    //=========================================
+    // true if stream_id is somewhere under ancestor
+    fn depends_on(&self, stream_id: u32, ancestor: u32) -> bool {
+        let mut cur = stream_id;
+        while cur != ROOT {
+            cur = self.nodes[&cur].parent;
+            if cur == ancestor {
+                return true;
+            }
+        }
+        false
+    }
+
+    // take stream_id off its parent, it keeps its children
+    fn unlink(&mut self, stream_id: u32) {
+        let parent = self.nodes[&stream_id].parent;
+        self.nodes.get_mut(&parent).unwrap().children.retain(|c| *c != stream_id);
+    }
+
+    // move stream_id (and everything under it) to be a child of parent
+    fn link(&mut self, stream_id: u32, parent: u32, weight: u16) {
+        self.unlink(stream_id);
+        {
+            let node = self.nodes.get_mut(&stream_id).unwrap();
+            node.parent = parent;
+            node.weight = weight;
+        }
+        self.nodes.get_mut(&parent).unwrap().children.push(stream_id);
+    }
+}
+
+#[cfg(test)]
+mod priority_tests {
+
+    use super::PriorityTree;
+    use frame::frame_types::Priority;
+
+    fn dep(stream_dependency: u32, exclusive: bool) -> Priority {
+        Priority { stream_dependency: stream_dependency, exclusive: exclusive, weight: 16 }
+    }
+
+    #[test]
+    fn default_priority() {
+        let mut tree = PriorityTree::new();
+
+        tree.insert(1, &Priority { stream_dependency: 0, exclusive: false, weight: 200 });
+        assert_eq!(tree.parent(1), Some(0));
+        assert_eq!(tree.weight(1), Some(200));
+
+        // depends on a stream that is not in the tree
+        tree.insert(3, &Priority { stream_dependency: 7, exclusive: false, weight: 100 });
+        assert_eq!(tree.parent(3), Some(0));
+        assert_eq!(tree.weight(3), Some(16));
+        assert_eq!(tree.children(0), Some(&[1, 3][..]));
+        assert_eq!(tree.parent(0), None);
+        assert!(!tree.contains(7));
+    }
+
+    // RFC 7540 Figure 4
+    #[test]
+    fn exclusive_reparents_children() {
+        let mut tree = PriorityTree::new();
+        let (a, b, c, d) = (1, 3, 5, 7);
+
+        tree.insert(a, &dep(0, false));
+        tree.insert(b, &dep(a, false));
+        tree.insert(c, &dep(a, false));
+
+        tree.insert(d, &dep(a, true));
+        assert_eq!(tree.children(a), Some(&[d][..]));
+        assert_eq!(tree.children(d), Some(&[b, c][..]));
+        assert_eq!(tree.parent(b), Some(d));
+        assert_eq!(tree.parent(c), Some(d));
+    }
+
+    // RFC 7540 Figure 5, A is moved under its own descendant D
+    #[test]
+    fn reprioritize_under_descendant() {
+        let mut tree = PriorityTree::new();
+        let (a, b, c, d, e, f) = (1, 3, 5, 7, 9, 11);
+
+        tree.insert(a, &dep(0, false));
+        tree.insert(b, &dep(a, false));
+        tree.insert(c, &dep(a, false));
+        tree.insert(d, &dep(c, false));
+        tree.insert(e, &dep(c, false));
+        tree.insert(f, &dep(d, false));
+
+        // non exclusive
+        tree.insert(a, &dep(d, false));
+        assert_eq!(tree.children(0), Some(&[d][..]));
+        assert_eq!(tree.children(d), Some(&[f, a][..]));
+        assert_eq!(tree.children(a), Some(&[b, c][..]));
+        assert_eq!(tree.children(c), Some(&[e][..]));
+    }
+
+    #[test]
+    fn reprioritize_exclusive_under_descendant() {
+        let mut tree = PriorityTree::new();
+        let (a, b, c, d, e, f) = (1, 3, 5, 7, 9, 11);
+
+        tree.insert(a, &dep(0, false));
+        tree.insert(b, &dep(a, false));
+        tree.insert(c, &dep(a, false));
+        tree.insert(d, &dep(c, false));
+        tree.insert(e, &dep(c, false));
+        tree.insert(f, &dep(d, false));
+
+        // exclusive
+        tree.insert(a, &dep(d, true));
+        assert_eq!(tree.children(0), Some(&[d][..]));
+        assert_eq!(tree.children(d), Some(&[a][..]));
+        let mut children = tree.children(a).unwrap().to_vec();
+        children.sort();
+        assert_eq!(children, vec![b, c, f]);
+        assert_eq!(tree.children(c), Some(&[e][..]));
+    }
+
+    #[test]
+    fn remove_stream() {
+        let mut tree = PriorityTree::new();
+
+        tree.insert(1, &dep(0, false));
+        tree.insert(3, &dep(1, false));
+        tree.insert(5, &dep(1, false));
+
+        tree.remove(1);
+        assert!(!tree.contains(1));
+        assert_eq!(tree.parent(3), Some(0));
+        assert_eq!(tree.children(0), Some(&[3, 5][..]));
+    }
+}
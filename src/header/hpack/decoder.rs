@@ -1,82 +1,439 @@
-use super::table::Table;
-use super::integers;
-use super::huffman::Huffman;
+use super::table::{HeaderTable, STATIC_TABLE_LEN, DEFAULT_TABLE_SIZE, entry_size};
+use super::integers::{self, PREFIX_4, PREFIX_5, PREFIX_6, PREFIX_7};
+use super::strings;
+use super::HpackError;
 
-use std::iter::Peekable;
-
-use borrow_iter::BorrowTake;
+use std::borrow::Cow;
+use std::str::{self, Utf8Error};
 
 use header::*;
+use header::validate::validate_name;
+use frame::Http2Error;
+
+// an encoder sends at most two size updates at the start of a
+// block, the smallest size it went down to and then the final one
+const MAX_SIZE_UPDATES: usize = 2;
+
+/// The kind of hpack instruction (RFC 7541 section 6) a trace event is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Indexed,
+    LiteralIncremental,
+    LiteralWithout,
+    LiteralNever,
+    SizeUpdate,
+}
+
+impl Instruction {
+
+    // the instruction a representation starting with octet is
+    fn from_octet(octet: u8) -> Instruction {
+        match octet {
+            val if val & 0x80 == 0x80 => Instruction::Indexed,
+            val if val & 0xC0 == 0x40 => Instruction::LiteralIncremental,
+            val if val & 0xE0 == 0x20 => Instruction::SizeUpdate,
+            val if val & 0x10 == 0x10 => Instruction::LiteralNever,
+            _ => Instruction::LiteralWithout,
+        }
+    }
+
+    fn prefix(&self) -> integers::PrefixSize {
+        match *self {
+            Instruction::Indexed            => PREFIX_7,
+            Instruction::LiteralIncremental => PREFIX_6,
+            Instruction::SizeUpdate         => PREFIX_5,
+            _                               => PREFIX_4,
+        }
+    }
+}
+
+/// One decoded instruction as handed to the trace hook
+///
+/// index is the table index the instruction used (0 for a literal
+/// name) or the new maximum size for a size update, which has an
+/// empty name and value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEvent<'a> {
+    pub kind: Instruction,
+    pub name: &'a str,
+    pub value: &'a str,
+    pub index: usize,
+}
 
 pub struct Decoder {
-    table: Table,
-    huffman: Huffman,
+    table: HeaderTable,
+    // the limit size updates from the peer are checked against
+    // (SETTINGS_HEADER_TABLE_SIZE)
+    protocol_max_size: usize,
+    // limit on the uncompressed size of a decoded block
+    // (SETTINGS_MAX_HEADER_LIST_SIZE) which stops a small block
+    // from expanding into a huge header list
+    max_header_list_size: usize,
+    // refuse everything that touches the dynamic table
+    static_only: bool,
+    // called for every instruction that is decoded, see set_trace
+    trace: Option<Box<FnMut(&TraceEvent)>>,
+    // huffman literals are decoded into this before they are stored
+    scratch: Vec<u8>,
 }
 
 impl Decoder {
 
     // create a new DynTable with the default capacity
     // the number of entries is just an assumption
+    //
+    // max_size is also taken as the protocol limit for
+    // dynamic table size updates
     pub fn new(max_size: usize, num_entries: usize) -> Self {
-        Decoder { table: Table::new(max_size, num_entries),
-            huffman: Huffman::new() }
+        Decoder { table: HeaderTable::new(max_size, num_entries),
+            protocol_max_size: max_size,
+            max_header_list_size: usize::max_value(),
+            static_only: false,
+            trace: None,
+            scratch: Vec::new() }
+    }
+
+    // see DecoderBuilder
+    pub fn builder() -> DecoderBuilder {
+        DecoderBuilder::new()
+    }
+
+    // call when SETTINGS_HEADER_TABLE_SIZE is sent to the peer (and acked)
+    //
+    // size updates in later blocks are checked against this, if the
+    // table is currently bigger it is shrunk right away
+    pub fn set_max_dynamic_table_size(&mut self, max_size: usize) {
+        self.protocol_max_size = max_size;
+        if self.table.dyn_max_size() > max_size {
+            self.table.max_size_update(max_size);
+        }
+    }
+
+    // there is no limit until this is set
+    //
+    // the size of a list is the sum of name + value + 32 for every
+    // field, the same way SETTINGS_MAX_HEADER_LIST_SIZE is defined
+    pub fn set_max_header_list_size(&mut self, max_header_list_size: usize) {
+        self.max_header_list_size = max_header_list_size;
+    }
+
+    /// Get the Decoder ready for a new connection
+    ///
+    /// The dynamic table is emptied (keeping its allocation) and the
+    /// table size limit goes back to the default of a new connection,
+    /// as if made with Decoder::new(4096, _). Local choices
+    /// (set_max_header_list_size, set_static_only and the trace hook)
+    /// are kept
+    pub fn reset(&mut self) {
+        self.table.reset(DEFAULT_TABLE_SIZE);
+        self.protocol_max_size = DEFAULT_TABLE_SIZE;
+    }
+
+    // when set incremental indexing, dynamic table size updates above 0
+    // and dynamic table indices are all a DynamicTableDisabled error
+    //
+    // this is for a server that advertises SETTINGS_HEADER_TABLE_SIZE
+    // of 0 and does not want to keep any memory for the peer, the
+    // update to 0 the peer has to send after that is still allowed
+    pub fn set_static_only(&mut self, static_only: bool) {
+        self.static_only = static_only;
+    }
+
+    pub fn is_static_only(&self) -> bool {
+        self.static_only
+    }
+
+    /// Call trace for every instruction as it is decoded, for
+    /// debugging what a peer is sending
+    ///
+    /// The event has the resolved name and value. It is only called
+    /// for instructions that decoded successfully
+    pub fn set_trace<F: FnMut(&TraceEvent) + 'static>(&mut self, trace: F) {
+        self.trace = Some(Box::new(trace));
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.trace = None;
+    }
+
+    // the size of the dynamic table as defined in RFC 7541 4.1
+    // (the sum of the entry sizes), eg. for monitoring memory use
+    pub fn dynamic_table_size(&self) -> usize {
+        self.table.dyn_size()
+    }
+
+    // the max size the peer last set with a size update
+    // (at most the SETTINGS_HEADER_TABLE_SIZE sent)
+    pub fn dynamic_table_max(&self) -> usize {
+        self.table.dyn_max_size()
+    }
+
+    // the dynamic table contents, for comparing against what
+    // the peer's encoder thinks the table holds
+    pub fn dump_table(&self) -> String {
+        self.table.dump()
     }
 
     /// function that takes the hpack block part of the header
     /// and creates a header list from it.
     ///
+    /// Same as decode but wrapped up as a HeaderList
+    pub fn get_header_list(&mut self, hpack_block: &[u8]) -> Result<HeaderList, Http2Error> {
+        let entries = try!(self.decode(hpack_block));
+        Ok(entries.into())
+    }
+
+    /// Same as decode but the headers do not share anything with
+    /// the dynamic table, static table hits are borrowed so they
+    /// are never allocated
+    pub fn decode_cow(&mut self, hpack_block: &[u8]) -> Result<Vec<DecodedHeader>, Http2Error> {
+        let entries = try!(self.decode(hpack_block));
+        Ok(entries.into_iter().map(|e| e.into()).collect())
+    }
+
+    /// Decode a complete header block into its header fields
+    /// in the order they were encoded.
+    ///
     /// This must take a complete block and not just a fragment
     /// ie. Until the END_HEADERS flag is passed
     ///
-    /// Needs the dynamic table to be managed by the connection
-    /// because it is a stateful list used for the entire connection
-    pub fn get_header_list(&mut self, hpack_block: &[u8]) -> Result<HeaderList, &'static str> {
-
-        let mut bts = hpack_block.iter().peekable();
+    /// The dynamic table is kept between calls because it is a
+    /// stateful list used for the entire connection, so every block
+    /// received on a connection must go through the same Decoder
+    pub fn decode(&mut self, hpack_block: &[u8]) -> Result<Vec<HeaderEntry>, Http2Error> {
 
         // just assuming 10 entries is enough for now
-        let mut header_list = HeaderList::with_capacity(10);
-
-        // loop though all the entries and determine the header representation
-        // type in order to decode it properly
-        //
-        // hpack_block points to the first encoded entry, after each entry is decoded
-        // must find out how much of the buffer has been consumed
-
-        while bts.peek().is_some() {
-            //let val = *bts.peek().unwrap();
-            let entry;
-
-            match *bts.peek().unwrap() {
-                val if val & 0x80 == 0x80 => entry = try!(self.indexed_header(&mut bts)),
-                val if val & 0xC0 == 0x40 => entry = try!(self.literal_header(&mut bts)),
-                val if val & 0xF0 == 0x00 => entry = try!(self.literal_header_unindexed(&mut bts)),
-                val if val & 0xF0 == 0x10 => entry = try!(self.literal_header_never_indexed(&mut bts)),
-                val if val & 0xE0 == 0x20 =>       { try!(self.size_update(&mut bts)); continue; },
-                _ => return Err("Unrecognized block type"),
+        let mut entries = Vec::with_capacity(10);
+        try!(self.decode_into(hpack_block, &mut entries));
+        Ok(entries)
+    }
+
+    /// Decode a header block of block_len octets from the front of
+    /// input, whatever comes after it (eg. the rest of a frame) is
+    /// not touched
+    ///
+    /// Header blocks do not say where they end so the length has to
+    /// come from the frame layer. The block must be made of complete
+    /// representations that end right at block_len, so the octets
+    /// consumed (given back with the fields) are always block_len
+    pub fn decode_with_len(&mut self, input: &[u8], block_len: usize) -> Result<(Vec<HeaderEntry>, usize), Http2Error> {
+        if input.len() < block_len {
+            return Err(HpackError::TruncatedBlock(block_len).into());
+        }
+        // a representation that runs past block_len is cut off and
+        // fails like any other truncated representation
+        let entries = try!(self.decode(&input[..block_len]));
+        Ok((entries, block_len))
+    }
+
+    /// Decode the block one field at a time
+    ///
+    /// Each field is decoded (and the dynamic table updated) as the
+    /// iterator is advanced. A field that is not allowed in HTTP/2 comes
+    /// out as an Err(Http2Error::Hpack(HpackError::Protocol)) and the
+    /// fields after it still follow, any other error ends the block.
+    ///
+    /// Dropping the iterator early decodes whatever is left so the table
+    /// still ends up in sync with the peer
+    pub fn decode_iter<'a>(&'a mut self, hpack_block: &'a [u8]) -> DecodeIter<'a> {
+        DecodeIter {
+            decoder: self,
+            rest: hpack_block,
+            block: BlockState::new(),
+            field: Vec::with_capacity(1),
+            done: false,
+        }
+    }
+
+    /// Same as decode but raw (not huffman encoded) literals are
+    /// borrowed from hpack_block instead of being copied
+    ///
+    /// Static table hits borrow the static strings. The dynamic
+    /// table changes while the block is decoded (an entry can be
+    /// evicted by the fields after it) so dynamic table hits and
+    /// huffman encoded literals are owned
+    pub fn decode_borrowed<'a>(&mut self, hpack_block: &'a [u8]) -> Result<Vec<BorrowedHeader<'a>>, Http2Error> {
+        let mut entries = Vec::with_capacity(10);
+        try!(self.decode_into(hpack_block, &mut entries));
+        Ok(entries)
+    }
+
+    /// Same as decode but every name and value is copied into arena
+    ///
+    /// Nothing is allocated for the fields themselves once the arena
+    /// is big enough (only the dynamic table gets its own copies).
+    /// The fields are added after what is already in the arena so it
+    /// should be reset between blocks, and after an error
+    ///
+    /// Returns the number of fields added
+    pub fn decode_arena(&mut self, hpack_block: &[u8], arena: &mut HeaderArena) -> Result<usize, Http2Error> {
+        let start_len = arena.len();
+        try!(self.decode_into(hpack_block, arena));
+        Ok(arena.len() - start_len)
+    }
+
+    // decode every field of the block into store
+    //
+    // a bad field makes the request malformed but the rest of
+    // the block must still be decoded to keep the dynamic table
+    // in sync with the peer, so the first one is kept until the end
+    fn decode_into<'a, S: FieldStore<'a>>(&mut self, hpack_block: &'a [u8], store: &mut S) -> Result<(), HpackError> {
+        let mut rest = hpack_block;
+        let mut block = BlockState::new();
+        let mut protocol_error = None;
+
+        loop {
+            match self.decode_field(&mut rest, &mut block, store) {
+                Ok(true)  => {},
+                Ok(false) => break,
+                Err(HpackError::Protocol(e)) => {
+                    if protocol_error.is_none() {
+                        protocol_error = Some(e);
+                    }
+                },
+                Err(e) => return Err(e),
             }
-            header_list.add_entry(entry);
         }
 
-        Ok(header_list)
+        match protocol_error {
+            Some(e) => Err(e.into()),
+            None    => Ok(()),
+        }
     }
 
+    // determine the representation of the next field in the block and
+    // decode it into store, every decode path goes through here
+    //
+    // Ok(false) at the end of the block. A field that is not allowed in
+    // HTTP/2 is taken off the block without being stored and comes back
+    // as Err(HpackError::Protocol), the block can still be decoded after
+    // that but not after any other error
+    fn decode_field<'a, S: FieldStore<'a>>(&mut self, block: &mut &'a [u8], state: &mut BlockState, store: &mut S) -> Result<bool, HpackError> {
+        loop {
+            let kind = match block.first() {
+                None => return Ok(false),
+                Some(&octet) => Instruction::from_octet(octet),
+            };
+            match kind {
+                // size updates are only allowed at the start of a block
+                Instruction::SizeUpdate if state.fields > 0 => return Err(HpackError::SizeUpdateNotAtStart),
+                Instruction::LiteralIncremental => try!(self.check_indexing()),
+                _ => {},
+            }
+            let (index, rest) = try!(integers::decode_integer_slice(*block, kind.prefix()));
+            let index = index as usize;
+            *block = rest;
 
-    // be carful using this funciton as it is stateful, call it in the correct order
-    fn consume_literal<'a, I: Iterator<Item=&'a u8>>(&self, bts: &mut Peekable<I>) -> Result<String, &'static str> {
-        // get value length and huffman status
-        let is_huffman = *bts.peek().unwrap() & 0x80 == 0x80;
-        let length = try!(integers::decode_integer(bts, 7)) as usize;
+            let (name, value) = match kind {
+                Instruction::Indexed => try!(self.indexed_header(index, store)),
+                Instruction::LiteralIncremental => try!(self.literal_header(index, block, store)),
+                Instruction::LiteralWithout => try!(self.literal_header_unindexed(index, block, store)),
+                Instruction::LiteralNever => try!(self.literal_header_never_indexed(index, block, store)),
+                Instruction::SizeUpdate => {
+                    try!(Decoder::count_size_update(&mut state.size_updates));
+                    if index != 0 {
+                        try!(self.check_indexing());
+                    }
+                    try!(self.size_update(index));
+                    self.trace(kind, "", "", index);
+                    continue;
+                },
+            };
 
-        let value;
-        if is_huffman {
-            value = self.huffman.decode(bts.borrow_take(length));
+            self.trace(kind, store.as_str(&name), store.as_str(&value), index);
+
+            state.fields += 1;
+            try!(self.check_field(store.as_str(&name), store.as_str(&value), &mut state.list_size));
+            // never indexed fields are marked so whoever encodes them
+            // next keeps them out of their dynamic table as well
+            store.push(name, value, kind == Instruction::LiteralNever);
+            return Ok(true);
         }
-        else {
-            value = bts.borrow_take(length).map(|x|*x).collect();
+    }
+
+    fn trace(&mut self, kind: Instruction, name: &str, value: &str, index: usize) {
+        if let Some(ref mut trace) = self.trace {
+            trace(&TraceEvent { kind: kind, name: name, value: value, index: index });
+        }
+    }
+
+    // what decode_field checks on every field
+    //
+    // the list size is checked as each field comes out so a bomb
+    // stops early, a bad name is an HpackError::Protocol
+    fn check_field(&self, name: &str, value: &str, list_size: &mut usize) -> Result<(), HpackError> {
+        *list_size = list_size.saturating_add(entry_size(name, value));
+        if *list_size > self.max_header_list_size {
+            return Err(HpackError::HeaderListTooLarge);
+        }
+        try!(validate_name(name));
+        Ok(())
+    }
+
+    // size updates must all come before the first field
+    // (that is checked by the caller) and there can only be two
+    fn count_size_update(size_updates: &mut usize) -> Result<(), HpackError> {
+        *size_updates += 1;
+        if *size_updates > MAX_SIZE_UPDATES {
+            return Err(HpackError::TooManySizeUpdates);
         }
+        Ok(())
+    }
 
-        unsafe { Ok(String::from_utf8_unchecked(value)) }
+    // in static only mode nothing may change the dynamic table
+    fn check_indexing(&self) -> Result<(), HpackError> {
+        if self.static_only {
+            return Err(HpackError::DynamicTableDisabled);
+        }
+        Ok(())
+    }
+
+    // in static only mode the index must be in the static table
+    fn check_index(&self, index: usize) -> Result<(), HpackError> {
+        if self.static_only && index > STATIC_TABLE_LEN {
+            return Err(HpackError::DynamicTableDisabled);
+        }
+        Ok(())
+    }
+
+    // a string literal from the front of block, it must be UTF-8
+    //
+    // raw literals are handed to the store as they are in the block,
+    // huffman literals are decoded into the scratch buffer first
+    fn literal<'a, S: FieldStore<'a>>(&mut self, block: &mut &'a [u8], store: &mut S) -> Result<S::Str, HpackError> {
+        let is_huffman = match block.first() {
+            Some(b) => *b & 0x80 == 0x80,
+            None    => false, // decode_integer_slice gives the error
+        };
+        let stored = if is_huffman {
+            self.scratch.clear();
+            *block = try!(strings::decode_string_into(*block, &mut self.scratch));
+            store.decoded(&self.scratch)
+        }
+        else {
+            let (octets, rest) = try!(strings::decode_string_slice(*block));
+            *block = rest;
+            match octets {
+                Cow::Borrowed(b) => store.raw(b),
+                Cow::Owned(v)    => store.decoded(&v),
+            }
+        };
+        stored.map_err(|_| HpackError::InvalidUtf8)
+    }
+
+    // the name of a literal, an index of 0 means the name is a literal
+    // in the block. The table entry for an indexed name is given back
+    // too so a new dynamic table entry can share it
+    fn literal_name<'a, S: FieldStore<'a>>(&mut self, index: usize, block: &mut &'a [u8], store: &mut S) -> Result<(S::Str, Option<EntryInner>), HpackError> {
+        if index == 0 {
+            let name = try!(self.literal(block, store));
+            Ok((name, None))
+        }
+        else {
+            try!(self.check_index(index));
+            let name = try!(self.table.get_name_rc(index));
+            Ok((store.entry(name.clone()), Some(name)))
+        }
     }
 
     /// ===============================
@@ -100,10 +457,15 @@ impl Decoder {
     /// The index value of 0 is not used. It MUST be treated as a decoding error if found in an indexed header field representation.
     ///
 
-    fn indexed_header<'a, I: Iterator<Item=&'a u8>>(&self, bts: &mut I) -> Result<HeaderEntry, &'static str> {
-        let index = try!(integers::decode_integer(bts, 7));
-        let entry = try!(self.table.get_header_entry(index as usize));
-        Ok(entry)
+    fn indexed_header<'a, S: FieldStore<'a>>(&self, index: usize, store: &mut S) -> Result<(S::Str, S::Str), HpackError> {
+        if index == 0 {
+            return Err(HpackError::ZeroIndex);
+        }
+        try!(self.check_index(index));
+        match self.table.get(index) {
+            Some(entry) => Ok((store.entry(entry.0), store.entry(entry.1))),
+            None        => Err(HpackError::InvalidIndex(index)),
+        }
     }
 
     /// 6.2 Literal Header Field Representation
@@ -157,24 +519,19 @@ impl Decoder {
     /// represented as a string literal (see Section 5.2).
     ///
 
-    fn literal_header<'a, I: Iterator<Item=&'a u8>>(&mut self, bts: &mut Peekable<I>) -> Result<HeaderEntry, &'static str> {
+    fn literal_header<'a, S: FieldStore<'a>>(&mut self, index: usize, block: &mut &'a [u8], store: &mut S) -> Result<(S::Str, S::Str), HpackError> {
+        let (name, name_entry) = try!(self.literal_name(index, block, store));
+        let value = try!(self.literal(block, store));
 
-        let index = try!(integers::decode_integer(bts, 6));
-
-        if index == 0 { // must get name and value from literal
-            let name = try!(self.consume_literal(bts));
-            let value = try!(self.consume_literal(bts));
-            self.table.add_entry_literal(name, value);
-        }
-        else { // have name via index
-            let value = try!(self.consume_literal(bts));
-            try!(self.table.add_entry_id(index as usize, value));
-        }
-
-        // the entry to return will always be the latest added
-        // entry in the dynamic table for this case
-        let header_entry = self.table.get_dyn_front();
-        Ok(header_entry)
+        // the table and the header list share the strings when the
+        // store allows it. The entry might not fit in the table (which
+        // then ends up empty) so the header is not read back out of the table
+        let name_entry = match name_entry {
+            Some(entry) => entry,
+            None        => store.to_entry(&name),
+        };
+        self.table.add_entry_literal(name_entry, store.to_entry(&value));
+        Ok((name, value))
     }
 
     ///
@@ -220,11 +577,10 @@ impl Decoder {
     /// Either form of header field name representation is followed by the header field value
     /// represented as a string literal (see Section 5.2).
 
-    fn literal_header_unindexed<'a, I: Iterator<Item=&'a u8>>(&self, bts: &mut Peekable<I>) -> Result<HeaderEntry, &'static str> {
-        // this function is more useful for intermediaries which
-        // this library does not care about at the moment
-        // so it will be treated the same as never indexed
-        self.literal_header_never_indexed(bts)
+    fn literal_header_unindexed<'a, S: FieldStore<'a>>(&mut self, index: usize, block: &mut &'a [u8], store: &mut S) -> Result<(S::Str, S::Str), HpackError> {
+        // same encoding as never indexed (4 bit prefix)
+        // just without the sensitive marking
+        self.literal_header_no_table(index, block, store)
     }
 
     ///
@@ -270,23 +626,17 @@ impl Decoder {
     ///
     /// The encoding of the representation is identical to the literal header field without indexing (see Section 6.2.2).
 
-    fn literal_header_never_indexed<'a, I: Iterator<Item=&'a u8>>(&self, bts: &mut Peekable<I>) -> Result<HeaderEntry, &'static str> {
-
-        let index = try!(integers::decode_integer(bts, 4));
-
-        let header_entry: HeaderEntry;
-        if index == 0 { // must get name and value from literal
-            let name = try!(self.consume_literal(bts));
-            let value = try!(self.consume_literal(bts));
-            header_entry = HeaderEntry::new(name, value);
-        }
-        else { // have name via index
-            let name_rc = try!(self.table.get_name_rc(index as usize));
-            let value = try!(self.consume_literal(bts));
-            header_entry = HeaderEntry::new(name_rc, value);
-        }
+    fn literal_header_never_indexed<'a, S: FieldStore<'a>>(&mut self, index: usize, block: &mut &'a [u8], store: &mut S) -> Result<(S::Str, S::Str), HpackError> {
+        // decode_field marks it sensitive
+        self.literal_header_no_table(index, block, store)
+    }
 
-        Ok(header_entry)
+    // the shared part of the without indexing and never indexed forms
+    // the dynamic table is not touched
+    fn literal_header_no_table<'a, S: FieldStore<'a>>(&mut self, index: usize, block: &mut &'a [u8], store: &mut S) -> Result<(S::Str, S::Str), HpackError> {
+        let (name, _) = try!(self.literal_name(index, block, store));
+        let value = try!(self.literal(block, store));
+        Ok((name, value))
     }
 
     ///
@@ -309,17 +659,229 @@ impl Decoder {
     ///
     /// Reducing the maximum size of the dynamic table can cause entries to be evicted (see Section 4.3).
 
-    fn size_update<'a, I: Iterator<Item=&'a u8>>(&mut self, bts: &mut I) -> Result<(), &'static str> {
-        let size = try!(integers::decode_integer(bts, 5));
-        self.table.max_size_update(size as usize);
+    fn size_update(&mut self, size: usize) -> Result<(), HpackError> {
+        if size > self.protocol_max_size {
+            return Err(HpackError::SizeUpdateTooLarge(size));
+        }
+        self.table.max_size_update(size);
         Ok(())
     }
 }
 
+/// The new size if block starts with a dynamic table size update
+/// (001xxxxx) and the octets it takes up, without decoding anything
+/// else or touching a Decoder
+///
+/// None if it starts with anything else, or the size is cut off or
+/// too big to be valid
+pub fn peek_size_update(block: &[u8]) -> Option<(u32, usize)> {
+    match block.first() {
+        Some(&first) if first & 0xE0 == 0x20 => {
+            integers::decode_integer_slice(block, PREFIX_5).ok()
+                .map(|(size, rest)| (size, block.len() - rest.len()))
+        },
+        _ => None,
+    }
+}
+
+/// Settings to make a Decoder with, each setter takes the
+/// builder by value so they can be chained
+///
+/// ```ignore
+/// let decoder = Decoder::builder()
+///     .max_header_list_size(16384)
+///     .static_only(true)
+///     .build();
+/// ```
+///
+/// The defaults are the same as a new connection, the same
+/// as Decoder::new(4096, 10)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderBuilder {
+    max_dynamic_table_size: usize,
+    num_entries: usize,
+    max_header_list_size: usize,
+    static_only: bool,
+}
+
+impl DecoderBuilder {
+    pub fn new() -> Self {
+        DecoderBuilder {
+            max_dynamic_table_size: DEFAULT_TABLE_SIZE,
+            num_entries: 10,
+            max_header_list_size: usize::max_value(),
+            static_only: false,
+        }
+    }
+
+    // the table size and the limit for size updates,
+    // see Decoder::set_max_dynamic_table_size
+    pub fn max_dynamic_table_size(mut self, max_size: usize) -> Self {
+        self.max_dynamic_table_size = max_size;
+        self
+    }
+
+    // the number of dynamic table entries to allocate for up front
+    pub fn num_entries(mut self, num_entries: usize) -> Self {
+        self.num_entries = num_entries;
+        self
+    }
+
+    // see Decoder::set_max_header_list_size
+    pub fn max_header_list_size(mut self, max_header_list_size: usize) -> Self {
+        self.max_header_list_size = max_header_list_size;
+        self
+    }
+
+    // see Decoder::set_static_only
+    pub fn static_only(mut self, static_only: bool) -> Self {
+        self.static_only = static_only;
+        self
+    }
+
+    pub fn build(&self) -> Decoder {
+        let mut decoder = Decoder::new(self.max_dynamic_table_size, self.num_entries);
+        decoder.set_max_header_list_size(self.max_header_list_size);
+        decoder.set_static_only(self.static_only);
+        decoder
+    }
+}
+
+impl Default for DecoderBuilder {
+    fn default() -> Self {
+        DecoderBuilder::new()
+    }
+}
+
+// what decode_field keeps track of through one block
+struct BlockState {
+    // number of fields so far, size updates must come before any
+    fields: usize,
+    list_size: usize,
+    size_updates: usize,
+}
+
+impl BlockState {
+    fn new() -> Self {
+        BlockState { fields: 0, list_size: 0, size_updates: 0 }
+    }
+}
+
+/// Where a decode path keeps the names and values it decodes, the
+/// representations are all decoded by the same step whatever the store
+///
+/// Raw literals can be kept borrowed from the block ('a), huffman
+/// literals are only borrowed until the call returns
+pub trait FieldStore<'a> {
+    type Str;
+
+    // a raw literal, as it is in the block
+    fn raw(&mut self, octets: &'a [u8]) -> Result<Self::Str, Utf8Error> {
+        self.decoded(octets)
+    }
+    // a huffman literal once it has been decoded
+    fn decoded(&mut self, octets: &[u8]) -> Result<Self::Str, Utf8Error>;
+    // a name or value from the table
+    fn entry(&mut self, entry: EntryInner) -> Self::Str;
+    // the string for a new dynamic table entry
+    fn to_entry(&self, s: &Self::Str) -> EntryInner;
+    fn as_str<'s>(&'s self, s: &'s Self::Str) -> &'s str;
+    // add a field once it is decoded and checked
+    fn push(&mut self, name: Self::Str, value: Self::Str, sensitive: bool);
+}
+
+// decode and decode_iter, literals are copied into Strings
+// that are shared with the dynamic table
+impl<'a> FieldStore<'a> for Vec<HeaderEntry> {
+    type Str = EntryInner;
+
+    fn decoded(&mut self, octets: &[u8]) -> Result<EntryInner, Utf8Error> {
+        str::from_utf8(octets).map(|s| String::from(s).into())
+    }
+    fn entry(&mut self, entry: EntryInner) -> EntryInner {
+        entry
+    }
+    fn to_entry(&self, s: &EntryInner) -> EntryInner {
+        s.clone()
+    }
+    fn as_str<'s>(&'s self, s: &'s EntryInner) -> &'s str {
+        s
+    }
+    fn push(&mut self, name: EntryInner, value: EntryInner, sensitive: bool) {
+        Vec::push(self, HeaderEntry::new(name, value).with_sensitive(sensitive));
+    }
+}
+
+// decode_borrowed, raw literals stay in the block
+impl<'a> FieldStore<'a> for Vec<BorrowedHeader<'a>> {
+    type Str = Cow<'a, str>;
+
+    fn raw(&mut self, octets: &'a [u8]) -> Result<Cow<'a, str>, Utf8Error> {
+        str::from_utf8(octets).map(Cow::Borrowed)
+    }
+    fn decoded(&mut self, octets: &[u8]) -> Result<Cow<'a, str>, Utf8Error> {
+        str::from_utf8(octets).map(|s| Cow::Owned(String::from(s)))
+    }
+    fn entry(&mut self, entry: EntryInner) -> Cow<'a, str> {
+        let s: Cow<'static, str> = entry.into();
+        s
+    }
+    // the table needs its own copy
+    fn to_entry(&self, s: &Cow<'a, str>) -> EntryInner {
+        String::from(&**s).into()
+    }
+    fn as_str<'s>(&'s self, s: &'s Cow<'a, str>) -> &'s str {
+        s
+    }
+    fn push(&mut self, name: Cow<'a, str>, value: Cow<'a, str>, sensitive: bool) {
+        Vec::push(self, BorrowedHeader { name: name, value: value, sensitive: sensitive });
+    }
+}
+
+/// Iterator over the fields of a header block, see Decoder::decode_iter
+pub struct DecodeIter<'a> {
+    decoder: &'a mut Decoder,
+    rest: &'a [u8],
+    block: BlockState,
+    // decode_field stores into this, it never
+    // holds more than the one field
+    field: Vec<HeaderEntry>,
+    // set after the end of the block or an error that
+    // leaves it impossible to keep going
+    done: bool,
+}
+
+impl<'a> Iterator for DecodeIter<'a> {
+    type Item = Result<HeaderEntry, Http2Error>;
+
+    fn next(&mut self) -> Option<Result<HeaderEntry, Http2Error>> {
+        if self.done {
+            return None;
+        }
+        match self.decoder.decode_field(&mut self.rest, &mut self.block, &mut self.field) {
+            Ok(true)                        => self.field.pop().map(Ok),
+            Ok(false)                       => { self.done = true; None },
+            Err(HpackError::Protocol(e))    => Some(Err(HpackError::Protocol(e).into())),
+            Err(e)                          => { self.done = true; Some(Err(e.into())) },
+        }
+    }
+}
+
+impl<'a> Drop for DecodeIter<'a> {
+    // the fields that were not looked at still change the
+    // dynamic table, so the block is always decoded to the end
+    fn drop(&mut self) {
+        while let Some(_) = self.next() {}
+    }
+}
+
 #[cfg(test)]
 mod decoder_tests {
 
     use super::Decoder;
+    use header::HeaderEntry;
+    use header::hpack::HpackError;
+    use frame::{ErrorCode, Http2Error};
 
     #[test]
     fn tmp_decoder_test() {
@@ -337,6 +899,823 @@ mod decoder_tests {
         assert_eq!(list.get_value_by_name("accept-charset"), Some("1"));
     }
 
+    #[test]
+    fn indexed_header() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        let list = decoder.get_header_list(&[0x82]).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.get_value_by_name(":method"), Some("GET"));
+
+        // index 0 is never valid
+        assert_eq!(decoder.get_header_list(&[0x80]).err(), Some(HpackError::ZeroIndex.into()));
+
+        // nothing in the dynamic table yet
+        assert_eq!(decoder.get_header_list(&[0xBE]).err(), Some(HpackError::InvalidIndex(62).into()));
+        assert_eq!(decoder.get_header_list(&[0xFF, 0x80, 0x01]).err(), Some(HpackError::InvalidIndex(255).into()));
+    }
+
+    #[test]
+    fn literal_incremental_indexed_name() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // :path (index 4) with the value /sample/path
+        let list = decoder.get_header_list(b"\x44\x0c/sample/path").unwrap();
+        assert_eq!(list.get_value_by_name(":path"), Some("/sample/path"));
+        assert_eq!(decoder.table.num_dyn_entries(), 1);
+        assert_eq!(decoder.table.get_header_entry(62).unwrap(), (":path", "/sample/path").into());
+    }
+
+    #[test]
+    fn literal_incremental_new_name() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        let list = decoder.get_header_list(b"\x40\x0acustom-key\x0dcustom-header").unwrap();
+        assert_eq!(list.get_value_by_name("custom-key"), Some("custom-header"));
+
+        // now addressable at the first dynamic index, and the old
+        // entry moves up when the next one goes in
+        let list = decoder.get_header_list(b"\xbe\x40\x01a\x01b").unwrap();
+        assert_eq!(list.get_value_by_name("custom-key"), Some("custom-header"));
+        assert_eq!(decoder.table.get_header_entry(62).unwrap(), ("a", "b").into());
+        assert_eq!(decoder.table.get_header_entry(63).unwrap(), ("custom-key", "custom-header").into());
+
+        // name taken from the dynamic table
+        let list = decoder.get_header_list(b"\x7f\x00\x01c").unwrap();
+        assert_eq!(list.get_value_by_name("custom-key"), Some("c"));
+        assert_eq!(decoder.table.num_dyn_entries(), 3);
+    }
+
+    #[test]
+    fn literal_incremental_too_big() {
+        let mut decoder = Decoder::new(40, 10);
+
+        decoder.get_header_list(b"\x40\x01a\x01b").unwrap();
+        assert_eq!(decoder.table.num_dyn_entries(), 1);
+
+        // does not fit so the table ends up empty but the header is still decoded
+        let list = decoder.get_header_list(b"\x40\x0acustom-key\x0dcustom-header").unwrap();
+        assert_eq!(list.get_value_by_name("custom-key"), Some("custom-header"));
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+    }
+
+    #[test]
+    fn literal_without_indexing() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // RFC 7541 C.2.2
+        let list = decoder.get_header_list(b"\x04\x0c/sample/path").unwrap();
+        let entry = list.iter().next().unwrap();
+        assert_eq!(entry, &(":path", "/sample/path").into());
+        assert!(!entry.is_sensitive());
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+
+        // new name form
+        let list = decoder.get_header_list(b"\x00\x01a\x01b").unwrap();
+        assert_eq!(list.get_value_by_name("a"), Some("b"));
+        assert!(!list.iter().next().unwrap().is_sensitive());
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+    }
+
+    #[test]
+    fn literal_never_indexed() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // RFC 7541 C.2.3
+        let list = decoder.get_header_list(b"\x10\x08password\x06secret").unwrap();
+        let entry = list.iter().next().unwrap();
+        assert_eq!(entry, &HeaderEntry::new("password", "secret").with_sensitive(true));
+        assert!(entry.is_sensitive());
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+
+        // indexed name form (authorization is 23)
+        let list = decoder.get_header_list(b"\x1f\x08\x05token").unwrap();
+        let entry = list.iter().next().unwrap();
+        assert_eq!(entry, &HeaderEntry::new("authorization", "token").with_sensitive(true));
+        assert!(entry.is_sensitive());
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+    }
+
+    #[test]
+    fn size_update_evicts() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // literal with incremental indexing (a: 1)
+        let list = decoder.get_header_list(&[0x40, 0x01, 0x61, 0x01, 0x31]).unwrap();
+        assert_eq!(list.get_value_by_name("a"), Some("1"));
+        assert_eq!(decoder.table.num_dyn_entries(), 1);
+
+        // shrink to 0 then refer to the evicted entry
+        assert_eq!(decoder.get_header_list(&[0x20, 0xBE]).err(), Some(HpackError::InvalidIndex(62).into()));
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+
+        // back up to 4096 is fine
+        let list = decoder.get_header_list(&[0x3F, 0xE1, 0x1F, 0x82]).unwrap();
+        assert_eq!(list.get_value_by_name(":method"), Some("GET"));
+    }
+
+    #[test]
+    fn size_update_illegal() {
+        let mut decoder = Decoder::new(100, 10);
+
+        // 4096 is over the limit of 100
+        assert_eq!(decoder.get_header_list(&[0x3F, 0xE1, 0x1F]).err(), Some(HpackError::SizeUpdateTooLarge(4096).into()));
+
+        // only allowed at the start of a block
+        assert_eq!(decoder.get_header_list(&[0x82, 0x20]).err(), Some(HpackError::SizeUpdateNotAtStart.into()));
+        assert_eq!(decoder.get_header_list(&[0x20, 0x82, 0x3f, 0x45]).err(), Some(HpackError::SizeUpdateNotAtStart.into()));
+        assert_eq!(decoder.decode_borrowed(&[0x20, 0x82, 0x20]).err(), Some(HpackError::SizeUpdateNotAtStart.into()));
+
+        // at most two in a row
+        assert_eq!(decoder.decode(&[0x20, 0x3f, 0x45, 0x20, 0x82]).err(), Some(HpackError::TooManySizeUpdates.into()));
+        assert_eq!(decoder.decode_borrowed(&[0x20, 0x20, 0x20]).err(), Some(HpackError::TooManySizeUpdates.into()));
+    }
+
+    #[test]
+    fn two_size_updates() {
+        let mut decoder = Decoder::new(100, 10);
+        decoder.decode(b"\x40\x01a\x01b").unwrap();
+
+        // down to 0 (evicting a: b) and then back up to 100
+        let entries = decoder.decode(&[0x20, 0x3f, 0x45, 0x82]).unwrap();
+        assert_eq!(entries, vec![(":method", "GET").into()]);
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+        assert_eq!(decoder.table.dyn_max_size(), 100);
+
+        let entries = decoder.decode_borrowed(&[0x3f, 0x2b, 0x3f, 0x45, 0x82]).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(decoder.table.dyn_max_size(), 100);
+    }
+
+    #[test]
+    fn dynamic_index_past_table() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // nothing in the dynamic table yet
+        let e = decoder.decode(&[0xbe]).unwrap_err();
+        assert_eq!(e, HpackError::InvalidIndex(62).into());
+        assert_eq!(e.to_string(), "hpack: index 62 is out of range");
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+        // as a name index too
+        assert_eq!(decoder.decode(b"\x7f\x00\x01a").err(), Some(HpackError::InvalidIndex(63).into()));
+        assert_eq!(decoder.decode(b"\x0f\x30\x01a").err(), Some(HpackError::InvalidIndex(63).into()));
+        assert_eq!(decoder.decode_borrowed(b"\x7f\x00\x01a").err(), Some(HpackError::InvalidIndex(63).into()));
+
+        // one entry means 62 is fine and 63 is not
+        decoder.decode(b"\x40\x01a\x01b").unwrap();
+        assert_eq!(decoder.decode(&[0xbe]).unwrap(), vec![("a", "b").into()]);
+        assert_eq!(decoder.decode(&[0xbf]).err(), Some(HpackError::InvalidIndex(63).into()));
+        assert_eq!(decoder.decode(&[0xff, 0x80, 0x01]).err(), Some(HpackError::InvalidIndex(255).into()));
+    }
+
+    #[test]
+    fn static_index_out_of_range() {
+        use header::hpack::integers::{encode_integer_into, PREFIX_4, PREFIX_6, PREFIX_7};
+        use header::HeaderArena;
+
+        let mut decoder = Decoder::new(4096, 10);
+        let mut arena = HeaderArena::new();
+
+        // indices just past the static table up to the largest
+        // the integer codec takes, as an index and as a name
+        for &index in &[62, 100, 0xFFFF, u32::max_value()] {
+            let mut blocks = Vec::new();
+            for &(prefix, flags) in &[(PREFIX_7, 0x80), (PREFIX_6, 0x40), (PREFIX_4, 0x00), (PREFIX_4, 0x10)] {
+                let mut block = Vec::new();
+                encode_integer_into(index, &mut block, prefix, flags);
+                if flags != 0x80 {
+                    block.extend_from_slice(b"\x01a");
+                }
+                blocks.push(block);
+            }
+
+            for block in &blocks {
+                let expected: Option<Http2Error> = Some(HpackError::InvalidIndex(index as usize).into());
+                assert_eq!(decoder.decode(block).err(), expected);
+                assert_eq!(decoder.decode_borrowed(block).err(), expected);
+                assert_eq!(decoder.decode_arena(block, &mut arena).err(), expected);
+                assert_eq!(decoder.decode_iter(block).next(), Some(Err(HpackError::InvalidIndex(index as usize).into())));
+                assert_eq!(decoder.decode(block).unwrap_err().error_code(), ErrorCode::CompressionError);
+                arena.reset();
+            }
+        }
+    }
+
+    // the request examples without huffman coding
+    // the blocks are decoded one after the other on the same
+    // connection so later ones refer to entries of earlier ones
+    #[test]
+    fn rfc_c3_requests() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // C.3.1
+        let entries = decoder.decode(b"\x82\x86\x84\x41\x0fwww.example.com").unwrap();
+        assert_eq!(entries, vec![
+            (":method", "GET").into(),
+            (":scheme", "http").into(),
+            (":path", "/").into(),
+            (":authority", "www.example.com").into(),
+        ] as Vec<HeaderEntry>);
+        assert_eq!(decoder.table.num_dyn_entries(), 1);
+        assert_eq!(decoder.table.dyn_size(), 57);
+
+        // C.3.2
+        let entries = decoder.decode(b"\x82\x86\x84\xbe\x58\x08no-cache").unwrap();
+        assert_eq!(entries, vec![
+            (":method", "GET").into(),
+            (":scheme", "http").into(),
+            (":path", "/").into(),
+            (":authority", "www.example.com").into(),
+            ("cache-control", "no-cache").into(),
+        ] as Vec<HeaderEntry>);
+        assert_eq!(decoder.table.num_dyn_entries(), 2);
+        assert_eq!(decoder.table.dyn_size(), 110);
+
+        // C.3.3
+        let entries = decoder.decode(b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value").unwrap();
+        assert_eq!(entries, vec![
+            (":method", "GET").into(),
+            (":scheme", "https").into(),
+            (":path", "/index.html").into(),
+            (":authority", "www.example.com").into(),
+            ("custom-key", "custom-value").into(),
+        ] as Vec<HeaderEntry>);
+        assert_eq!(decoder.table.num_dyn_entries(), 3);
+        assert_eq!(decoder.table.dyn_size(), 164);
+        assert_eq!(decoder.table.get_header_entry(62).unwrap(), ("custom-key", "custom-value").into());
+        assert_eq!(decoder.table.get_header_entry(63).unwrap(), ("cache-control", "no-cache").into());
+        assert_eq!(decoder.table.get_header_entry(64).unwrap(), (":authority", "www.example.com").into());
+    }
+
+    #[test]
+    fn header_list_too_large() {
+        let mut decoder = Decoder::new(4096, 10);
+        decoder.set_max_header_list_size(16384);
+
+        // one 1000 octet value added to the table and then
+        // referenced over and over, ~1MB once decoded
+        let mut block = vec![0x40, 0x01, b'a', 0x7f, 0xe9, 0x06];
+        block.extend_from_slice(&[b'x'; 1000]);
+        block.extend_from_slice(&[0xbe; 1000]);
+
+        assert_eq!(decoder.decode(&block).err(), Some(HpackError::HeaderListTooLarge.into()));
+
+        // exactly at the limit is fine
+        let mut decoder = Decoder::new(4096, 10);
+        decoder.set_max_header_list_size(2 * (1 + 1 + 32));
+        assert!(decoder.decode(b"\x40\x01a\x01b\xbe").is_ok());
+        assert_eq!(decoder.decode(b"\xbe\xbe\xbe").err(), Some(HpackError::HeaderListTooLarge.into()));
+    }
+
+    #[test]
+    fn builder() {
+        use super::DecoderBuilder;
+
+        let mut decoder = Decoder::builder().max_header_list_size(2 * (1 + 1 + 32)).build();
+        assert!(decoder.decode(b"\x40\x01a\x01b\xbe").is_ok());
+        assert_eq!(decoder.decode(b"\xbe\xbe\xbe").err(), Some(HpackError::HeaderListTooLarge.into()));
+        assert_eq!(decoder.dynamic_table_max(), 4096);
+
+        let builder = DecoderBuilder::default().max_dynamic_table_size(100).num_entries(2).static_only(true);
+        let mut decoder = builder.build();
+        assert!(decoder.is_static_only());
+        assert_eq!(decoder.dynamic_table_max(), 100);
+        assert_eq!(decoder.decode(b"\x40\x01a\x01b").err(), Some(HpackError::DynamicTableDisabled.into()));
+
+        // size updates are checked against the builder size
+        let mut decoder = builder.static_only(false).build();
+        assert_eq!(decoder.decode(b"\x3f\x46").err(), Some(HpackError::SizeUpdateTooLarge(101).into()));
+        assert!(decoder.decode(b"\x3f\x45").is_ok());
+    }
+
+    #[test]
+    fn peek_size_update() {
+        use super::peek_size_update;
+
+        assert_eq!(peek_size_update(b"\x3f\xe1\x1f\x82"), Some((4096, 3)));
+        assert_eq!(peek_size_update(b"\x20\x3f\xe1\x1f"), Some((0, 1)));
+        assert_eq!(peek_size_update(b"\x3e"), Some((30, 1)));
+
+        // any other first representation
+        assert_eq!(peek_size_update(b"\x82\x20"), None);
+        assert_eq!(peek_size_update(b"\x40\x01a\x01b"), None);
+        assert_eq!(peek_size_update(b"\x00\x01a\x01b"), None);
+        assert_eq!(peek_size_update(b""), None);
+        // cut off
+        assert_eq!(peek_size_update(b"\x3f\xe1"), None);
+
+        // the block is still decoded as usual afterwards
+        let block = b"\x3f\x45\x82";
+        assert_eq!(peek_size_update(block), Some((100, 2)));
+        let mut decoder = Decoder::new(4096, 10);
+        assert_eq!(decoder.decode(block).unwrap().len(), 1);
+        assert_eq!(decoder.dynamic_table_max(), 100);
+    }
+
+    #[test]
+    fn invalid_names() {
+        use header::ProtocolError;
+
+        let mut decoder = Decoder::new(4096, 10);
+
+        // mixed case name, with incremental indexing
+        assert_eq!(decoder.decode(b"\x40\x0aCustom-Key\x01a").err(),
+            Some(HpackError::Protocol(ProtocolError::UppercaseName).into()));
+        // the entry still went in the table to stay in sync with the peer
+        assert_eq!(decoder.table.num_dyn_entries(), 1);
+
+        // embedded space, without indexing, the following field is still decoded
+        assert_eq!(decoder.decode(b"\x00\x0acustom key\x01a\x40\x01b\x01c").err(),
+            Some(HpackError::Protocol(ProtocolError::InvalidNameChar(b' ')).into()));
+        assert_eq!(decoder.table.num_dyn_entries(), 2);
+
+        // a ':' only goes at the start
+        assert_eq!(decoder.decode(b"\x10\x03a:b\x01a").err(),
+            Some(HpackError::Protocol(ProtocolError::InvalidNameChar(b':')).into()));
+        assert!(decoder.decode(b"\x00\x05:path\x01/").is_ok());
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        use header::hpack::strings::encode_string;
+        use header::HeaderArena;
+
+        let mut decoder = Decoder::new(4096, 10);
+        let mut arena = HeaderArena::new();
+        // a huffman coded value that decodes to a lone 0xff
+        let mut huffman = b"\x04".to_vec();
+        encode_string(b"\xff", true, &mut huffman);
+
+        // a raw value, a raw name, a cut off character and a huffman
+        // value, in each of the literal forms
+        let blocks: &[&[u8]] = &[b"\x00\x01a\x01\xff", b"\x10\x02\xc3(\x01v",
+            b"\x40\x01a\x02\xe2\x82", b"\x0f\x10\x01\x80", &huffman];
+        for block in blocks {
+            assert_eq!(decoder.decode(block).err(), Some(HpackError::InvalidUtf8.into()));
+            assert_eq!(decoder.decode_borrowed(block).err(), Some(HpackError::InvalidUtf8.into()));
+            assert_eq!(decoder.decode_arena(block, &mut arena).err(), Some(HpackError::InvalidUtf8.into()));
+            assert!(arena.is_empty());
+            assert_eq!(decoder.decode(block).unwrap_err().error_code(), ErrorCode::CompressionError);
+        }
+        assert_eq!(decoder.dynamic_table_size(), 0);
+
+        // anything that is UTF-8 is fine
+        let block = b"\x00\x01a\x05\xc3\xa9\xe2\x82\xac";
+        assert_eq!(decoder.decode(block).unwrap(), vec![HeaderEntry::new("a", String::from("\u{e9}\u{20ac}"))]);
+        assert_eq!(&*decoder.decode_borrowed(block).unwrap()[0].value, "\u{e9}\u{20ac}");
+        decoder.decode_arena(block, &mut arena).unwrap();
+        assert_eq!(&*arena.get(0).unwrap().value, "\u{e9}\u{20ac}");
+    }
+
+    #[test]
+    fn empty_names() {
+        use header::{ProtocolError, HeaderArena};
+
+        let mut decoder = Decoder::new(4096, 10);
+        let empty: Option<Http2Error> = Some(HpackError::Protocol(ProtocolError::EmptyName).into());
+        let mut arena = HeaderArena::new();
+
+        // a zero length name literal in each of the literal forms
+        for block in &[&b"\x00\x00\x01v"[..], b"\x10\x00\x01v", b"\x00\x80\x01v"] {
+            assert_eq!(decoder.decode(block).err(), empty);
+            assert_eq!(decoder.decode_borrowed(block).err(), empty);
+            assert_eq!(decoder.decode_arena(block, &mut arena).err(), empty);
+            arena.reset();
+        }
+        assert_eq!(decoder.decode(b"\x40\x00\x01v\x82").err(), empty);
+        assert_eq!(decoder.decode(b"\x40\x00\x01v").unwrap_err().error_code(), ErrorCode::ProtocolError);
+
+        // the entries still went in the table, so a table entry with
+        // an empty name is caught too
+        assert_eq!(decoder.table.num_dyn_entries(), 2);
+        assert_eq!(decoder.decode(b"\xbe").err(), empty);
+        assert_eq!(decoder.decode_borrowed(b"\xbe").err(), empty);
+        assert_eq!(decoder.decode(b"\x0f\x2f\x01w").err(), empty);
+
+        // an empty value is fine (like :authority in the static table)
+        assert_eq!(decoder.decode(b"\x81\x00\x01a\x00").unwrap(), vec![(":authority", "").into(), ("a", "").into()]);
+    }
+
+    #[test]
+    fn lower_max_dynamic_table_size() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // two entries of size 34
+        decoder.decode(b"\x40\x01a\x011\x40\x01b\x012").unwrap();
+        assert_eq!(decoder.table.num_dyn_entries(), 2);
+
+        // only room for one now, the oldest is evicted
+        decoder.set_max_dynamic_table_size(40);
+        assert_eq!(decoder.table.num_dyn_entries(), 1);
+        assert_eq!(decoder.table.dyn_max_size(), 40);
+
+        let entries = decoder.decode(b"\xbe").unwrap();
+        assert_eq!(entries, vec![("b", "2").into()] as Vec<HeaderEntry>);
+        assert_eq!(decoder.decode(b"\xbf").err(), Some(HpackError::InvalidIndex(63).into()));
+
+        // new entries are held to the new size
+        decoder.decode(b"\x40\x01c\x013").unwrap();
+        assert_eq!(decoder.table.num_dyn_entries(), 1);
+
+        // the peer can not size the table back up past the setting
+        assert_eq!(decoder.decode(b"\x3f\x22").err(), Some(HpackError::SizeUpdateTooLarge(65).into()));
+        decoder.decode(b"\x3f\x09").unwrap();
+        assert_eq!(decoder.table.dyn_max_size(), 40);
+
+        // raising the setting does not grow the table until the peer says so
+        decoder.set_max_dynamic_table_size(4096);
+        assert_eq!(decoder.table.dyn_max_size(), 40);
+        decoder.decode(b"\x3f\xe1\x1f").unwrap();
+        assert_eq!(decoder.table.dyn_max_size(), 4096);
+    }
+
+    #[test]
+    fn decode_cow_static_no_alloc() {
+        use std::borrow::Cow;
+        use header::DecodedHeader;
+        use super::alloc_counter::allocations;
+
+        let mut decoder = Decoder::new(4096, 10);
+
+        // only the Vecs should allocate so the count does not
+        // change with the number of headers
+        let (few, headers) = allocations(|| decoder.decode_cow(&[0x82, 0x86, 0x84]).unwrap());
+        let (many, _) = allocations(|| decoder.decode_cow(&[0x82, 0x86, 0x84, 0x87, 0x85, 0x88, 0x89, 0x8a]).unwrap());
+        assert_eq!(few, many);
+        assert!(few <= 2);
+
+        assert_eq!(headers[0], DecodedHeader { name: Cow::Borrowed(":method"), value: Cow::Borrowed("GET"), sensitive: false });
+        for h in headers.iter() {
+            match (&h.name, &h.value) {
+                (&Cow::Borrowed(_), &Cow::Borrowed(_)) => {},
+                _ => panic!("static entry was copied {:?}", h),
+            }
+        }
+
+        // literals are owned, including the one shared with the dynamic table
+        let headers = decoder.decode_cow(b"\x40\x01a\x01b\x04\x01c").unwrap();
+        assert_eq!(headers[0].name, "a");
+        match headers[0].name { Cow::Owned(_) => {}, _ => panic!() }
+        match headers[1].name { Cow::Borrowed(":path") => {}, _ => panic!() }
+        match headers[1].value { Cow::Owned(_) => {}, _ => panic!() }
+    }
+
+    #[test]
+    fn decode_borrowed_literals() {
+        use std::borrow::Cow;
+
+        let mut decoder = Decoder::new(4096, 10);
+
+        // without indexing, never indexed and with indexing raw literals
+        let block = b"\x00\x0acustom-key\x0ccustom-value\x14\x06secret\x40\x01a\x01b\x82";
+        let start = block.as_ptr() as usize;
+        let in_block = |s: &Cow<str>| match *s {
+            Cow::Borrowed(b) => b.as_ptr() as usize >= start && (b.as_ptr() as usize) < start + block.len(),
+            Cow::Owned(_)    => false,
+        };
+
+        let headers = decoder.decode_borrowed(block).unwrap();
+        assert_eq!(headers.len(), 4);
+
+        assert_eq!((&*headers[0].name, &*headers[0].value), ("custom-key", "custom-value"));
+        assert!(in_block(&headers[0].name) && in_block(&headers[0].value));
+
+        // the name is from the static table
+        assert_eq!((&*headers[1].name, &*headers[1].value), (":path", "secret"));
+        assert!(headers[1].sensitive);
+        match headers[1].name { Cow::Borrowed(":path") => {}, _ => panic!() }
+        assert!(in_block(&headers[1].value));
+
+        assert_eq!((&*headers[2].name, &*headers[2].value), ("a", "b"));
+        assert!(in_block(&headers[2].name) && in_block(&headers[2].value));
+        assert_eq!((&*headers[3].name, &*headers[3].value), (":method", "GET"));
+
+        // the table got its own copy
+        assert_eq!(decoder.table.get_header_entry(62).unwrap(), ("a", "b").into());
+        let headers = decoder.decode_borrowed(b"\xbe").unwrap();
+        match (&headers[0].name, &headers[0].value) {
+            (&Cow::Owned(ref n), &Cow::Owned(ref v)) => assert_eq!((&**n, &**v), ("a", "b")),
+            _ => panic!("dynamic entry was borrowed"),
+        }
+
+        // huffman literals are owned, RFC 7541 C.4.1
+        let headers = decoder.decode_borrowed(&[0x01, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff]).unwrap();
+        assert_eq!(headers[0].value, "www.example.com");
+        match headers[0].value { Cow::Owned(_) => {}, _ => panic!() }
+    }
+
+    #[test]
+    fn decode_borrowed_errors() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        assert_eq!(decoder.decode_borrowed(b"\x82\x3f\xe1\x1f").err(), Some(HpackError::SizeUpdateNotAtStart.into()));
+        assert_eq!(decoder.decode_borrowed(b"\x80").err(), Some(HpackError::ZeroIndex.into()));
+        assert_eq!(decoder.decode_borrowed(b"\xbe").err(), Some(HpackError::InvalidIndex(62).into()));
+        assert_eq!(decoder.decode_borrowed(b"\x00\x05ab").err(), Some(HpackError::TruncatedString.into()));
+        assert!(decoder.decode_borrowed(b"\x00\x01A\x01b").is_err());
+
+        // decode_borrowed leaves the table the same as decode
+        let block = b"\x20\x40\x01a\x01b";
+        let mut other = Decoder::new(4096, 10);
+        decoder.decode_borrowed(block).unwrap();
+        other.decode(block).unwrap();
+        assert_eq!(decoder.table.dyn_size(), other.table.dyn_size());
+        assert_eq!(decoder.table.dyn_max_size(), 0);
+    }
+
+    #[test]
+    fn decode_iter_lazy() {
+        // authorization: token then incremental indexing of a: b and c: d
+        let block = b"\x0f\x08\x05token\x40\x01a\x01b\x40\x01c\x01d";
+        let mut decoder = Decoder::new(4096, 10);
+        let mut other = Decoder::new(4096, 10);
+        other.decode(block).unwrap();
+
+        {
+            let mut iter = decoder.decode_iter(block);
+            // stop after the first header, like after finding authorization
+            assert_eq!(iter.next(), Some(Ok(("authorization", "token").into())));
+        }
+        // the rest was still decoded into the table
+        assert_eq!(decoder.dump_table(), other.dump_table());
+        assert_eq!(decoder.table.num_dyn_entries(), 2);
+
+        // so the next block on the connection still lines up
+        let next = b"\xbe\xbf";
+        assert_eq!(decoder.decode(next).unwrap(), other.decode(next).unwrap());
+
+        let entries: Result<Vec<_>, _> = decoder.decode_iter(block).collect();
+        assert_eq!(entries.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn decode_iter_errors() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // a bad name is reported and the fields after it keep coming
+        let mut iter = decoder.decode_iter(b"\x82\x00\x01A\x01b\x84");
+        assert_eq!(iter.next(), Some(Ok((":method", "GET").into())));
+        assert!(match iter.next() { Some(Err(Http2Error::Hpack(HpackError::Protocol(_)))) => true, _ => false });
+        assert_eq!(iter.next(), Some(Ok((":path", "/").into())));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+        drop(iter);
+
+        // anything else ends the block
+        let mut iter = decoder.decode_iter(b"\x82\x80\x84");
+        assert_eq!(iter.next(), Some(Ok((":method", "GET").into())));
+        assert_eq!(iter.next(), Some(Err(HpackError::ZeroIndex.into())));
+        assert_eq!(iter.next(), None);
+        drop(iter);
+
+        let mut iter = decoder.decode_iter(b"\x82\x20");
+        iter.next();
+        assert_eq!(iter.next(), Some(Err(HpackError::SizeUpdateNotAtStart.into())));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn decode_arena_reset() {
+        use header::{HeaderArena, BorrowedHeader};
+        use super::alloc_counter::allocations;
+
+        // RFC 7541 C.4.1 and C.4.2 (huffman coded)
+        let block1 = b"\x82\x86\x84\x41\x8c\xf1\xe3\xc2\xe5\xf2\x3a\x6b\xa0\xab\x90\xf4\xff";
+        let block2 = b"\x82\x86\x84\xbe\x58\x86\xa8\xeb\x10\x64\x9c\xbf";
+
+        let mut decoder = Decoder::new(4096, 10);
+        let mut other = Decoder::new(4096, 10);
+        let mut arena = HeaderArena::with_capacity(256, 16);
+
+        for block in &[&block1[..], &block2[..]] {
+            arena.reset();
+            assert!(arena.is_empty());
+            let n = decoder.decode_arena(block, &mut arena).unwrap();
+            let expected: Vec<BorrowedHeader> = other.decode(block).unwrap().into_iter().map(|e| e.into()).collect();
+            assert_eq!(n, expected.len());
+            assert_eq!(arena.iter().collect::<Vec<_>>(), expected);
+            assert_eq!(decoder.dump_table(), other.dump_table());
+        }
+        assert_eq!(arena.len(), 5);
+        assert_eq!(arena.get(4).unwrap().value, "no-cache");
+        assert_eq!(arena.octets(), ":methodGET:schemehttp:path/:authoritywww.example.comcache-controlno-cache".len());
+
+        // nothing is allocated for fields that stay out of the table
+        // (indexed and a never indexed huffman literal)
+        arena.reset();
+        let block = b"\x82\xbf\x1f\x08\x86\xa8\xeb\x10\x64\x9c\xbf";
+        let (allocs, n) = allocations(|| decoder.decode_arena(block, &mut arena).unwrap());
+        assert_eq!(n, 3);
+        assert_eq!(allocs, 0);
+        let auth = arena.get(2).unwrap();
+        assert_eq!((&*auth.name, &*auth.value, auth.sensitive), ("authorization", "no-cache", true));
+
+        // same errors as decode
+        assert_eq!(decoder.decode_arena(b"\x82\x20", &mut arena).err(), Some(HpackError::SizeUpdateNotAtStart.into()));
+        assert_eq!(decoder.decode_arena(b"\x00\x05ab", &mut arena).err(), Some(HpackError::TruncatedString.into()));
+        arena.reset();
+        assert!(decoder.decode_arena(b"\x00\x01A\x01b", &mut arena).is_err());
+    }
+
+    #[test]
+    fn dynamic_table_sizes() {
+        use header::hpack::table::entry_size;
+
+        let mut decoder = Decoder::new(4096, 10);
+        assert_eq!((decoder.dynamic_table_size(), decoder.dynamic_table_max()), (0, 4096));
+
+        let mut size = 0;
+        for &(name, value) in &[("a", "b"), ("custom-key", "custom-value"), ("x", "")] {
+            let mut block = vec![0x40];
+            block.push(name.len() as u8);
+            block.extend_from_slice(name.as_bytes());
+            block.push(value.len() as u8);
+            block.extend_from_slice(value.as_bytes());
+            decoder.decode(&block).unwrap();
+
+            size += entry_size(name, value);
+            assert_eq!(decoder.dynamic_table_size(), size);
+        }
+        assert_eq!(size, 34 + 54 + 33);
+
+        // a size update evicts down to the new max
+        decoder.decode(&[0x3f, 0x3b]).unwrap();
+        assert_eq!(decoder.dynamic_table_max(), 90);
+        assert_eq!(decoder.dynamic_table_size(), 54 + 33);
+    }
+
+    #[test]
+    fn static_only() {
+        let mut decoder = Decoder::new(4096, 10);
+        decoder.set_static_only(true);
+        assert!(decoder.is_static_only());
+
+        // static indices and literals that stay out of the table
+        let block = b"\x82\x84\x0f\x08\x05token\x00\x01a\x01b\x10\x01c\x01d";
+        let entries = decoder.decode(block).unwrap();
+        assert_eq!(entries, vec![
+            (":method", "GET").into(),
+            (":path", "/").into(),
+            ("authorization", "token").into(),
+            ("a", "b").into(),
+            HeaderEntry::new("c", "d").with_sensitive(true),
+        ]);
+        assert_eq!(decoder.decode_borrowed(block).unwrap().len(), 5);
+
+        // a dynamic index, with or without anything in the table
+        assert_eq!(decoder.decode(b"\x82\xbe").err(), Some(HpackError::DynamicTableDisabled.into()));
+        assert_eq!(decoder.decode(b"\x0f\x2f\x01a").err(), Some(HpackError::DynamicTableDisabled.into()));
+        // incremental indexing and size updates
+        assert_eq!(decoder.decode(b"\x41\x01a").err(), Some(HpackError::DynamicTableDisabled.into()));
+        assert_eq!(decoder.decode(b"\x40\x01a\x01b").err(), Some(HpackError::DynamicTableDisabled.into()));
+        assert_eq!(decoder.decode(b"\x3f\xe1\x1f\x82").err(), Some(HpackError::DynamicTableDisabled.into()));
+        for block in &[&b"\xbe"[..], b"\x0f\x2f\x01a", b"\x41\x01a", b"\x3f\xe1\x1f\x82"] {
+            assert_eq!(decoder.decode_borrowed(block).err(), Some(HpackError::DynamicTableDisabled.into()));
+        }
+        // a size update to 0 (what a peer sends after a
+        // SETTINGS_HEADER_TABLE_SIZE of 0) is fine
+        assert_eq!(decoder.decode(b"\x20\x82").unwrap(), vec![(":method", "GET").into()]);
+        assert_eq!(decoder.decode_borrowed(b"\x20\x82").unwrap().len(), 1);
+        assert_eq!(decoder.dynamic_table_max(), 0);
+        // and still counts towards the limit of two
+        assert_eq!(decoder.decode(b"\x20\x20\x20\x82").err(), Some(HpackError::TooManySizeUpdates.into()));
+        assert_eq!(decoder.table.num_dyn_entries(), 0);
+        assert_eq!(HpackError::DynamicTableDisabled.error_code(), ErrorCode::CompressionError);
+
+        // the same blocks are fine once it is turned off
+        // (after the table is sized back up from 0)
+        decoder.set_static_only(false);
+        assert_eq!(decoder.decode(b"\x3f\xe1\x1f\x40\x01a\x01b\xbe").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn decode_with_len() {
+        let mut decoder = Decoder::new(4096, 10);
+
+        // a block followed by the start of the next frame
+        let input = b"\x82\x86\x84\x41\x0fwww.example.com\x00\x00\x04\x08";
+        let (entries, used) = decoder.decode_with_len(input, 20).unwrap();
+        assert_eq!(used, 20);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[3], (":authority", "www.example.com").into());
+
+        // a length that ends between representations
+        let (entries, used) = decoder.decode_with_len(b"\x82\x86\x84", 2).unwrap();
+        assert_eq!((entries.len(), used), (2, 2));
+
+        // and one that cuts a representation short
+        let e = decoder.decode_with_len(input, 10).unwrap_err();
+        assert_eq!(e, HpackError::TruncatedString.into());
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+        assert_eq!(decoder.decode_with_len(b"\xff\x80\x01", 2).err(), Some(HpackError::Integer(::header::IntegerError::Incomplete).into()));
+
+        // more octets than there are
+        let e = decoder.decode_with_len(b"\x82\x86", 3).unwrap_err();
+        assert_eq!(e, HpackError::TruncatedBlock(3).into());
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+
+        assert_eq!(decoder.decode_with_len(input, 0).unwrap(), (vec![], 0));
+    }
+
+    // all five kinds of instruction in one block, the size update
+    // has to be applied before the entries after it are added
+    #[test]
+    fn every_representation() {
+        use super::Instruction::*;
+        use header::Encoder;
+        use std::rc::Rc;
+        use std::cell::RefCell;
+
+        let block = b"\x3f\xe1\x01\x82\x44\x05/path\x40\x0acustom-key\x05value\
+            \x0f\x0b\x04gzip\x10\x06secret\x03abc\xbe\xbf";
+        let expected: Vec<HeaderEntry> = vec![
+            (":method", "GET").into(),
+            (":path", "/path").into(),
+            ("custom-key", "value").into(),
+            ("content-encoding", "gzip").into(),
+            HeaderEntry::from(("secret", "abc")).with_sensitive(true),
+            ("custom-key", "value").into(),
+            (":path", "/path").into(),
+        ];
+        let table = "\
+[ 62] (s = 47) custom-key: value
+[ 63] (s = 42) :path: /path
+      Table size: 89/256
+";
+
+        let kinds = Rc::new(RefCell::new(Vec::new()));
+        let mut decoder = Decoder::new(4096, 10);
+        {
+            let kinds = kinds.clone();
+            decoder.set_trace(move |e| kinds.borrow_mut().push(e.kind));
+        }
+        let decoded = decoder.decode(block).unwrap();
+        assert_eq!(decoded, expected);
+        assert_eq!(decoder.dump_table(), table);
+        assert_eq!(*kinds.borrow(), vec![SizeUpdate, Indexed, LiteralIncremental, LiteralIncremental,
+            LiteralWithout, LiteralNever, Indexed, Indexed]);
+
+        // the other paths agree
+        let mut borrowed = Decoder::new(4096, 10);
+        let fields = borrowed.decode_borrowed(block).unwrap();
+        assert_eq!(fields.len(), expected.len());
+        assert!(fields[4].sensitive);
+        assert_eq!(borrowed.dump_table(), table);
+
+        // and back again through an Encoder with the same table size
+        let mut encoder = Encoder::new(4096, 10);
+        encoder.set_max_dynamic_table_size(256);
+        let encoded = encoder.encode(&decoded);
+        assert_eq!(&encoded[..3], b"\x3f\xe1\x01");
+        let mut round_trip = Decoder::new(4096, 10);
+        let again = round_trip.decode(&encoded).unwrap();
+        assert_eq!(again, expected);
+        assert_eq!(round_trip.dump_table(), encoder.dump_table());
+    }
+
+    #[test]
+    fn trace_instructions() {
+        use super::Instruction::*;
+        use std::rc::Rc;
+        use std::cell::RefCell;
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut decoder = Decoder::new(4096, 10);
+        {
+            let events = events.clone();
+            decoder.set_trace(move |e| events.borrow_mut().push((e.kind, e.name.to_string(), e.value.to_string(), e.index)));
+        }
+
+        // RFC 7541 C.3.1
+        decoder.decode(b"\x82\x86\x84\x41\x0fwww.example.com").unwrap();
+        assert_eq!(*events.borrow(), vec![
+            (Indexed, ":method".to_string(), "GET".to_string(), 2),
+            (Indexed, ":scheme".to_string(), "http".to_string(), 6),
+            (Indexed, ":path".to_string(), "/".to_string(), 4),
+            (LiteralIncremental, ":authority".to_string(), "www.example.com".to_string(), 1),
+        ]);
+
+        // the other paths trace the same instructions
+        let mut borrowed_decoder = Decoder::new(4096, 10);
+        let kinds = Rc::new(RefCell::new(Vec::new()));
+        {
+            let kinds = kinds.clone();
+            borrowed_decoder.set_trace(move |e| kinds.borrow_mut().push((e.kind, e.index)));
+        }
+        let block = b"\x3f\xe1\x1f\x40\x01a\x01b\x0f\x08\x01c\x10\x01d\x01e\xbe";
+        borrowed_decoder.decode_borrowed(block).unwrap();
+        let mut arena = ::header::HeaderArena::new();
+        borrowed_decoder.decode_arena(b"\xbe", &mut arena).unwrap();
+        assert_eq!(*kinds.borrow(), vec![
+            (SizeUpdate, 4096), (LiteralIncremental, 0), (LiteralWithout, 23),
+            (LiteralNever, 0), (Indexed, 62), (Indexed, 62)]);
+
+        events.borrow_mut().clear();
+        decoder.clear_trace();
+        decoder.decode(b"\x82").unwrap();
+        assert!(events.borrow().is_empty());
+    }
+
     #[test]
     fn comp_decoder_test() {
         let mut decoder = Decoder::new(4096, 10);
@@ -360,3 +1739,141 @@ mod decoder_tests {
         assert_eq!(list.get_value_by_name("accept-language"), Some("en-US,en;q=0.8"));
     }
 }
+
+// counts heap allocations made on the current thread
+// so tests can check a path does not allocate
+#[cfg(test)]
+mod alloc_counter {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    struct Counter;
+
+    thread_local! {
+        static COUNT: Cell<usize> = Cell::new(0);
+    }
+
+    unsafe impl GlobalAlloc for Counter {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = COUNT.try_with(|c| c.set(c.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static COUNTER: Counter = Counter;
+
+    pub fn allocations<T, F: FnOnce() -> T>(f: F) -> (usize, T) {
+        let before = COUNT.with(|c| c.get());
+        let result = f();
+        (COUNT.with(|c| c.get()) - before, result)
+    }
+}
+
+// whole header block decode throughput
+//
+// run with `make bench` (needs nightly), to also see how many
+// allocations decoding each block makes:
+//
+//   rustup run nightly cargo bench --features bench decoder_benches -- --nocapture
+//
+// every iteration decodes the block with a fresh Decoder, the same
+// as the first block on a new connection
+#[cfg(all(test, feature = "bench"))]
+mod decoder_benches {
+
+    use test::{Bencher, black_box};
+
+    use header::{HeaderEntry, Encoder, IndexingPolicy};
+    use super::Decoder;
+    use super::alloc_counter::allocations;
+
+    // a browser request, mostly static table hits and a couple of cookies
+    fn browser_request() -> Vec<HeaderEntry> {
+        vec![
+            (":method", "GET").into(),
+            (":scheme", "https").into(),
+            (":authority", "www.example.com").into(),
+            (":path", "/index.html").into(),
+            ("user-agent", "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0").into(),
+            ("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8").into(),
+            ("accept-language", "en-US,en;q=0.5").into(),
+            ("accept-encoding", "gzip, deflate").into(),
+            ("cookie", "sessionid=38afes7a8; csrftoken=b1f0c2d4e5a6978877665544332211").into(),
+            ("cookie", "_ga=GA1.2.1234567890.1234567890; theme=dark").into(),
+            ("cache-control", "no-cache").into(),
+        ]
+    }
+
+    // a large response with a pile of set-cookie fields
+    fn large_response() -> Vec<HeaderEntry> {
+        let mut headers: Vec<HeaderEntry> = vec![
+            (":status", "200").into(),
+            ("content-type", "text/html; charset=utf-8").into(),
+            ("content-length", "104857").into(),
+            ("date", "Mon, 21 Oct 2013 20:13:21 GMT").into(),
+            ("server", "kurisu").into(),
+            ("cache-control", "private, max-age=0").into(),
+            ("strict-transport-security", "max-age=31536000; includeSubDomains").into(),
+        ];
+        for i in 0..20 {
+            let cookie = format!("cookie{}=a3fWa{:08x}; Max-Age=2592000; Path=/; Secure; HttpOnly", i, i * 7919);
+            headers.push(HeaderEntry::new("set-cookie", cookie));
+        }
+        headers
+    }
+
+    // nothing is in either table so every name and value is a literal
+    fn all_literal() -> Vec<HeaderEntry> {
+        (0..20).map(|i| {
+            HeaderEntry::new(format!("x-custom-field-{}", i), format!("some value that will not match {}", i))
+        }).collect()
+    }
+
+    fn bench_block(b: &mut Bencher, name: &str, block: &[u8]) {
+        let (allocs, _) = allocations(|| Decoder::new(4096, 10).decode(block).unwrap());
+        println!("{}: {} octets, {} allocations per block", name, block.len(), allocs);
+
+        b.bytes = block.len() as u64;
+        b.iter(|| {
+            let mut decoder = Decoder::new(4096, 10);
+            black_box(decoder.decode(block).unwrap())
+        });
+    }
+
+    #[bench]
+    fn decode_browser_request(b: &mut Bencher) {
+        let block = Encoder::new(4096, 10).encode(&browser_request());
+        bench_block(b, "browser request", &block);
+    }
+
+    #[bench]
+    fn decode_large_response(b: &mut Bencher) {
+        let block = Encoder::new(4096, 10).encode(&large_response());
+        bench_block(b, "large response", &block);
+    }
+
+    #[bench]
+    fn decode_all_literal(b: &mut Bencher) {
+        let mut encoder = Encoder::new(4096, 10);
+        encoder.set_indexing_policy(IndexingPolicy::Never);
+        let block = encoder.encode(&all_literal());
+        bench_block(b, "all literal", &block);
+    }
+
+    // the same blocks through decode_borrowed for comparison
+    #[bench]
+    fn decode_borrowed_browser_request(b: &mut Bencher) {
+        let block = Encoder::new(4096, 10).encode(&browser_request());
+
+        b.bytes = block.len() as u64;
+        b.iter(|| {
+            let mut decoder = Decoder::new(4096, 10);
+            black_box(decoder.decode_borrowed(&block).unwrap().len())
+        });
+    }
+}
@@ -0,0 +1,701 @@
+use super::table::{HeaderTable, TableMatch, DEFAULT_TABLE_SIZE, entry_size};
+use super::integers::{encode_integer_into, PrefixSize, PREFIX_4, PREFIX_5, PREFIX_6, PREFIX_7, MAX_INTEGER_LEN};
+use super::strings::encode_string;
+use super::huffman::Huffman;
+
+use header::*;
+use header::validate::lowercase_name;
+
+use std::cmp;
+
+/// When the Encoder is allowed to add entries to its dynamic table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexingPolicy {
+    /// literals are sent with incremental indexing
+    Default,
+    /// literals are sent without indexing so the dynamic table
+    /// stays empty, only the static table is used for lookups
+    Never,
+}
+
+/// When the Encoder uses huffman coding for string literals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuffmanPolicy {
+    /// huffman only when it makes the string shorter
+    Auto,
+    /// always huffman
+    Always,
+    /// always raw octets, eg. to not leak anything about
+    /// sensitive values through the compressed length
+    Never,
+}
+
+/// The representation (RFC 7541 section 6) a field was encoded with
+///
+/// Indexed has the index of the field, the literal forms have the
+/// index of the name or 0 when the name was sent as a literal too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    Indexed(usize),
+    LiteralIncremental(usize),
+    LiteralWithout(usize),
+    LiteralNever(usize),
+}
+
+/// The encoding side of an hpack compression context
+///
+/// The Encoder keeps its own dynamic table which mirrors the
+/// table the peer's decoder builds from what is sent, so one
+/// Encoder must be used for every block sent on a connection
+pub struct Encoder {
+    table: HeaderTable,
+    policy: IndexingPolicy,
+    huffman_policy: HuffmanPolicy,
+    // (smallest, last) max size set since the last block
+    pending_size_update: Option<(usize, usize)>,
+}
+
+impl Encoder {
+
+    // max_size should be the SETTINGS_HEADER_TABLE_SIZE of the peer
+    // the number of entries is just an assumption
+    pub fn new(max_size: usize, num_entries: usize) -> Self {
+        Encoder { table: HeaderTable::new(max_size, num_entries),
+            policy: IndexingPolicy::Default, huffman_policy: HuffmanPolicy::Auto,
+            pending_size_update: None }
+    }
+
+    // get ready for a new connection, the dynamic table is emptied
+    // (keeping its allocation) and set back to the default 4096
+    // the policies are kept
+    pub fn reset(&mut self) {
+        self.table.reset(DEFAULT_TABLE_SIZE);
+        self.pending_size_update = None;
+    }
+
+    // an Encoder that never touches the dynamic table
+    // trading compression for not having to hold any state
+    pub fn new_static_only() -> Self {
+        let mut encoder = Encoder::new(0, 0);
+        encoder.set_indexing_policy(IndexingPolicy::Never);
+        encoder
+    }
+
+    // only affects headers encoded from now on, entries that
+    // are already in the dynamic table can still be referenced
+    pub fn set_indexing_policy(&mut self, policy: IndexingPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn indexing_policy(&self) -> IndexingPolicy {
+        self.policy
+    }
+
+    pub fn set_huffman_policy(&mut self, policy: HuffmanPolicy) {
+        self.huffman_policy = policy;
+    }
+
+    pub fn huffman_policy(&self) -> HuffmanPolicy {
+        self.huffman_policy
+    }
+
+    // change the size of the dynamic table, entries are evicted
+    // right away and the next block starts with the size update
+    //
+    // when the size changes more than once between blocks the
+    // smallest size is sent first and then the last (RFC 7541 4.2)
+    //
+    // the size update is a u32 on the wire (the same as
+    // SETTINGS_HEADER_TABLE_SIZE) so anything bigger is cut down
+    // to u32::MAX, the table and the update always agree
+    pub fn set_max_dynamic_table_size(&mut self, max_size: usize) {
+        let max_size = cmp::min(max_size, u32::max_value() as usize);
+        self.table.max_size_update(max_size);
+        self.pending_size_update = match self.pending_size_update {
+            Some((smallest, _)) if smallest < max_size => Some((smallest, max_size)),
+            _ => Some((max_size, max_size)),
+        };
+    }
+
+    // see Decoder::dynamic_table_size, the two should agree
+    pub fn dynamic_table_size(&self) -> usize {
+        self.table.dyn_size()
+    }
+
+    pub fn dynamic_table_max(&self) -> usize {
+        self.table.dyn_max_size()
+    }
+
+    // see Decoder::dump_table
+    pub fn dump_table(&self) -> String {
+        self.table.dump()
+    }
+
+    /// Encode the headers into a complete header block
+    ///
+    /// The headers are kept in order
+    pub fn encode(&mut self, headers: &[HeaderEntry]) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(headers, &mut out);
+        out
+    }
+
+    /// Same as encode but append the block onto out
+    ///
+    /// Nothing already in out is touched so one buffer can be
+    /// cleared and reused for every block, returns the number
+    /// of octets appended
+    pub fn encode_into(&mut self, headers: &[HeaderEntry], out: &mut Vec<u8>) -> usize {
+        let start = out.len();
+        out.reserve(self.max_encoded_len(headers));
+        if let Some((smallest, last)) = self.pending_size_update.take() {
+            if smallest < last {
+                encode_integer_into(smallest as u32, out, PREFIX_5, 0x20);
+            }
+            encode_integer_into(last as u32, out, PREFIX_5, 0x20);
+        }
+        for header in headers {
+            self.encode_field(header, out);
+        }
+        out.len() - start
+    }
+
+    /// The most octets encoding the headers can take right now
+    ///
+    /// Every field is counted as a literal with a literal name and
+    /// the longest possible integers. Huffman is only counted when
+    /// HuffmanPolicy::Always could make a string longer
+    pub fn max_encoded_len(&self, headers: &[HeaderEntry]) -> usize {
+        let mut len = match self.pending_size_update {
+            Some(_) => 2 * MAX_INTEGER_LEN,
+            None    => 0,
+        };
+        for header in headers {
+            // name index, name length, value length
+            len += 3 * MAX_INTEGER_LEN;
+            len += self.max_str_len(header.name()) + self.max_str_len(header.value());
+        }
+        len
+    }
+
+    fn max_str_len(&self, s: &str) -> usize {
+        match self.huffman_policy {
+            HuffmanPolicy::Always => ::std::cmp::max(s.len(), Huffman::new().encoded_len(s.as_bytes())),
+            _                     => s.len(),
+        }
+    }
+
+    /// Encode one field onto the end of out with the best
+    /// representation for it, the dynamic table is updated the
+    /// same as in encode
+    ///
+    /// - indexed when the name and value are both in a table
+    /// - literal with incremental indexing otherwise, using the name index if there is one
+    /// - never indexed for sensitive headers, they do not go in the table
+    /// - without indexing instead of incremental indexing with IndexingPolicy::Never
+    ///   or when the field is bigger than the whole dynamic table
+    ///
+    /// This is what encode_into does for every field. A pending
+    /// dynamic table size update is not written, it has to start
+    /// the block so that is left to encode_into
+    pub fn encode_field(&mut self, header: &HeaderEntry, out: &mut Vec<u8>) -> Representation {
+        // names always go out lowercase whatever the application gave
+        let name = lowercase_name(header.name());
+        let value = header.value();
+        let found = self.table.find(&name, value);
+
+        if header.is_sensitive() {
+            let name_index = match found {
+                TableMatch::NameAndValue(i) | TableMatch::NameOnly(i) => i,
+                TableMatch::None => 0,
+            };
+            self.encode_literal(&name, value, name_index, PREFIX_4, 0x10, out);
+            return Representation::LiteralNever(name_index);
+        }
+
+        // a field that can not fit would only empty the table (RFC 7541 4.4)
+        let too_big = entry_size(&name, value) > self.table.dyn_max_size();
+
+        if self.policy == IndexingPolicy::Never || too_big {
+            return match found {
+                TableMatch::NameAndValue(i) => {
+                    encode_integer_into(i as u32, out, PREFIX_7, 0x80);
+                    Representation::Indexed(i)
+                },
+                TableMatch::NameOnly(i) => {
+                    self.encode_literal(&name, value, i, PREFIX_4, 0x00, out);
+                    Representation::LiteralWithout(i)
+                },
+                TableMatch::None => {
+                    self.encode_literal(&name, value, 0, PREFIX_4, 0x00, out);
+                    Representation::LiteralWithout(0)
+                },
+            };
+        }
+
+        match found {
+            TableMatch::NameAndValue(i) => {
+                encode_integer_into(i as u32, out, PREFIX_7, 0x80);
+                Representation::Indexed(i)
+            },
+            TableMatch::NameOnly(i) => {
+                self.encode_literal(&name, value, i, PREFIX_6, 0x40, out);
+                // reuse the table name instead of allocating a new one
+                let name = self.table.get_name_rc(i).expect("index came from find");
+                self.table.add_entry_literal(name, value.to_string());
+                Representation::LiteralIncremental(i)
+            },
+            TableMatch::None => {
+                self.encode_literal(&name, value, 0, PREFIX_6, 0x40, out);
+                self.table.add_entry_literal(name.into_owned(), value.to_string());
+                Representation::LiteralIncremental(0)
+            },
+        }
+    }
+
+    // write a literal representation, name_index of 0 means
+    // the name is written as a literal too
+    fn encode_literal(&self, name: &str, value: &str, name_index: usize, prefix_size: PrefixSize, flags: u8, out: &mut Vec<u8>) {
+        encode_integer_into(name_index as u32, out, prefix_size, flags);
+        if name_index == 0 {
+            self.encode_str(name, out);
+        }
+        self.encode_str(value, out);
+    }
+
+    // with HuffmanPolicy::Auto huffman is only used when
+    // it actually makes the string shorter
+    fn encode_str(&self, s: &str, out: &mut Vec<u8>) {
+        let huffman = match self.huffman_policy {
+            HuffmanPolicy::Auto   => Huffman::new().encoded_len(s.as_bytes()) < s.len(),
+            HuffmanPolicy::Always => true,
+            HuffmanPolicy::Never  => false,
+        };
+        encode_string(s.as_bytes(), huffman, out);
+    }
+}
+
+#[cfg(test)]
+mod encoder_tests {
+
+    use super::{Encoder, IndexingPolicy, HuffmanPolicy, Representation};
+    use header::{Decoder, HeaderEntry, peek_size_update};
+
+    fn request1() -> Vec<HeaderEntry> {
+        vec![
+            (":method", "GET").into(),
+            (":scheme", "http").into(),
+            (":path", "/").into(),
+            (":authority", "www.example.com").into(),
+        ]
+    }
+
+    // RFC 7541 C.4, the same requests as C.3 but with huffman coding
+    #[test]
+    fn rfc_c4_requests() {
+        let mut encoder = Encoder::new(4096, 10);
+
+        assert_eq!(encoder.encode(&request1()), vec![
+            0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff]);
+        assert_eq!(encoder.table.dyn_size(), 57);
+
+        let mut request2 = request1();
+        request2.push(("cache-control", "no-cache").into());
+        assert_eq!(encoder.encode(&request2), vec![
+            0x82, 0x86, 0x84, 0xbe, 0x58, 0x86, 0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf]);
+        assert_eq!(encoder.table.dyn_size(), 110);
+
+        let request3: Vec<HeaderEntry> = vec![
+            (":method", "GET").into(),
+            (":scheme", "https").into(),
+            (":path", "/index.html").into(),
+            (":authority", "www.example.com").into(),
+            ("custom-key", "custom-value").into(),
+        ];
+        assert_eq!(encoder.encode(&request3), vec![
+            0x82, 0x87, 0x85, 0xbf, 0x40, 0x88, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xa9, 0x7d, 0x7f,
+            0x89, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xb8, 0xe8, 0xb4, 0xbf]);
+        assert_eq!(encoder.table.dyn_size(), 164);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut encoder = Encoder::new(256, 10);
+        let mut decoder = Decoder::new(256, 10);
+
+        let mut secret: HeaderEntry = ("authorization", "secret-token").into();
+        secret.set_sensitive(true);
+        let mut cookie: HeaderEntry = ("x-session", "abc123").into();
+        cookie.set_sensitive(true);
+
+        let blocks: Vec<Vec<HeaderEntry>> = vec![
+            request1(),
+            vec![
+                (":method", "POST").into(),
+                (":path", "/upload").into(),
+                ("content-type", "application/json").into(),
+                ("x-custom", "1").into(),
+                secret,
+                cookie,
+            ],
+            vec![
+                (":method", "POST").into(),
+                (":path", "/upload").into(),
+                ("x-custom", "1").into(),
+                ("x-custom", "2").into(),
+                // enough to force evictions in a 256 octet table
+                ("x-long", "a fairly long value that takes up a lot of the table space").into(),
+                ("x-longer", "another long value which pushes the older entries out of the table").into(),
+            ],
+            request1(),
+        ];
+
+        for headers in blocks {
+            let block = encoder.encode(&headers);
+            let decoded = decoder.decode(&block).unwrap();
+            assert_eq!(decoded, headers);
+            for (d, h) in decoded.iter().zip(headers.iter()) {
+                assert_eq!(d.is_sensitive(), h.is_sensitive());
+            }
+        }
+    }
+
+    #[test]
+    fn sensitive_not_indexed() {
+        let mut encoder = Encoder::new(4096, 10);
+        let mut decoder = Decoder::new(4096, 10);
+
+        let mut entry: HeaderEntry = ("password", "secret").into();
+        entry.set_sensitive(true);
+        let block = encoder.encode(&[entry]);
+        assert_eq!(block[0], 0x10);
+        assert!(decoder.decode(&block).unwrap()[0].is_sensitive());
+        assert_eq!(encoder.table.num_dyn_entries(), 0);
+
+        // name index from the static table is still used (authorization is 23)
+        let mut entry: HeaderEntry = ("authorization", "token").into();
+        entry.set_sensitive(true);
+        let block = encoder.encode(&[entry]);
+        assert_eq!(&block[..2], &[0x1f, 0x08]);
+        assert!(decoder.decode(&block).unwrap()[0].is_sensitive());
+        assert_eq!(encoder.table.num_dyn_entries(), 0);
+    }
+
+    #[test]
+    fn static_only() {
+        let mut encoder = Encoder::new_static_only();
+        let mut decoder = Decoder::new(4096, 10);
+        assert_eq!(encoder.indexing_policy(), IndexingPolicy::Never);
+
+        let mut headers = request1();
+        for i in 0..100 {
+            headers.push((format!("x-header-{}", i), format!("value {}", i)).into());
+            headers.push(("accept-encoding", "gzip").into());
+        }
+
+        for _ in 0..2 {
+            let block = encoder.encode(&headers);
+            assert_eq!(decoder.decode(&block).unwrap(), headers);
+            assert_eq!(encoder.table.dyn_size(), 0);
+        }
+        // static entries are still indexed
+        assert_eq!(encoder.encode(&[(":method", "GET").into()]), vec![0x82]);
+    }
+
+    #[test]
+    fn switch_policy() {
+        let mut encoder = Encoder::new(4096, 10);
+
+        encoder.encode(&[("x-custom", "1").into()]);
+        assert_eq!(encoder.table.num_dyn_entries(), 1);
+
+        // already indexed entries can still be used
+        encoder.set_indexing_policy(IndexingPolicy::Never);
+        assert_eq!(encoder.encode(&[("x-custom", "1").into()]), vec![0xbe]);
+        encoder.encode(&[("x-custom", "2").into()]);
+        assert_eq!(encoder.table.num_dyn_entries(), 1);
+    }
+
+    #[test]
+    fn mixed_case_names() {
+        let mut encoder = Encoder::new(4096, 10);
+        let mut decoder = Decoder::new(4096, 10);
+
+        // matches the static entry, content-type is 31
+        let block = encoder.encode(&[("Content-Type", "text/html").into()]);
+        assert_eq!(block[0], 0x40 | 31);
+        assert_eq!(decoder.decode(&block).unwrap(), vec![("content-type", "text/html").into()] as Vec<HeaderEntry>);
+
+        // full match on the static table
+        assert_eq!(encoder.encode(&[(":Method", "GET").into()]), vec![0x82]);
+
+        // new names go out lowercase and match afterwards in either case
+        let block = encoder.encode(&[("X-Custom", "1").into()]);
+        assert_eq!(decoder.decode(&block).unwrap(), vec![("x-custom", "1").into()] as Vec<HeaderEntry>);
+        assert_eq!(encoder.encode(&[("x-CUSTOM", "1").into()]), vec![0xbe]);
+    }
+
+    #[test]
+    fn huffman_policy() {
+        let mut encoder = Encoder::new_static_only();
+        let mut decoder = Decoder::new(4096, 10);
+        assert_eq!(encoder.huffman_policy(), HuffmanPolicy::Auto);
+
+        // symbols with long huffman codes do not compress
+        // so the value goes out raw (length 6, no huffman bit)
+        let headers: Vec<HeaderEntry> = vec![("x-random", "{^}~|\\").into()];
+        let block = encoder.encode(&headers);
+        assert_eq!(&block[block.len() - 7..], b"\x06{^}~|\\");
+        assert_eq!(decoder.decode(&block).unwrap(), headers);
+
+        encoder.set_huffman_policy(HuffmanPolicy::Always);
+        let block = encoder.encode(&headers);
+        assert_eq!(block[1] & 0x80, 0x80);
+        assert_eq!(decoder.decode(&block).unwrap(), headers);
+
+        // www.example.com would normally compress
+        let headers: Vec<HeaderEntry> = vec![(":authority", "www.example.com").into()];
+        encoder.set_huffman_policy(HuffmanPolicy::Never);
+        let block = encoder.encode(&headers);
+        assert_eq!(&block[1..], b"\x0fwww.example.com");
+        assert_eq!(decoder.decode(&block).unwrap(), headers);
+    }
+
+    #[test]
+    fn dynamic_table_size_update() {
+        let mut encoder = Encoder::new(4096, 10);
+        let mut decoder = Decoder::new(4096, 10);
+
+        let block = encoder.encode(&[("x-custom", "1").into()]);
+        decoder.decode(&block).unwrap();
+
+        // clearing the table, 0 with a 5 bit prefix
+        encoder.set_max_dynamic_table_size(0);
+        assert_eq!(encoder.table.num_dyn_entries(), 0);
+        let block = encoder.encode(&[(":method", "GET").into()]);
+        assert_eq!(block, vec![0x20, 0x82]);
+        decoder.decode(&block).unwrap();
+
+        // only the next block gets the update
+        assert_eq!(encoder.encode(&[(":method", "GET").into()]), vec![0x82]);
+
+        // 1024 = 31 + 993 over two continuation octets
+        encoder.set_max_dynamic_table_size(1024);
+        let block = encoder.encode(&[]);
+        assert_eq!(block, vec![0x3f, 0xe1, 0x07]);
+        decoder.decode(&block).unwrap();
+
+        // shrink then grow, both are sent smallest first
+        encoder.set_max_dynamic_table_size(100);
+        encoder.set_max_dynamic_table_size(4096);
+        let block = encoder.encode(&[("x-custom", "1").into()]);
+        assert_eq!(&block[..5], &[0x3f, 0x45, 0x3f, 0xe1, 0x1f]);
+        assert_eq!(decoder.decode(&block).unwrap(), vec![("x-custom", "1").into()] as Vec<HeaderEntry>);
+
+        // too big for the wire, the update says what the table uses
+        encoder.set_max_dynamic_table_size(usize::max_value());
+        assert_eq!(encoder.dynamic_table_max(), u32::max_value() as usize);
+        let block = encoder.encode(&[]);
+        assert_eq!(peek_size_update(&block), Some((u32::max_value(), block.len())));
+    }
+
+    #[test]
+    fn reuse_buffer() {
+        let blocks: Vec<Vec<HeaderEntry>> = vec![
+            request1(),
+            vec![(":method", "POST").into(), ("x-custom", "1").into(), ("content-type", "text/plain").into()],
+            vec![("x-custom", "1").into(), (":authority", "www.example.com").into()],
+        ];
+
+        let mut fresh = Encoder::new(4096, 10);
+        let mut reused = Encoder::new(4096, 10);
+        let mut buf = Vec::with_capacity(64);
+
+        for headers in &blocks {
+            buf.clear();
+            let n = reused.encode_into(headers, &mut buf);
+            assert_eq!(n, buf.len());
+            assert_eq!(buf, fresh.encode(headers));
+        }
+
+        // appends after what is already there
+        let mut buf = vec![0xAA];
+        let n = Encoder::new(4096, 10).encode_into(&request1(), &mut buf);
+        assert_eq!(buf[0], 0xAA);
+        assert_eq!(&buf[1..], &Encoder::new(4096, 10).encode(&request1())[..]);
+        assert_eq!(n, buf.len() - 1);
+    }
+
+    #[test]
+    fn max_encoded_len_bound() {
+        let long = "x".repeat(300);
+        let mut secret: HeaderEntry = ("authorization", "secret-token").into();
+        secret.set_sensitive(true);
+        let blocks: Vec<Vec<HeaderEntry>> = vec![
+            vec![],
+            request1(),
+            request1(),
+            vec![(":method", "POST").into(), ("x-custom", "1").into(), ("content-type", "text/plain").into(), secret],
+            vec![(long.clone(), long.clone()).into(), ("Upper-Case", "").into()],
+            // octets that huffman codes with 20+ bits
+            vec![("x-binary", "\u{7f}\u{7f}\u{7f}\u{7f}").into(), ("x-tabs", "\t\t\t\t").into()],
+        ];
+
+        for &policy in &[HuffmanPolicy::Auto, HuffmanPolicy::Always, HuffmanPolicy::Never] {
+            let mut encoder = Encoder::new(4096, 10);
+            encoder.set_huffman_policy(policy);
+            for (i, headers) in blocks.iter().enumerate() {
+                if i == 2 {
+                    // the size updates count too
+                    encoder.set_max_dynamic_table_size(0);
+                    encoder.set_max_dynamic_table_size(4096);
+                }
+                let bound = encoder.max_encoded_len(headers);
+                let block = encoder.encode(headers);
+                assert!(bound >= block.len(), "{:?} {}: {} < {}", policy, i, bound, block.len());
+            }
+        }
+
+        // encode_into reserves it all up front
+        let mut buf = Vec::new();
+        let bound = Encoder::new(4096, 10).max_encoded_len(&request1());
+        Encoder::new(4096, 10).encode_into(&request1(), &mut buf);
+        assert!(buf.capacity() >= bound);
+    }
+
+    #[test]
+    fn dynamic_table_sizes() {
+        let mut encoder = Encoder::new(4096, 10);
+        let mut decoder = Decoder::new(4096, 10);
+        assert_eq!((encoder.dynamic_table_size(), encoder.dynamic_table_max()), (0, 4096));
+
+        // :authority is 10 + 15 + 32 and custom-key 10 + 12 + 32
+        let headers: Vec<HeaderEntry> = vec![(":authority", "www.example.com").into(), ("custom-key", "custom-value").into()];
+        decoder.decode(&encoder.encode(&headers)).unwrap();
+        assert_eq!(encoder.dynamic_table_size(), 57 + 54);
+        assert_eq!(encoder.dynamic_table_size(), decoder.dynamic_table_size());
+
+        encoder.set_max_dynamic_table_size(60);
+        assert_eq!((encoder.dynamic_table_size(), encoder.dynamic_table_max()), (54, 60));
+        decoder.decode(&encoder.encode(&[])).unwrap();
+        assert_eq!(decoder.dynamic_table_size(), 54);
+        assert_eq!(decoder.dynamic_table_max(), 60);
+    }
+
+    // a proxy decoding and encoding again must keep never indexed fields
+    // out of every dynamic table on the way
+    #[test]
+    fn proxy_keeps_never_indexed() {
+        let mut from_client = Decoder::new(4096, 10);
+        let mut to_server = Encoder::new(4096, 10);
+        let mut server = Decoder::new(4096, 10);
+
+        // authorization (23) never indexed with a raw value
+        let block = b"\x1f\x08\x0cBearer token";
+        let decoded = from_client.decode(block).unwrap();
+        assert!(decoded[0].is_sensitive());
+        assert_eq!(from_client.dump_table(), "      Table size: 0/4096\n");
+
+        // whatever the policy says
+        for policy in &[IndexingPolicy::Default, IndexingPolicy::Never] {
+            to_server.set_indexing_policy(*policy);
+            let block = to_server.encode(&decoded);
+            assert_eq!(block[0] & 0xF0, 0x10);
+            assert_eq!(to_server.table.num_dyn_entries(), 0);
+
+            let forwarded = server.decode(&block).unwrap();
+            assert_eq!(forwarded, vec![HeaderEntry::new("authorization", "Bearer token").with_sensitive(true)]);
+            assert!(forwarded[0].is_sensitive());
+            assert_eq!(server.dump_table(), "      Table size: 0/4096\n");
+        }
+
+        // the same through the owned form
+        let decoded: Vec<HeaderEntry> = from_client.decode_cow(block).unwrap().into_iter().map(|h| h.into()).collect();
+        assert!(decoded[0].is_sensitive());
+        let forwarded = server.decode(&to_server.encode(&decoded)).unwrap();
+        assert!(forwarded[0].is_sensitive());
+        assert_eq!(server.dump_table(), "      Table size: 0/4096\n");
+    }
+
+    #[test]
+    fn encode_field_representation() {
+        let mut encoder = Encoder::new(4096, 10);
+        let mut out = Vec::new();
+
+        assert_eq!(encoder.encode_field(&(":method", "GET").into(), &mut out), Representation::Indexed(2));
+        assert_eq!(out, [0x82]);
+
+        out.clear();
+        assert_eq!(encoder.encode_field(&("custom-key", "custom-value").into(), &mut out), Representation::LiteralIncremental(0));
+        assert_eq!(encoder.encode_field(&("custom-key", "custom-value").into(), &mut out), Representation::Indexed(62));
+        assert_eq!(encoder.encode_field(&(":path", "/sample/path").into(), &mut out), Representation::LiteralIncremental(4));
+
+        let secret = HeaderEntry::from(("authorization", "secret")).with_sensitive(true);
+        assert_eq!(encoder.encode_field(&secret, &mut out), Representation::LiteralNever(23));
+
+        encoder.set_indexing_policy(IndexingPolicy::Never);
+        assert_eq!(encoder.encode_field(&("other-key", "1").into(), &mut out), Representation::LiteralWithout(0));
+        assert_eq!(encoder.encode_field(&("custom-key", "2").into(), &mut out), Representation::LiteralWithout(63));
+
+        // and the peer follows along
+        let mut decoder = Decoder::new(4096, 10);
+        let decoded = decoder.decode(&out).unwrap();
+        assert_eq!(decoded.len(), 6);
+        assert_eq!(decoded[3], secret);
+    }
+
+    // the encoder table evicts the same way the peer's does, so an
+    // evicted entry is never referenced and the peer stays in sync
+    #[test]
+    fn evicted_entries_not_referenced() {
+        // room for three of the 1000 octet entries
+        let mut encoder = Encoder::new(3100, 10);
+        let mut decoder = Decoder::new(3100, 10);
+        let big = |i: usize| -> HeaderEntry { (format!("x-big-{}", i), "v".repeat(1000 - 7)).into() };
+
+        let mut out = Vec::new();
+        assert_eq!(encoder.encode_field(&("first", "1").into(), &mut out), Representation::LiteralIncremental(0));
+        assert_eq!(encoder.encode_field(&("first", "1").into(), &mut out), Representation::Indexed(62));
+        for i in 0..3 {
+            assert_eq!(encoder.encode_field(&big(i), &mut out), Representation::LiteralIncremental(0));
+        }
+        // "first" was evicted by the third big entry
+        assert_eq!(encoder.encode_field(&("first", "1").into(), &mut out), Representation::LiteralIncremental(0));
+        assert_eq!(encoder.encode_field(&big(0), &mut out), Representation::LiteralIncremental(0));
+        assert_eq!(encoder.encode_field(&big(2), &mut out), Representation::Indexed(64));
+
+        let decoded = decoder.decode(&out).unwrap();
+        assert_eq!(decoded.len(), 8);
+        assert_eq!(decoded[5], ("first", "1").into());
+        assert_eq!(decoded[7], big(2));
+        assert_eq!(decoder.dump_table(), encoder.dump_table());
+    }
+
+    #[test]
+    fn oversized_field_not_indexed() {
+        let mut encoder = Encoder::new(100, 10);
+        let mut out = Vec::new();
+
+        encoder.encode_field(&("a", "1").into(), &mut out);
+        let big: HeaderEntry = ("x-big", "v".repeat(100)).into();
+        assert_eq!(encoder.encode_field(&big, &mut out), Representation::LiteralWithout(0));
+        // the table was left alone
+        assert_eq!(encoder.table.num_dyn_entries(), 1);
+        assert_eq!(encoder.encode_field(&("a", "1").into(), &mut out), Representation::Indexed(62));
+
+        // the name can still come from a table
+        let big_date: HeaderEntry = ("date", "d".repeat(100)).into();
+        assert_eq!(encoder.encode_field(&big_date, &mut out), Representation::LiteralWithout(33));
+        // exactly the table size still goes in
+        let fits: HeaderEntry = ("b", "2".repeat(100 - 32 - 1)).into();
+        assert_eq!(encoder.encode_field(&fits, &mut out), Representation::LiteralIncremental(0));
+        assert_eq!(encoder.table.dyn_size(), 100);
+
+        // the peer ends up with the same table
+        let mut decoder = Decoder::new(100, 10);
+        let decoded = decoder.decode(&out).unwrap();
+        assert_eq!(decoded[1], big);
+        assert_eq!(decoded[3], big_date);
+        assert_eq!(decoder.dump_table(), encoder.dump_table());
+    }
+}
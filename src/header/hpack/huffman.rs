@@ -1,40 +1,161 @@
 
-use std::collections::HashMap;
-use std::slice;
-
-use bititor::BitItor;
+use std::fmt;
+use std::error::Error;
 
 // huffman layout array of (huffman code, length of code)
 type HuffmanTable = [(u32, u8)];
 
+/// Ways a huffman encoded string can be malformed
+/// (all are a COMPRESSION_ERROR per RFC 7541 5.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuffmanError {
+    /// more than 7 bits were left over after the last symbol
+    PaddingTooLong,
+    /// the left over bits were not all 1's (the EOS prefix)
+    InvalidPadding,
+    /// the full 30 bit EOS symbol was in the string
+    EosInString,
+}
+
+impl HuffmanError {
+    fn as_str(&self) -> &'static str {
+        use self::HuffmanError::*;
+        match *self {
+            PaddingTooLong  => "huffman: padding longer than 7 bits",
+            InvalidPadding  => "huffman: padding is not a prefix of EOS",
+            EosInString     => "huffman: string contains the EOS symbol",
+        }
+    }
+}
+
+impl fmt::Display for HuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Error for HuffmanError {
+    fn description(&self) -> &str {
+        self.as_str()
+    }
+}
+
 /// Decodes Huffman encoded strings
 /// Optimized specialized for http2 Huffman encoded strings
 pub struct Huffman {
-    decode_table: &'static HashMap<(u32, u8), u8>,
+    decode_table: &'static Fsm,
     encode_table: &'static HuffmanTable,
 }
 
+// one step of the decode state machine
+// taken for each nibble of input
+#[derive(Clone, Copy)]
+struct FsmStep {
+    // state after the nibble
+    next: u8,
+    // symbol completed during the nibble (codes are at least
+    // 5 bits so there is never more than one)
+    sym: u8,
+    flags: u8,
+}
+
+const FSM_EMIT: u8 = 0x1;
+// the nibble completed EOS
+const FSM_EOS: u8 = 0x2;
+
+// the decode state machine
+//
+// every state is an internal node of the code tree (256 of them),
+// the root is state 0. Each state has a step for all 16 nibbles
+struct Fsm {
+    steps: Vec<[FsmStep; 16]>,
+    // depth in the tree and if the path from the root is all 1's
+    // used to check the padding once the input runs out
+    states: Vec<(u8, bool)>,
+}
+
 lazy_static! {
-    static ref D_TABLE: HashMap<(u32, u8), u8> = {
-        let len = HUFFMAN_TABLE.len();
+    // generated once from HUFFMAN_TABLE, which stays the only
+    // place the codes are written down
+    static ref D_TABLE: Fsm = build_fsm(HUFFMAN_TABLE);
+}
+
+// expand the (code, length) pairs of RFC 7541 Appendix B
+// into the nibble at a time decode state machine
+//
+// the encoder uses the pairs as they are, indexed by symbol
+fn build_fsm(table: &HuffmanTable) -> Fsm {
+    #[derive(Clone, Copy)]
+    enum Child {
+        Empty,
+        Node(usize),
+        Leaf(u16),
+    }
 
-        let mut hash_map = HashMap::with_capacity(len);
+    // build the code tree from the code table
+    let mut nodes: Vec<[Child; 2]> = vec![[Child::Empty; 2]];
+    let mut states: Vec<(u8, bool)> = vec![(0, true)];
+
+    for (sym, &(code, len)) in table.iter().enumerate() {
+        let mut cur = 0;
+        for i in (0..len).rev() {
+            let bit = ((code >> i) & 1) as usize;
+            if i == 0 {
+                nodes[cur][bit] = Child::Leaf(sym as u16);
+                break;
+            }
+            cur = match nodes[cur][bit] {
+                Child::Node(n) => n,
+                _ => {
+                    let (depth, ones) = states[cur];
+                    nodes.push([Child::Empty; 2]);
+                    states.push((depth + 1, ones && bit == 1));
+                    let n = nodes.len() - 1;
+                    nodes[cur][bit] = Child::Node(n);
+                    n
+                },
+            };
+        }
+    }
 
-        for i in 0..len {
-            hash_map.insert(HUFFMAN_TABLE[i], i as u8);
+    debug_assert_eq!(nodes.len(), 256);
+
+    // walk every nibble from every state
+    let empty = FsmStep { next: 0, sym: 0, flags: 0 };
+    let mut steps = vec![[empty; 16]; nodes.len()];
+
+    for state in 0..nodes.len() {
+        for nibble in 0..16 {
+            let mut step = empty;
+            let mut cur = state;
+            for i in (0..4).rev() {
+                let bit = (nibble >> i) & 1;
+                match nodes[cur][bit] {
+                    Child::Node(n) => cur = n,
+                    // the code is complete so Empty is never reached
+                    Child::Leaf(256) | Child::Empty => {
+                        step.flags |= FSM_EOS;
+                        cur = 0;
+                    },
+                    Child::Leaf(sym) => {
+                        step.sym = sym as u8;
+                        step.flags |= FSM_EMIT;
+                        cur = 0;
+                    },
+                }
+            }
+            step.next = cur as u8;
+            steps[state][nibble] = step;
         }
+    }
 
-        drun!({ // checking the memory efficiency of the huffman encoder/decoder
-            use std::mem;
-            println!("huffman static HUFFMAN_TABLE len: {}", len);
-            println!("huffman HUFFMAN_TABLE hasmap:\nmain size bytes {} :: Table size bytes {} :: Capacity {}",
-                     mem::size_of::<Huffman>(),
-                     mem::size_of_val(&hash_map.entry((0x1ff8, 13))) * hash_map.capacity(),
-                     hash_map.capacity());
-        });
-
-        hash_map
-    };
+    drun!({ // checking the memory efficiency of the huffman decoder
+        use std::mem;
+        println!("huffman decode fsm: {} states :: table size bytes {}",
+                 steps.len(), steps.len() * mem::size_of::<[FsmStep; 16]>());
+    });
+
+    Fsm { steps: steps, states: states }
 }
 
 impl Huffman {
@@ -45,50 +166,22 @@ impl Huffman {
         }
     }
 
-    pub fn decode<'a, 'b, B: IntoIterator<Item=&'b u8>>(&self, buf: B) -> Vec<u8>
+    // decode the full buf
+    //
+    // up to 7 bits of padding are allowed after the last symbol,
+    // and they must be the most significant bits of EOS (all 1's)
+    pub fn decode<'a, 'b, B: IntoIterator<Item=&'b u8>>(&self, buf: B) -> Result<Vec<u8>, HuffmanError>
         where <B as ::std::iter::IntoIterator>::IntoIter: 'a {
         // create vec with enough space for most of the decoded buf
         // some reallocation will probably happen with current implementation
 
-        let mut bts = buf.into_iter();
+        let bts = buf.into_iter();
 
         //let bts: &mut B::IntoIter = &mut itr;
 
         let decode_size: usize = f32::ceil(bts.size_hint().0 as f32 * 1.5) as usize;
         let mut decoded = Vec::with_capacity(decode_size);
-
-        // drun!{{
-        //     println!("pre fill capacity: {}", decoded.capacity());
-        //     for b in buf {
-        //         print!("{:02X}", b);
-        //     }
-        //     println!("");
-        // }}
-
-        let bits = BitItor::new(&mut bts);
-
-        // the encoded bits
-        let mut code = 0u32;
-        // the number of encoded bits
-        let mut size = 0u8;
-        for bit in bits {
-            // get the bit
-            code <<= 1;
-            if bit {
-                code |= 0x1;
-            }
-            size += 1;
-
-            // check if the curently read bits are a valid huffman code
-            match self.decode_table.get(&(code, size)) {
-                Some(val)   => {
-                    decoded.push(*val);
-                    code = 0;
-                    size = 0;
-                },
-                None        => {},
-            }
-        }
+        try!(self.decode_into(bts, &mut decoded));
 
         drun!( {
             let len = decoded.len();
@@ -98,87 +191,100 @@ impl Huffman {
             println!("len capacity ratio: {}", len as f32 / cap as f32);
         } );
 
-        decoded
+        Ok(decoded)
     }
 
-    // write the encoded result to dest and return the length of result
-    pub fn encode(&self, src: &[u8], dest: &mut [u8]) -> usize {
-        let mut dest_i = 0; // byte index
-        let mut offset = 0;
-
-        for i in src { // for each char in src as index
-            let mut code_i = 0;
+    // same as decode but appends to decoded, returns the number
+    // of octets written. On an error some octets may already be there
+    pub fn decode_into<'b, B: IntoIterator<Item=&'b u8>>(&self, buf: B, decoded: &mut Vec<u8>) -> Result<usize, HuffmanError> {
+        let start = decoded.len();
+        let bts = buf.into_iter();
 
-            let encodeing = self.encode_table[*i as usize];
-            let mut code = encodeing.0;
-            let mut code_len = encodeing.1 as i8;
-
-            let shift = 32 - code_len - offset;
-
-            code = code << shift;
+        // drun!{{
+        //     println!("pre fill capacity: {}", decoded.capacity());
+        //     for b in buf {
+        //         print!("{:02X}", b);
+        //     }
+        //     println!("");
+        // }}
 
-            let be_code = u32::to_be(code);
+        let fsm: &Fsm = self.decode_table;
+
+        // feed the machine one nibble at a time
+        let mut state = 0;
+        for byte in bts {
+            for nibble in &[byte >> 4, byte & 0xF] {
+                let step = fsm.steps[state][*nibble as usize];
+                if step.flags & FSM_EOS != 0 {
+                    // EOS can not be part of a string, this is not
+                    // just too much padding (which stops short of EOS)
+                    return Err(HuffmanError::EosInString);
+                }
+                if step.flags & FSM_EMIT != 0 {
+                    decoded.push(step.sym);
+                }
+                state = step.next as usize;
+            }
+        }
 
-            let code_buf: &[u8] = unsafe { slice::from_raw_parts(&be_code as *const u32 as *const u8, 4) };
+        // what is left must be valid padding
+        let (depth, all_ones) = fsm.states[state];
+        if depth > 7 {
+            return Err(HuffmanError::PaddingTooLong);
+        }
+        if !all_ones {
+            return Err(HuffmanError::InvalidPadding);
+        }
 
-            debug_assert!(offset >= 0);
-            // this deal with what happens when a dest byte is
-            // only partial filled by the previous huff code
-            // because the codes need to be tightly packed
-            if offset > 0 {
-                dest[dest_i] |= code_buf[code_i];
+        Ok(decoded.len() - start)
+    }
 
-                let t_offset = code_len + offset;
-                if t_offset < 8 {
-                    offset = t_offset;
-                    continue;
-                }
+    // encode src into a new buffer with the codes packed MSB first
+    // and the last octet padded with 1's (the start of EOS)
+    pub fn encode(&self, src: &[u8]) -> Vec<u8> {
+        let mut dest = Vec::with_capacity(self.encoded_len(src));
+        self.encode_into(src, &mut dest);
+        dest
+    }
 
-                dest_i += 1;
-                code_len -= 8 - offset;
-                if code_len <= 0 {
-                    offset = code_len;
-                    continue;
-                }
+    // same as encode but appends to dest,
+    // returns the number of octets written
+    pub fn encode_into(&self, src: &[u8], dest: &mut Vec<u8>) -> usize {
+        let start = dest.len();
 
-                code_i += 1;
-                offset = 0;
-            }
+        // bits waiting to be written, the low `pending` bits are used
+        // (the longest code is 30 bits so 64 never overflows)
+        let mut bits = 0u64;
+        let mut pending = 0u8;
 
-            // deal with writing each part of the code to dest
-            loop {
-                dest[dest_i] = code_buf[code_i];
+        for i in src {
+            let (code, code_len) = self.encode_table[*i as usize];
 
-                code_len -= 8;
-                if code_len > 0 {
-                    dest_i += 1;
-                }
-                else if code_len == 0 {
-                    dest_i += 1;
-                    break;
-                }
-                else {
-                    offset = 8 + code_len;
-                    break;
-                }
+            bits = (bits << code_len) | code as u64;
+            pending += code_len;
 
-                code_i += 1;
+            while pending >= 8 {
+                pending -= 8;
+                dest.push((bits >> pending) as u8);
             }
         }
 
         // write the 1's that "pad" the last dest byte if it
         // is not completely filled
-        if offset != 0 {
-            let end_bits = 8 - offset;
-            debug_assert!(end_bits < 8 && end_bits > 0);
-            let mut bits = 0;
-            for _ in 0..end_bits {
-                bits = ( bits << 1) | 1;
-            }
-            dest[dest_i] |= bits;
+        if pending > 0 {
+            let end_bits = 8 - pending;
+            let last = (bits << end_bits) as u8 | ((1u8 << end_bits) - 1);
+            dest.push(last);
         }
 
-        dest_i + 1
+        dest.len() - start
+    }
+
+    // the number of octets src will take once encoded
+    // useful to check if encoding is worth it before doing it
+    pub fn encoded_len(&self, src: &[u8]) -> usize {
+        let bits: usize = src.iter().map(|i| self.encode_table[*i as usize].1 as usize).sum();
+        (bits + 7) / 8
     }
 }
 
@@ -445,7 +551,7 @@ static HUFFMAN_TABLE: &'static HuffmanTable = &[
 
 #[cfg(test)]
 mod huffman_tests {
-    use super::Huffman;
+    use super::{Huffman, HuffmanError};
     use std::str;
 
     #[test]
@@ -453,7 +559,7 @@ mod huffman_tests {
         let encoded = [0x08, 0x9D, 0x5C, 0x0B, 0x81, 0x70, 0xDC, 0x78, 0x0F, 0x03];
 
         let huff = Huffman::new();
-        let decoded = huff.decode(&encoded);
+        let decoded = huff.decode(&encoded).unwrap();
 
         println!("decoded value: {}", str::from_utf8(&decoded).unwrap());
 
@@ -465,7 +571,7 @@ mod huffman_tests {
         let encoded = [0xA0, 0xE4, 0x1D, 0x13, 0x9D, 0x09, 0xB8, 0xF0, 0x1E, 0x07];
 
         let huff = Huffman::new();
-        let decoded = huff.decode(&encoded);
+        let decoded = huff.decode(&encoded).unwrap();
 
         println!("decoded value: {}", str::from_utf8(&decoded).unwrap());
 
@@ -473,53 +579,193 @@ mod huffman_tests {
     }
 
     #[test]
-    fn encode_test() {
-        let mut v = Vec::with_capacity(20);
-        unsafe { v.set_len(20) };
+    fn decode_rfc_example() {
+        // RFC 7541 C.4.1
+        let encoded = [0xF1, 0xE3, 0xC2, 0xE5, 0xF2, 0x3A, 0x6B, 0xA0, 0xAB, 0x90, 0xF4, 0xFF];
 
-        let s = b"localhost:8080";
+        let huff = Huffman::new();
+        assert_eq!(huff.decode(&encoded).unwrap(), b"www.example.com");
+    }
+
+    #[test]
+    fn decode_padding() {
+        let huff = Huffman::new();
+
+        // 'a' is 00011 followed by 3 bits of valid padding
+        assert_eq!(huff.decode(&[0x1F]).unwrap(), b"a");
+
+        // padding of 0's
+        assert_eq!(huff.decode(&[0x18]), Err(HuffmanError::InvalidPadding));
+
+        // a full extra octet of 1's is to much padding
+        assert_eq!(huff.decode(&[0x1F, 0xFF]), Err(HuffmanError::PaddingTooLong));
+        assert_eq!(huff.decode(&[0xFF, 0xFF, 0xFF]), Err(HuffmanError::PaddingTooLong));
+        // 30 1's is EOS
+        assert_eq!(huff.decode(&[0xFF; 40]), Err(HuffmanError::EosInString));
+    }
+
+    #[test]
+    fn decode_eos() {
+        let huff = Huffman::new();
+
+        // 29 1's and a 0 is a (30 bit) symbol and not EOS,
+        // it is only the 0's left over that are wrong
+        assert_eq!(huff.decode(&[0xFF, 0xFF, 0xFF, 0xF8]), Err(HuffmanError::InvalidPadding));
 
-        encode(s, &mut v);
+        // 'a' (00011) then EOS then 5 bits of padding
+        assert_eq!(huff.decode(&[0x1F, 0xFF, 0xFF, 0xFF, 0xFF]), Err(HuffmanError::EosInString));
+        // EOS then 'a' then 5 bits of padding
+        assert_eq!(huff.decode(&[0xFF, 0xFF, 0xFF, 0xFC, 0x7F]), Err(HuffmanError::EosInString));
 
+        // which is a COMPRESSION_ERROR
+        use header::HpackError;
+        use frame::ErrorCode;
+        let e: HpackError = HuffmanError::EosInString.into();
+        assert_eq!(e.error_code(), ErrorCode::CompressionError);
+    }
+
+    #[test]
+    fn encode_test() {
+        let huff = Huffman::new();
+
+        let s = b"localhost:8080";
         let encoded = [0xA0, 0xE4, 0x1D, 0x13, 0x9D, 0x09, 0xB8, 0xF0, 0x1E, 0x07];
-        assert_eq!(encoded, v[..]);
+        assert_eq!(huff.encode(s), encoded);
+        assert_eq!(huff.encoded_len(s), encoded.len());
 
         // longer test string
         let s = b"Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/53.0.2785.116 Safari/537.36";
 
-        let mut v = Vec::with_capacity(100);
-        unsafe { v.set_len(100) };
-        encode(s, &mut v);
-
         let encoded = [0xD0, 0x7F, 0x66, 0xA2, 0x81, 0xB0, 0xDA, 0xE0, 0x53, 0xFA, 0xFC, 0x08, 0x7E, 0xD4, 0xCE, 0x6A, 0xAD, 0xF2, 0xA7, 0x97, 0x9C, 0x89, 0xC6, 0xBF, 0xB5, 0x21, 0xAE, 0xBA, 0x0B, 0xC8, 0xB1, 0xE6, 0x32, 0x58, 0x6D, 0x97, 0x57, 0x65, 0xC5, 0x3F, 0xAC, 0xD8, 0xF7, 0xE8, 0xCF, 0xF4, 0xA5, 0x06, 0xEA, 0x55, 0x31, 0x14, 0x9D, 0x4F, 0xFD, 0xA9, 0x7A, 0x7B, 0x0F, 0x49, 0x58, 0x6D, 0x95, 0xC0, 0xB8, 0x9D, 0x79, 0xB5, 0xC2, 0x17, 0x14, 0xDC, 0x39, 0x47, 0x61, 0x98, 0x6D, 0x97, 0x57, 0x65, 0xCF];
 
-        for (x, y) in v.iter().zip(encoded.iter()) {
-            assert_eq!(x, y);
-        }
+        assert_eq!(huff.encode(s)[..], encoded[..]);
 
         // another test string
         let s = b"text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8";
 
-        let mut v = Vec::with_capacity(100);
-        unsafe { v.set_len(100) };
-        encode(s, &mut v);
-
         let encoded = [0x49, 0x7C, 0xA5, 0x89, 0xD3, 0x4D, 0x1F, 0x43, 0xAE, 0xBA, 0x0C, 0x41, 0xA4, 0xC7, 0xA9, 0x8F, 0x33, 0xA6, 0x9A, 0x3F, 0xDF, 0x9A, 0x68, 0xFA, 0x1D, 0x75, 0xD0, 0x62, 0x0D, 0x26, 0x3D, 0x4C, 0x79, 0xA6, 0x8F, 0xBE, 0xD0, 0x01, 0x77, 0xFE, 0x8D, 0x48, 0xE6, 0x2B, 0x1E, 0x0B, 0x1D, 0x7F, 0x5F, 0x2C, 0x7C, 0xFD, 0xF6, 0x80, 0x0B, 0xBD];
 
-        for (x, y) in v.iter().zip(encoded.iter()) {
-            assert_eq!(x, y);
+        assert_eq!(huff.encode(s)[..], encoded[..]);
+
+        // RFC 7541 C.4.1
+        let encoded = [0xF1, 0xE3, 0xC2, 0xE5, 0xF2, 0x3A, 0x6B, 0xA0, 0xAB, 0x90, 0xF4, 0xFF];
+        assert_eq!(huff.encode(b"www.example.com"), encoded);
+    }
+
+    #[test]
+    fn encode_into_appends() {
+        let huff = Huffman::new();
+
+        let mut dest = vec![0x01];
+        let n = huff.encode_into(b"a", &mut dest);
+        assert_eq!(n, 1);
+        assert_eq!(dest, vec![0x01, 0x1F]);
+
+        assert_eq!(huff.encode(b""), vec![]);
+    }
+
+    #[test]
+    fn round_trip_every_symbol() {
+        use super::HUFFMAN_TABLE;
+
+        // 256 symbols + EOS
+        assert_eq!(HUFFMAN_TABLE.len(), 257);
+
+        let huff = Huffman::new();
+        for sym in 0..256 {
+            let src = [sym as u8];
+            let encoded = huff.encode(&src);
+            assert_eq!(encoded.len(), (HUFFMAN_TABLE[sym].1 as usize + 7) / 8);
+            assert_eq!(huff.decode(&encoded).unwrap(), src);
         }
+
+        // EOS itself never decodes
+        assert_eq!(huff.decode(&[0xFF, 0xFF, 0xFF, 0xFF]), Err(HuffmanError::EosInString));
     }
 
-    fn encode(src: &[u8], dest: &mut Vec<u8>) {
+    #[test]
+    fn round_trip_random() {
         let huff = Huffman::new();
-        let size = huff.encode(src, dest);
 
-        unsafe { dest.set_len(size) };
+        // small xorshift so the test is repeatable
+        let mut state = 0x2545F491u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for _ in 0..200 {
+            let len = (next() % 64) as usize;
+            let src: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+
+            let encoded = huff.encode(&src);
+            assert_eq!(encoded.len(), huff.encoded_len(&src));
+            assert_eq!(huff.decode(&encoded).unwrap(), src);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod huffman_benches {
 
-        for b in dest {
-            print!("{:02X}", b);
+    use test::Bencher;
+    use std::collections::HashMap;
+
+    use bititor::BitItor;
+    use super::{Huffman, HUFFMAN_TABLE};
+
+    // the old bit at a time decoder for comparison
+    fn decode_bitwise(table: &HashMap<(u32, u8), u8>, buf: &[u8]) -> Vec<u8> {
+        let mut bts = buf.iter();
+        let bits = BitItor::new(&mut bts);
+        let mut decoded = Vec::with_capacity(buf.len() * 3 / 2);
+
+        let mut code = 0u32;
+        let mut size = 0u8;
+        for bit in bits {
+            code <<= 1;
+            if bit {
+                code |= 0x1;
+            }
+            size += 1;
+
+            if let Some(val) = table.get(&(code, size)) {
+                decoded.push(*val);
+                code = 0;
+                size = 0;
+            }
+        }
+        decoded
+    }
+
+    // 4KB cookie like value
+    fn cookie() -> Vec<u8> {
+        let chars = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789=;-_%";
+        (0..4096).map(|i| chars[(i * 7 + i / 3) % chars.len()]).collect()
+    }
+
+    #[bench]
+    fn decode_cookie_bitwise(b: &mut Bencher) {
+        let huff = Huffman::new();
+        let encoded = huff.encode(&cookie());
+
+        let mut table = HashMap::with_capacity(256);
+        for i in 0..256 {
+            table.insert(HUFFMAN_TABLE[i], i as u8);
         }
-        println!("");
+
+        b.bytes = encoded.len() as u64;
+        b.iter(|| decode_bitwise(&table, &encoded));
+    }
+
+    #[bench]
+    fn decode_cookie_fsm(b: &mut Bencher) {
+        let huff = Huffman::new();
+        let encoded = huff.encode(&cookie());
+
+        b.bytes = encoded.len() as u64;
+        b.iter(|| huff.decode(&encoded).unwrap());
     }
 }
@@ -30,120 +30,529 @@
 /// The prefix size, N, is always between 1 and 8 bits. An integer starting at an octet boundary will have an 8-bit prefix.
 ///
 
-// pub fn decode_integer<'a, B: IntoIterator<Item=&'a u8>>(bts: B, prefix_size: u8) -> Result<u32, &'static str> {
-pub fn decode_integer<'a, 'b, I: Iterator<Item=&'b u8>>(bts: &'a mut I, prefix_size: u8) -> Result<u32, &'static str> {
-    use std::num::Wrapping;
-
-    if prefix_size < 1 || prefix_size > 8 {
-        return Err("hpack integer: invalid prefix");
-    }
-    // if bts.peek().is_none() {
-    //     return Err("hpack integer: not enough octets (0)");
-    // }
-
-    // Make sure there's no overflow in the shift operation
-    let Wrapping(mask) = if prefix_size == 8 {
-        Wrapping(0xFFu8)
-    } else {
-        Wrapping(1u8 << prefix_size) - Wrapping(1)
+// Only core is used in here (Error is left out with the no_std
+// feature) so the integer codec works without std or allocation,
+// encode_integer_into takes anything that can be extended with octets
+//
+// no_std/ builds this file in a #![no_std] crate, keep it building there
+
+use core::fmt;
+#[cfg(not(feature = "no_std"))]
+use std::error::Error;
+
+/// The ways decoding an hpack integer can fail
+///
+/// Callers that read from a stream should match on `Incomplete`
+/// since it only means more octets are needed, every other variant
+/// is a hard error (COMPRESSION_ERROR when it comes from the peer)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerError {
+    /// ran out of octets before the integer was terminated,
+    /// buffer and retry when more data arrives
+    Incomplete,
+    /// the encoding used more octets than any valid integer needs
+    TooManyOctets,
+    /// the encoded value does not fit in a u32 (u64 for the _u64 functions)
+    Overflow,
+    /// the destination buffer ran out of octets while encoding
+    OutOfSpace,
+}
+
+impl IntegerError {
+    fn as_str(&self) -> &'static str {
+        use self::IntegerError::*;
+        match *self {
+            Incomplete      => "hpack integer: not enough octets",
+            TooManyOctets   => "hpack integer: too many octets",
+            Overflow        => "hpack integer: value overflows the integer type",
+            OutOfSpace      => "hpack integer: not enough space to encode",
+        }
+    }
+}
+
+impl fmt::Display for IntegerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl Error for IntegerError {
+    fn description(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// The most octets any u32 takes encoded, with any prefix size
+/// (the prefix octet and then 7 bits in each of the next 5)
+pub const MAX_INTEGER_LEN: usize = 6;
+
+/// Same as MAX_INTEGER_LEN for a u64 (the prefix octet and 10 more)
+pub const MAX_INTEGER_LEN_U64: usize = 11;
+
+// the unsigned types the codec is written for, hpack only needs u32
+// but the same encoding is handy for larger values outside of hpack
+//
+// just the few operations the algorithm uses so the decoding and
+// encoding is written once for every width
+trait CodecInt: Copy + PartialOrd {
+    // continuation octets it takes to carry every bit of the type
+    const OCTET_LIMIT: usize;
+
+    fn from_octet(b: u8) -> Self;
+    // self + (part << 7 * i), None on overflow
+    fn add_part(self, part: u8, i: usize) -> Option<Self>;
+    // the low octet
+    fn to_octet(self) -> u8;
+    fn shift_out(self) -> Self;
+    fn sub_octet(self, b: u8) -> Self;
+}
+
+macro_rules! codec_int {
+    ($int:ty, $octet_limit:expr) => {
+        impl CodecInt for $int {
+            const OCTET_LIMIT: usize = $octet_limit;
+
+            fn from_octet(b: u8) -> Self {
+                b as $int
+            }
+
+            fn add_part(self, part: u8, i: usize) -> Option<Self> {
+                (part as $int).checked_mul(1 << (7 * i)).and_then(|part| self.checked_add(part))
+            }
+
+            fn to_octet(self) -> u8 {
+                self as u8
+            }
+
+            fn shift_out(self) -> Self {
+                self >> 7
+            }
+
+            fn sub_octet(self, b: u8) -> Self {
+                self - b as $int
+            }
+        }
+    }
+}
+
+// 5 * 7 = 35 and 10 * 7 = 70 bits
+codec_int!(u32, 5);
+codec_int!(u64, 10);
+
+/// The number of bits of the first octet an integer uses (N above)
+///
+/// Only 1..=8 can be made so the codec never has to check it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixSize(u8);
+
+// the prefix sizes hpack uses
+pub const PREFIX_4: PrefixSize = PrefixSize(4);
+pub const PREFIX_5: PrefixSize = PrefixSize(5);
+pub const PREFIX_6: PrefixSize = PrefixSize(6);
+pub const PREFIX_7: PrefixSize = PrefixSize(7);
+pub const PREFIX_8: PrefixSize = PrefixSize(8);
+
+impl PrefixSize {
+    // None unless 1 <= bits <= 8
+    pub const fn new(bits: u8) -> Option<PrefixSize> {
+        if bits >= 1 && bits <= 8 {
+            Some(PrefixSize(bits))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    // all prefix bits set, 2^N - 1
+    fn mask(&self) -> u8 {
+        (0xFFu16 >> (8 - self.0)) as u8
+    }
+}
+
+pub fn decode_integer<'a, 'b, I: Iterator<Item=&'b u8>>(bts: &'a mut I, prefix_size: PrefixSize) -> Result<u32, IntegerError> {
+    match try!(IntegerDecoder::new(prefix_size).feed(bts)) {
+        IntegerPoll::Ready(value)   => Ok(value),
+        // If we have reached here, it means the buffer has been exhausted without
+        // hitting the termination condition.
+        IntegerPoll::Pending        => Err(IntegerError::Incomplete),
+    }
+}
+
+// same as decode_integer but also gives the number of octets
+// the integer took up, for when bts is not kept around to
+// find where the next field starts
+pub fn decode_integer_counted<'a, 'b, I: Iterator<Item=&'b u8>>(bts: &'a mut I, prefix_size: PrefixSize) -> Result<(u32, usize), IntegerError> {
+    let mut used = 0;
+    let value = {
+        let mut counted = bts.inspect(|_| used += 1);
+        try!(decode_integer(&mut counted, prefix_size))
     };
+    Ok((value, used))
+}
 
-    let tv = bts.next();
+// slice form of decode_integer, gives back the value and
+// what is left of input after the integer
+pub fn decode_integer_slice(input: &[u8], prefix_size: PrefixSize) -> Result<(u32, &[u8]), IntegerError> {
+    let mut bts = input.iter();
+    let value = try!(decode_integer(&mut bts, prefix_size));
+    Ok((value, bts.as_slice()))
+}
 
-    if tv.is_none() { return Err("hpack integer: not enough octets (0)"); }
+/// Result of feeding octets to an IntegerDecoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerPoll {
+    Ready(u32),
+    /// every octet given was used and the integer is not done yet
+    Pending,
+}
 
-    let mut value = (tv.unwrap() & mask) as u32;
+/// Decodes one integer from octets that may arrive in pieces
+///
+/// The partial value is kept between calls to feed so an integer
+/// split across two reads does not have to be buffered up first.
+/// After Ready is returned the decoder starts over on a new integer
+pub struct IntegerDecoder {
+    prefix_size: PrefixSize,
+    value: u32,
+    // number of continuation octets read, None until
+    // the prefix octet has been read
+    octets: Option<usize>,
+}
 
-    // if there is only one octet in the encodeing
-    if value < mask as u32 {
-        // Value fits in the prefix bits.
-        return Ok(value);
+impl IntegerDecoder {
+    pub fn new(prefix_size: PrefixSize) -> Self {
+        IntegerDecoder { prefix_size: prefix_size, value: 0, octets: None }
     }
 
-    // The value does not fit into the prefix bits, so we read as many following
-    // bytes as necessary to decode the integer.
-    // Already one byte used (the prefix)
-    let mut m = 0;
-    // The octet limit is chosen such that the maximum allowed *value* can
-    // never overflow an unsigned 32-bit integer. The maximum value of any
-    // integer that can be encoded with 5 octets is ~2^28
-    let octet_limit = 5;
+    // read octets until the integer is done or bts runs out
+    // octets after the end of the integer are left in bts
+    pub fn feed<'a, 'b, I: Iterator<Item=&'b u8>>(&mut self, bts: &'a mut I) -> Result<IntegerPoll, IntegerError> {
+        match try!(feed_octets(self.prefix_size, &mut self.value, &mut self.octets, bts)) {
+            Some(value) => {
+                self.value = 0;
+                Ok(IntegerPoll::Ready(value))
+            },
+            None        => Ok(IntegerPoll::Pending),
+        }
+    }
+}
 
-    for (i, b) in bts.enumerate() {
-        value += ((b & 127) as u32) * (1 << m);
-        m += 7;
+// the decoding for every width, value and octets hold the
+// partial integer between calls (octets is None before the
+// prefix octet and again once the integer is done)
+//
+// returns None if bts ran out first
+fn feed_octets<'a, 'b, T: CodecInt, I: Iterator<Item=&'b u8>>(prefix_size: PrefixSize, value: &mut T, octets: &mut Option<usize>, bts: &'a mut I) -> Result<Option<T>, IntegerError> {
+    if octets.is_none() {
+        let mask = prefix_size.mask();
 
-        if b & 128 != 128 {
-            // Most significant bit is not set => no more continuation bytes
-            return Ok(value);
+        let first = match bts.next() {
+            Some(b) => b & mask,
+            None    => return Ok(None),
+        };
+
+        // if there is only one octet in the encodeing
+        if first < mask {
+            // Value fits in the prefix bits.
+            return Ok(Some(T::from_octet(first)));
         }
+        *value = T::from_octet(first);
+        *octets = Some(0);
+    }
+
+    // The value does not fit into the prefix bits, so we read as many following
+    // bytes as necessary to decode the integer.
+    //
+    // The octet limit is the number of continuation octets needed to
+    // carry every bit of T (5 * 7 = 35 for a u32), anything longer can not
+    // be valid. Values that still don't fit are caught by the checked math
+    let mut i = octets.unwrap();
 
-        if i == octet_limit {
+    for b in bts {
+        if i == T::OCTET_LIMIT {
             // The spec tells us that we MUST treat situations where the
             // encoded representation is too long (in octets) as an error.
-            return Err("hpack integer: to many octets");
+            return Err(IntegerError::TooManyOctets);
         }
+
+        *value = try!(value.add_part(b & 127, i).ok_or(IntegerError::Overflow));
+
+        if b & 128 != 128 {
+            // Most significant bit is not set => no more continuation bytes
+            //
+            // a last octet of 0 after other continuation octets adds nothing
+            // to the value, so the encoding is padded out and not minimal.
+            // (the first continuation octet can be 0 when value == 2^N-1)
+            if *b == 0 && i > 0 {
+                return Err(IntegerError::TooManyOctets);
+            }
+            *octets = None;
+            return Ok(Some(*value));
+        }
+        i += 1;
     }
 
-    // If we have reached here, it means the buffer has been exhausted without
-    // hitting the termination condition.
-    Err("hpack integer: not enough octets")
+    *octets = Some(i);
+    Ok(None)
+}
+
+// decode_integer for values up to u64, for uses outside of hpack
+// (hpack itself limits integers to u32)
+pub fn decode_integer_u64<'a, 'b, I: Iterator<Item=&'b u8>>(bts: &'a mut I, prefix_size: PrefixSize) -> Result<u64, IntegerError> {
+    let mut value = 0;
+    match try!(feed_octets(prefix_size, &mut value, &mut None, bts)) {
+        Some(value) => Ok(value),
+        None        => Err(IntegerError::Incomplete),
+    }
 }
 
 // encode n into bst
-pub fn encode_integer<'a, 'b, I: Iterator<Item=&'b mut u8>>(n: u32, bts: &'a mut I, prefix_size: u8) {
-    let mut n = n;
-    let check = ( 1 << prefix_size ) - 1;
+// returns the number of octets written (including the prefix octet)
+// so the caller knows where the next field begins
+//
+// past the prefix octet every octet but the last has its high
+// (continuation) bit set and the last one always has it clear,
+// so there is never an extra 0x80 or an empty trailing octet
+//
+// only the prefix bits of the first octet are written, the bits
+// above prefix_size are left as they are so the representation
+// flags (eg. 0x80 for an indexed field) can be set beforehand
+//
+// if bts runs out before the integer is finished OutOfSpace is returned,
+// the octets already written are left as they are
+pub fn encode_integer<'a, 'b, I: Iterator<Item=&'b mut u8>>(n: u32, bts: &'a mut I, prefix_size: PrefixSize) -> Result<usize, IntegerError> {
+    encode_octets(n, bts, prefix_size)
+}
+
+// encode_integer for values up to u64, see decode_integer_u64
+pub fn encode_integer_u64<'a, 'b, I: Iterator<Item=&'b mut u8>>(n: u64, bts: &'a mut I, prefix_size: PrefixSize) -> Result<usize, IntegerError> {
+    encode_octets(n, bts, prefix_size)
+}
+
+// the encoding for every width
+fn encode_octets<'a, 'b, T: CodecInt, I: Iterator<Item=&'b mut u8>>(n: T, bts: &'a mut I, prefix_size: PrefixSize) -> Result<usize, IntegerError> {
+    let mask = prefix_size.mask();
+    let check = T::from_octet(mask);
 
-    let first_byte = bts.next().unwrap();
+    let first_byte = try!(bts.next().ok_or(IntegerError::OutOfSpace));
 
-    *first_byte = 0;
+    *first_byte &= !mask;
 
     if n < check {
-        *first_byte |= n as u8;
-        return;
+        *first_byte |= n.to_octet();
+        return Ok(1);
     }
 
-    *first_byte |= check as u8;
-    n -= check;
+    *first_byte |= mask;
+    let mut n = n.sub_octet(mask);
 
-    loop {
-        let br = bts.next().unwrap();
+    // 7 bits at a time, low bits first, with the continuation
+    // bit set on all but the last octet (same as encode_integer_into)
+    let mut written = 1;
+    while n >= T::from_octet(128) {
+        let br = try!(bts.next().ok_or(IntegerError::OutOfSpace));
+        *br = 0x80 | ( n.to_octet() & 0x7f );
+        n = n.shift_out();
+        written += 1;
+    }
+    let br = try!(bts.next().ok_or(IntegerError::OutOfSpace));
+    *br = n.to_octet();
+    Ok(written + 1)
+}
 
-        if n < 128 {
-            *br = n as u8;
-            break;
-        }
+// encode n onto the end of out, first_byte_flags is OR-ed into the
+// prefix octet so the representation bits (eg. 0x80 for an indexed field)
+// can be set in the same step. The flags should only use the bits above
+// prefix_size
+//
+// out is usually a Vec<u8> but any octet sink works (eg. a fixed buffer)
+//
+// returns the number of octets pushed
+pub fn encode_integer_into<E: Extend<u8>>(n: u32, out: &mut E, prefix_size: PrefixSize, first_byte_flags: u8) -> usize {
+    let check = prefix_size.mask() as u32;
 
-        *br = 0x80 | ( n as u8 & 0x7f );
-        n >>= 7;
+    if n < check {
+        out.extend(Some(first_byte_flags | n as u8));
+        return 1;
+    }
 
-        if n == 0 {
-            break;
-        }
+    out.extend(Some(first_byte_flags | check as u8));
+
+    let mut written = 1;
+    let mut n = n - check;
+    while n >= 128 {
+        out.extend(Some(0x80 | ( n as u8 & 0x7f )));
+        n >>= 7;
+        written += 1;
     }
+    out.extend(Some(n as u8));
+
+    written + 1
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{decode_integer, encode_integer};
+    use super::{decode_integer, encode_integer, encode_integer_into, IntegerError};
+    use super::{IntegerDecoder, IntegerPoll, decode_integer_counted, decode_integer_slice};
+    use super::{PrefixSize, PREFIX_4, PREFIX_5, PREFIX_6, PREFIX_7, PREFIX_8, MAX_INTEGER_LEN};
+    use super::{decode_integer_u64, encode_integer_u64, MAX_INTEGER_LEN_U64};
+
+    #[test]
+    fn prefix_sizes() {
+        // checked in a const context too
+        const TOO_BIG: Option<PrefixSize> = PrefixSize::new(9);
+        assert_eq!(TOO_BIG, None);
+        assert_eq!(PrefixSize::new(0), None);
+        assert_eq!(PrefixSize::new(1).map(|p| p.bits()), Some(1));
+
+        // the value 2^N - 1 is the first that needs a continuation octet
+        for &(prefix, bits) in &[(PREFIX_4, 4), (PREFIX_5, 5), (PREFIX_6, 6), (PREFIX_7, 7), (PREFIX_8, 8)] {
+            assert_eq!(prefix.bits(), bits);
+            assert_eq!(Some(prefix), PrefixSize::new(bits));
+
+            let max_in_prefix = (1u32 << bits) - 2;
+            let mut out = Vec::new();
+            assert_eq!(encode_integer_into(max_in_prefix, &mut out, prefix, 0), 1);
+            assert_eq!(encode_integer_into(max_in_prefix + 1, &mut out, prefix, 0), 2);
+            assert_eq!(decode_integer(&mut out[..1].iter(), prefix), Ok(max_in_prefix));
+            assert_eq!(decode_integer(&mut out[1..].iter(), prefix), Ok(max_in_prefix + 1));
+        }
+    }
 
     #[test]
     fn decode_test() {
         // simple tst
         let tst_num = vec![0x41u8];
-        let num = decode_integer(&mut tst_num.iter(), 8).unwrap();
+        let num = decode_integer(&mut tst_num.iter(), PREFIX_8).unwrap();
         assert_eq!(num, 65);
 
         // complex number
         let tst_num = vec![0xFF, 0x05];
-        let num = decode_integer(&mut tst_num.iter(), 8).unwrap();
+        let num = decode_integer(&mut tst_num.iter(), PREFIX_8).unwrap();
         assert_eq!(num, 260);
 
         // more complex number
         let tst_num = vec![0x1F, 0x9A, 0x0A];
-        let num = decode_integer(&mut tst_num.iter(), 5).unwrap();
+        let num = decode_integer(&mut tst_num.iter(), PREFIX_5).unwrap();
+        assert_eq!(num, 1337);
+    }
+
+    #[test]
+    fn decode_slice_test() {
+        assert_eq!(decode_integer_slice(&[0x41], PREFIX_8), Ok((65, &[][..])));
+        assert_eq!(decode_integer_slice(&[0xFF, 0x05], PREFIX_8), Ok((260, &[][..])));
+        assert_eq!(decode_integer_slice(&[0x1F, 0x9A, 0x0A], PREFIX_5), Ok((1337, &[][..])));
+
+        // the rest of the buffer is handed back
+        let buf = [0x1F, 0x9A, 0x0A, 0x82, 0x86];
+        let (num, rest) = decode_integer_slice(&buf, PREFIX_5).unwrap();
         assert_eq!(num, 1337);
+        assert_eq!(rest, &[0x82, 0x86]);
+        assert_eq!(decode_integer_slice(rest, PREFIX_7), Ok((2, &[0x86][..])));
+
+        assert_eq!(decode_integer_slice(&[0x1F, 0x9A], PREFIX_5), Err(IntegerError::Incomplete));
+        assert_eq!(decode_integer_slice(&[], PREFIX_5), Err(IntegerError::Incomplete));
+    }
+
+    #[test]
+    fn decode_non_minimal_test() {
+        // minimal encoding of 31 with a 5 bit prefix
+        let tst_num = vec![0x1F, 0x00];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_5), Ok(31));
+
+        // same value padded with a redundant continuation
+        let tst_num = vec![0x1F, 0x80, 0x00];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_5), Err(IntegerError::TooManyOctets));
+
+        // minimal encoding of 255 with an 8 bit prefix
+        let tst_num = vec![0xFF, 0x00];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_8), Ok(255));
+
+        let tst_num = vec![0xFF, 0x80, 0x80, 0x80, 0x00];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_8), Err(IntegerError::TooManyOctets));
+    }
+
+    #[test]
+    fn decode_max_value_test() {
+        let mut vec = vec![0; 10];
+
+        let n = encode_integer(::std::u32::MAX, &mut vec.iter_mut(), PREFIX_8).unwrap();
+        assert_eq!(n, 6);
+        assert_eq!(decode_integer(&mut vec[..n].iter(), PREFIX_8), Ok(::std::u32::MAX));
+
+        let n = encode_integer(::std::u32::MAX, &mut vec.iter_mut(), PrefixSize::new(1).unwrap()).unwrap();
+        assert_eq!(decode_integer(&mut vec[..n].iter(), PrefixSize::new(1).unwrap()), Ok(::std::u32::MAX));
+
+        // 255 + (2^32 - 1) does not fit
+        let tst_num = vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_8), Err(IntegerError::Overflow));
+
+        // last octet carries bits past 2^32
+        let tst_num = vec![0x1F, 0x80, 0x80, 0x80, 0x80, 0x7F];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_5), Err(IntegerError::Overflow));
+    }
+
+    #[test]
+    fn decode_error_test() {
+        // no octets at all
+        let tst_num: Vec<u8> = vec![];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_5), Err(IntegerError::Incomplete));
+
+        // continuation flag set on the last available octet
+        let tst_num = vec![0x1F, 0x9A];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_5), Err(IntegerError::Incomplete));
+
+        // to many continuation octets
+        let tst_num = vec![0x1F, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert_eq!(decode_integer(&mut tst_num.iter(), PREFIX_5), Err(IntegerError::TooManyOctets));
+
+
+        assert_eq!(IntegerError::Incomplete.to_string(), "hpack integer: not enough octets");
+    }
+
+    #[test]
+    fn decode_counted_test() {
+        assert_eq!(decode_integer_counted(&mut [0x1F, 0x9A, 0x0A].iter(), PREFIX_5), Ok((1337, 3)));
+        assert_eq!(decode_integer_counted(&mut [0x0A, 0x0A].iter(), PREFIX_5), Ok((10, 1)));
+
+        // octets after the integer are not read
+        let buf = [0xFF, 0x05, 0x82];
+        let mut bts = buf.iter();
+        assert_eq!(decode_integer_counted(&mut bts, PREFIX_8), Ok((260, 2)));
+        assert_eq!(bts.next(), Some(&0x82));
+
+        assert_eq!(decode_integer_counted(&mut [0x1F, 0x9A].iter(), PREFIX_5), Err(IntegerError::Incomplete));
+    }
+
+    #[test]
+    fn streaming_decode_test() {
+        let mut decoder = IntegerDecoder::new(PREFIX_5);
+
+        assert_eq!(decoder.feed(&mut [0x1F].iter()), Ok(IntegerPoll::Pending));
+        assert_eq!(decoder.feed(&mut [].iter()), Ok(IntegerPoll::Pending));
+        assert_eq!(decoder.feed(&mut [0x9A].iter()), Ok(IntegerPoll::Pending));
+        assert_eq!(decoder.feed(&mut [0x0A].iter()), Ok(IntegerPoll::Ready(1337)));
+
+        // starts over after Ready and leaves the octets after the integer
+        let buf = [0x0A, 0x1F, 0x9A, 0x0A, 0x55];
+        let mut bts = buf.iter();
+        assert_eq!(decoder.feed(&mut bts), Ok(IntegerPoll::Ready(10)));
+        assert_eq!(decoder.feed(&mut bts), Ok(IntegerPoll::Ready(1337)));
+        assert_eq!(bts.next(), Some(&0x55));
+
+        // the octet limit counts across calls
+        let mut decoder = IntegerDecoder::new(PREFIX_5);
+        assert_eq!(decoder.feed(&mut [0x1F].iter()), Ok(IntegerPoll::Pending));
+        for _ in 0..5 {
+            assert_eq!(decoder.feed(&mut [0x80].iter()), Ok(IntegerPoll::Pending));
+        }
+        assert_eq!(decoder.feed(&mut [0x01].iter()), Err(IntegerError::TooManyOctets));
+
     }
 
     // this test relise on decodeing to work
@@ -153,20 +562,301 @@ mod tests {
 
         // simple
         let tst_code = vec![0x4];
-        encode_integer(4, &mut vec.iter_mut(), 8);
-        let num = decode_integer(&mut vec.iter(), 8).unwrap();
+        let n = encode_integer(4, &mut vec.iter_mut(), PREFIX_8).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(&vec[..n], &tst_code[..]);
+        let num = decode_integer(&mut vec.iter(), PREFIX_8).unwrap();
         assert_eq!(num, 4);
 
         // little less simple
+        // (the bits above the prefix are kept, so clear the last value)
+        vec[0] = 0;
         let tst_code = vec![0x03, 0x01];
-        encode_integer(4, &mut vec.iter_mut(), 2);
-        let num = decode_integer(&mut vec.iter(), 2).unwrap();
+        let n = encode_integer(4, &mut vec.iter_mut(), PrefixSize::new(2).unwrap()).unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(&vec[..n], &tst_code[..]);
+        let num = decode_integer(&mut vec.iter(), PrefixSize::new(2).unwrap()).unwrap();
         assert_eq!(num, 4);
 
         // more complex
         let tst_code = vec![0x1F, 0x9A, 0x0A];
-        encode_integer(1337, &mut vec.iter_mut(), 5);
-        let num = decode_integer(&mut vec.iter(), 5).unwrap();
+        let n = encode_integer(1337, &mut vec.iter_mut(), PREFIX_5).unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(&vec[..n], &tst_code[..]);
+        let num = decode_integer(&mut vec.iter(), PREFIX_5).unwrap();
         assert_eq!(num, 1337);
+
+        // more than one continuation octet
+        let tst_code = vec![0x1F, 0x81, 0x8D, 0x06];
+        let n = encode_integer(100000, &mut vec.iter_mut(), PREFIX_5).unwrap();
+        assert_eq!(n, 4);
+        assert_eq!(&vec[..n], &tst_code[..]);
+        let num = decode_integer(&mut vec.iter(), PREFIX_5).unwrap();
+        assert_eq!(num, 100000);
+
+        // the longest encoding any u32 has, 5 continuation octets
+        let tst_code = vec![0x1F, 0xE0, 0xFF, 0xFF, 0xFF, 0x0F];
+        let n = encode_integer(::std::u32::MAX, &mut vec.iter_mut(), PREFIX_5).unwrap();
+        assert_eq!(n, MAX_INTEGER_LEN);
+        assert_eq!(&vec[..n], &tst_code[..]);
+        let num = decode_integer(&mut vec.iter(), PREFIX_5).unwrap();
+        assert_eq!(num, ::std::u32::MAX);
+
+        let mut out = Vec::new();
+        encode_integer_into(::std::u32::MAX, &mut out, PREFIX_5, 0);
+        assert_eq!(out, tst_code);
+    }
+
+    // encode_integer only takes a PrefixSize so a prefix outside
+    // of 1..=8 can't reach the mask math
+    #[test]
+    fn encode_prefix_range() {
+        for bits in 0..=255u8 {
+            let encoded = PrefixSize::new(bits).map(|prefix| {
+                let mut buf = [0u8; MAX_INTEGER_LEN];
+                let len = encode_integer(255, &mut buf.iter_mut(), prefix).unwrap();
+                assert_eq!(decode_integer(&mut buf[..len].iter(), prefix), Ok(255));
+                len
+            });
+            assert_eq!(encoded.is_some(), bits >= 1 && bits <= 8, "prefix {}", bits);
+        }
+    }
+
+    #[test]
+    fn u64_round_trip() {
+        let values = [0, 30, 31, 1337, ::std::u32::MAX as u64, ::std::u32::MAX as u64 + 1,
+            1 << 40, (1 << 63) - 1, 1 << 63, ::std::u64::MAX];
+        for &n in values.iter() {
+            for &prefix in &[PREFIX_5, PREFIX_8, PrefixSize::new(1).unwrap()] {
+                let mut buf = [0u8; MAX_INTEGER_LEN_U64];
+                let len = encode_integer_u64(n, &mut buf.iter_mut(), prefix).unwrap();
+                let mut bts = buf[..len].iter();
+                assert_eq!(decode_integer_u64(&mut bts, prefix), Ok(n), "{} with prefix {}", n, prefix.bits());
+                assert!(bts.next().is_none());
+            }
+        }
+
+        // the longest encoding takes all 10 continuation octets
+        let mut buf = [0u8; MAX_INTEGER_LEN_U64];
+        assert_eq!(encode_integer_u64(::std::u64::MAX, &mut buf.iter_mut(), PREFIX_8), Ok(MAX_INTEGER_LEN_U64));
+        assert_eq!(buf, [0xff, 0x80, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        // which is too much for a u32
+        assert_eq!(decode_integer(&mut buf.iter(), PREFIX_8), Err(IntegerError::Overflow));
+
+        // the same octets as encode_integer for anything in a u32
+        let mut small = [0u8; MAX_INTEGER_LEN];
+        let len = encode_integer(100000, &mut small.iter_mut(), PREFIX_5).unwrap();
+        let mut buf = [0u8; MAX_INTEGER_LEN_U64];
+        let len_u64 = encode_integer_u64(100000, &mut buf.iter_mut(), PREFIX_5).unwrap();
+        assert_eq!(&small[..len], &buf[..len_u64]);
+    }
+
+    #[test]
+    fn u64_errors() {
+        // one past u64::MAX
+        let octets = [0xff, 0x81, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(decode_integer_u64(&mut octets.iter(), PREFIX_8), Err(IntegerError::Overflow));
+        let octets = [0xff, 0x80, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x80, 0x01];
+        assert_eq!(decode_integer_u64(&mut octets.iter(), PREFIX_8), Err(IntegerError::TooManyOctets));
+        assert_eq!(decode_integer_u64(&mut [0x1f, 0x80].iter(), PREFIX_5), Err(IntegerError::Incomplete));
+
+        let mut buf = [0u8; MAX_INTEGER_LEN];
+        assert_eq!(encode_integer_u64(::std::u64::MAX, &mut buf.iter_mut(), PREFIX_8), Err(IntegerError::OutOfSpace));
+    }
+
+    #[test]
+    fn encode_keeps_flags() {
+        let mut buf = [0x80, 0];
+        assert_eq!(encode_integer(2, &mut buf.iter_mut(), PREFIX_7), Ok(1));
+        assert_eq!(buf[0], 0x82);
+
+        // the old prefix bits are cleared, the flags stay
+        let mut buf = [0x5f, 0, 0];
+        assert_eq!(encode_integer(1337, &mut buf.iter_mut(), PREFIX_5), Ok(3));
+        assert_eq!(buf, [0x5f, 0x9a, 0x0a]);
+        let mut buf = [0x5f];
+        assert_eq!(encode_integer(10, &mut buf.iter_mut(), PREFIX_5), Ok(1));
+        assert_eq!(buf[0], 0x4a);
+
+        // a full octet prefix has no flag bits
+        let mut buf = [0xff];
+        assert_eq!(encode_integer(4, &mut buf.iter_mut(), PREFIX_8), Ok(1));
+        assert_eq!(buf[0], 4);
+    }
+
+    #[test]
+    fn encode_boundaries() {
+        for bits in 1..9 {
+            let prefix = PrefixSize::new(bits).unwrap();
+            let check = (1u32 << bits) - 1;
+            let values = [check - 1, check, check + 1, check + 127, check + 128,
+                127, 128, 255, 256, 16383, 16384, check + 16383, check + 16384];
+
+            for &n in values.iter() {
+                let mut buf = [0u8; MAX_INTEGER_LEN];
+                let len = encode_integer(n, &mut buf.iter_mut(), prefix).unwrap();
+
+                // one octet per started 7 bits after the prefix
+                let expected_len = if n < check { 1 } else {
+                    let mut rest = n - check;
+                    let mut len = 2;
+                    while rest >= 128 {
+                        rest >>= 7;
+                        len += 1;
+                    }
+                    len
+                };
+                assert_eq!(len, expected_len, "{} with prefix {}", n, bits);
+                if len > 1 {
+                    assert_eq!(buf[0] as u32, check);
+                    assert!(buf[1..len - 1].iter().all(|b| b & 0x80 == 0x80));
+                    assert_eq!(buf[len - 1] & 0x80, 0);
+                }
+
+                assert_eq!(decode_integer(&mut buf[..len].iter(), prefix), Ok(n));
+                let mut out = Vec::new();
+                assert_eq!(encode_integer_into(n, &mut out, prefix, 0), len);
+                assert_eq!(&out[..], &buf[..len]);
+            }
+        }
+
+        // with a 5 bit prefix 31 + 127 is the last value with one continuation octet
+        let mut out = Vec::new();
+        encode_integer_into(158, &mut out, PREFIX_5, 0);
+        assert_eq!(out, vec![0x1F, 0x7F]);
+        out.clear();
+        encode_integer_into(159, &mut out, PREFIX_5, 0);
+        assert_eq!(out, vec![0x1F, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn encode_into_test() {
+        let mut out = Vec::new();
+
+        // indexed header field :method GET
+        assert_eq!(encode_integer_into(2, &mut out, PREFIX_7, 0x80), 1);
+        assert_eq!(out, vec![0x82]);
+
+        // appends after what is already there
+        assert_eq!(encode_integer_into(1337, &mut out, PREFIX_5, 0x20), 3);
+        assert_eq!(out, vec![0x82, 0x3F, 0x9A, 0x0A]);
+
+        let num = decode_integer(&mut out[1..].iter(), PREFIX_5).unwrap();
+        assert_eq!(num, 1337);
+
+        // value exactly at the prefix limit
+        let mut out = Vec::new();
+        assert_eq!(encode_integer_into(255, &mut out, PREFIX_8, 0), 2);
+        assert_eq!(out, vec![0xFF, 0x00]);
+    }
+
+    #[test]
+    fn encode_out_of_space_test() {
+        let mut vec = vec![0; 1];
+
+        // fits in the prefix
+        assert_eq!(encode_integer(10, &mut vec.iter_mut(), PREFIX_5), Ok(1));
+
+        // needs 3 octets
+        assert_eq!(encode_integer(1337, &mut vec.iter_mut(), PREFIX_5), Err(IntegerError::OutOfSpace));
+        assert_eq!(vec[0], 0x1F);
+
+        let mut empty: Vec<u8> = vec![];
+        assert_eq!(encode_integer(1, &mut empty.iter_mut(), PREFIX_5), Err(IntegerError::OutOfSpace));
+    }
+}
+
+#[cfg(test)]
+mod prop_tests {
+    use proptest::prelude::*;
+    use proptest::collection;
+    use super::{decode_integer, encode_integer, PrefixSize};
+    use super::{decode_integer_u64, encode_integer_u64, MAX_INTEGER_LEN_U64};
+
+    proptest! {
+        #[test]
+        fn round_trip_u64(n in any::<u64>(), prefix_size in 1u8..9) {
+            let prefix = PrefixSize::new(prefix_size).unwrap();
+            let mut buf = [0u8; MAX_INTEGER_LEN_U64];
+            let written = encode_integer_u64(n, &mut buf.iter_mut(), prefix).unwrap();
+            let mut bts = buf[..written].iter();
+            prop_assert_eq!(decode_integer_u64(&mut bts, prefix), Ok(n));
+            prop_assert!(bts.next().is_none());
+        }
+
+        #[test]
+        fn round_trip(n in any::<u32>(), prefix_size in 1u8..9) {
+            let prefix = PrefixSize::new(prefix_size).unwrap();
+            // 6 octets is the most any u32 needs
+            let mut buf = [0u8; 6];
+            let written = encode_integer(n, &mut buf.iter_mut(), prefix).unwrap();
+            let mut bts = buf[..written].iter();
+            prop_assert_eq!(decode_integer(&mut bts, prefix), Ok(n));
+            prop_assert!(bts.next().is_none());
+        }
+
+        // whatever decodes must have been the minimal
+        // encoding, so encoding it again gives the same octets
+        #[test]
+        fn decode_encode_same_octets(bytes in collection::vec(any::<u8>(), 1..8), prefix_size in 1u8..9) {
+            // the bits above the prefix are not part of the integer
+            let mut bytes = bytes;
+            if prefix_size < 8 {
+                bytes[0] &= (1u8 << prefix_size) - 1;
+            }
+            let prefix = PrefixSize::new(prefix_size).unwrap();
+            let mut bts = bytes.iter();
+            if let Ok(n) = decode_integer(&mut bts, prefix) {
+                let used = bytes.len() - bts.len();
+                let mut buf = [0u8; 6];
+                let written = encode_integer(n, &mut buf.iter_mut(), prefix).unwrap();
+                prop_assert_eq!(&buf[..written], &bytes[..used]);
+            }
+        }
+    }
+}
+
+// everything here only uses core, the same way it would be
+// used on a target without std (these still run with std,
+// no_std/ is what checks the codec builds without it)
+#[cfg(test)]
+mod no_std_tests {
+    use core::iter::Extend;
+    use super::{decode_integer, encode_integer, encode_integer_into, IntegerDecoder, IntegerPoll, IntegerError};
+    use super::{PREFIX_5, PREFIX_8, MAX_INTEGER_LEN};
+
+    // fixed size octet sink with no allocation
+    struct FixedBuf {
+        buf: [u8; 8],
+        len: usize,
+    }
+
+    impl Extend<u8> for FixedBuf {
+        fn extend<T: IntoIterator<Item=u8>>(&mut self, iter: T) {
+            for b in iter {
+                self.buf[self.len] = b;
+                self.len += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_buffers() {
+        let mut buf = [0u8; 6];
+        assert_eq!(encode_integer(1337, &mut buf.iter_mut(), PREFIX_5), Ok(3));
+        assert_eq!(decode_integer(&mut buf[..3].iter(), PREFIX_5), Ok(1337));
+
+        let mut out = FixedBuf { buf: [0; 8], len: 0 };
+        assert_eq!(encode_integer_into(::core::u32::MAX, &mut out, PREFIX_8, 0), MAX_INTEGER_LEN);
+        assert_eq!(out.len, 6);
+        assert_eq!(decode_integer(&mut out.buf[..out.len].iter(), PREFIX_8), Ok(::core::u32::MAX));
+
+        let mut decoder = IntegerDecoder::new(PREFIX_5);
+        assert_eq!(decoder.feed(&mut buf[..2].iter()), Ok(IntegerPoll::Pending));
+        assert_eq!(decoder.feed(&mut buf[2..3].iter()), Ok(IntegerPoll::Ready(1337)));
+
+        // errors are plain values
+        let mut small = [0u8; 1];
+        assert_eq!(encode_integer(1337, &mut small.iter_mut(), PREFIX_5), Err(IntegerError::OutOfSpace));
     }
 }
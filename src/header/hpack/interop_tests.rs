@@ -0,0 +1,161 @@
+//! Header blocks written by other hpack implementations
+//!
+//! Every block is decoded and checked against the headers that
+//! were sent, then the headers are encoded again with a fresh
+//! Encoder and decoded back to catch anything our own side does
+//! differently (huffman padding, indexing decisions)
+//!
+//! Where the blocks came from
+//! - curl: curl 7.88.1 (libcurl with nghttp2 1.52.0) run with
+//!   --http2-prior-knowledge against a plain TCP listener, the
+//!   HEADERS payload was cut out of what it sent. Each is the
+//!   first block on a new connection
+//! - nghttp2: libnghttp2 1.52.0 nghttp2_hd_deflate_hd called through
+//!   its C API, consecutive blocks share one deflater so they depend
+//!   on the dynamic table built by the blocks before them
+
+use super::decoder::Decoder;
+use super::encoder::Encoder;
+use header::HeaderEntry;
+
+// turn the hex dump of a capture into its octets
+fn hex(s: &str) -> Vec<u8> {
+    let s: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    s.chunks(2).map(|pair| {
+        let digits = ::std::str::from_utf8(pair).unwrap();
+        u8::from_str_radix(digits, 16).unwrap()
+    }).collect()
+}
+
+fn headers(fields: &[(&'static str, &'static str)]) -> Vec<HeaderEntry> {
+    fields.iter().map(|&f| f.into()).collect()
+}
+
+// the blocks go through decoder in order (one connection) and are
+// then re-encoded through one new Encoder and Decoder pair
+fn check_connection(mut decoder: Decoder, blocks: &[(Vec<u8>, Vec<HeaderEntry>)], table_size: usize) {
+    let mut encoder = Encoder::new(table_size, 10);
+    let mut round_trip = Decoder::new(table_size, 10);
+
+    for &(ref block, ref expected) in blocks {
+        let decoded = decoder.decode(block).unwrap();
+        assert_eq!(&decoded, expected);
+        for (d, e) in decoded.iter().zip(expected.iter()) {
+            assert_eq!(d.is_sensitive(), e.is_sensitive(), "{:?}", d);
+        }
+
+        let encoded = encoder.encode(&decoded);
+        let again = round_trip.decode(&encoded).unwrap();
+        assert_eq!(&again, expected);
+        for (a, e) in again.iter().zip(expected.iter()) {
+            assert_eq!(a.is_sensitive(), e.is_sensitive());
+        }
+    }
+}
+
+#[test]
+fn curl_get() {
+    // curl http://127.0.0.1:18080/
+    let block = hex("828486418b089d5c0b8170dc0bc0781f7a8825b650c3abbcf2e153032a2f2a");
+    check_connection(Decoder::new(4096, 10), &[(block, headers(&[
+        (":method", "GET"),
+        (":path", "/"),
+        (":scheme", "http"),
+        (":authority", "127.0.0.1:18080"),
+        ("user-agent", "curl/7.88.1"),
+        ("accept", "*/*"),
+    ]))], 4096);
+}
+
+#[test]
+fn curl_custom_headers() {
+    // curl -H "X-Custom: hello" -H "Cookie: a=b; c=d" -A "kurisu-test/1.0" \
+    //     "http://127.0.0.1:18080/path/to/resource?query=1&x=y"
+    //
+    // the short cookie is sent never indexed
+    let block = hex("82049662b1a67612762c2a0f6d842ff9dad2d9ea00fc7983d786418b089d5c0b8170dc0bc0781f7a8beb6d8645ab
+                     24a84b015c1f53032a2f2a4086f2b12d424f4f849cb4507f1f11861c11fda84824");
+    let mut expected = headers(&[
+        (":method", "GET"),
+        (":path", "/path/to/resource?query=1&x=y"),
+        (":scheme", "http"),
+        (":authority", "127.0.0.1:18080"),
+        ("user-agent", "kurisu-test/1.0"),
+        ("accept", "*/*"),
+        ("x-custom", "hello"),
+    ]);
+    expected.push(HeaderEntry::new("cookie", "a=b; c=d").with_sensitive(true));
+    check_connection(Decoder::new(4096, 10), &[(block, expected)], 4096);
+}
+
+#[test]
+fn nghttp2_responses_small_table() {
+    // nghttp2_hd_deflate_new with a 256 octet table, the first block starts
+    // with a size update to 256. The later blocks refer back to (and evict)
+    // entries from the earlier ones, authorization was given NO_INDEX
+    let block1 = hex("3fe101885f92497ca589d34d1f6a1271d882a60b532acf7f0f0d8308996b6196e4593e940b4a6a22541004e2
+                      8115c002e000a62d1bff7686aa69d29afcff0f289f4150831ea8001132d36e3af3e38c92165fb5358d33c0c7da
+                      98d29af55547af");
+    let block2 = hex("88c00f0d836dc75e6196e4593e940b4a6a22541004e28115c002e002a62d1bffbf5889a47e561cc58197000f");
+    let block3 = hex("8d5f8b1d75d0620d263d4c7441ea0f0d0132c0c11f0889ba51d85b1441496153");
+
+    let response1 = headers(&[
+        (":status", "200"),
+        ("content-type", "text/html; charset=utf-8"),
+        ("content-length", "1234"),
+        ("date", "Wed, 14 Oct 2026 12:00:00 GMT"),
+        ("server", "nghttpx"),
+        ("set-cookie", "session=0123456789abcdef; Path=/; HttpOnly"),
+    ]);
+    let response2 = headers(&[
+        (":status", "200"),
+        ("content-type", "text/html; charset=utf-8"),
+        ("content-length", "5678"),
+        ("date", "Wed, 14 Oct 2026 12:00:01 GMT"),
+        ("server", "nghttpx"),
+        ("cache-control", "max-age=3600"),
+    ]);
+    let mut response3 = headers(&[
+        (":status", "404"),
+        ("content-type", "application/json"),
+        ("content-length", "2"),
+        ("date", "Wed, 14 Oct 2026 12:00:01 GMT"),
+        ("server", "nghttpx"),
+    ]);
+    response3.push(HeaderEntry::new("authorization", "Bearer secret").with_sensitive(true));
+
+    let mut decoder = Decoder::new(4096, 10);
+    decoder.set_max_dynamic_table_size(256);
+    check_connection(decoder, &[(block1, response1), (block2, response2), (block3, response3)], 256);
+}
+
+#[test]
+fn nghttp2_table_size_update() {
+    // the same request twice with nghttp2_hd_deflate_change_table_size(0)
+    // in between, the second block clears the table before anything else
+    let block1 = hex("828741882f91d35d055cf64d847a8beb6d8645ab24a84b015c1f53032a2f2a");
+    let block2 = hex("20828701882f91d35d055cf64d840f2b8beb6d8645ab24a84b015c1f0f04032a2f2a");
+
+    let request = || headers(&[
+        (":method", "GET"),
+        (":scheme", "https"),
+        (":authority", "example.org"),
+        (":path", "/"),
+        ("user-agent", "kurisu-test/1.0"),
+        ("accept", "*/*"),
+    ]);
+
+    let mut decoder = Decoder::new(4096, 10);
+    assert_eq!(decoder.decode(&block1).unwrap(), request());
+    assert!(decoder.dump_table().contains(":authority: example.org"));
+    assert_eq!(decoder.decode(&block2).unwrap(), request());
+    assert_eq!(decoder.dump_table(), "      Table size: 0/0\n");
+
+    // the second block is what a peer sends once it has seen a
+    // SETTINGS_HEADER_TABLE_SIZE of 0, so static only mode takes it
+    let mut decoder = Decoder::new(4096, 10);
+    decoder.set_static_only(true);
+    assert_eq!(decoder.decode(&block2).unwrap(), request());
+
+    check_connection(Decoder::new(4096, 10), &[(block1, request()), (block2, request())], 4096);
+}
@@ -1,7 +1,114 @@
 //! Every connection manages an instance of the hpack encoder/decoder
 //! This is so that a dynamic table can be properly managed per connection
 
+use std::fmt;
+use std::error::Error;
+
 mod huffman;
 mod integers;
+mod strings;
 mod table;
 pub mod decoder;
+pub mod encoder;
+
+#[cfg(test)]
+mod rfc_tests;
+
+#[cfg(test)]
+mod interop_tests;
+
+pub use self::integers::IntegerError;
+pub use self::huffman::HuffmanError;
+pub use self::table::static_names;
+use header::ProtocolError;
+use frame::ErrorCode;
+
+/// Errors from decoding or encoding an hpack header block
+///
+/// When decoding any of these is a COMPRESSION_ERROR
+/// except for Protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpackError {
+    Integer(IntegerError),
+    Huffman(HuffmanError),
+    /// a string literal was cut off before its length was reached
+    TruncatedString,
+    /// there are fewer octets than the length of the block,
+    /// the usize is the length that was given
+    TruncatedBlock(usize),
+    /// index 0 is never valid
+    ZeroIndex,
+    /// the index is past the end of the dynamic table
+    InvalidIndex(usize),
+    /// a dynamic table size update came after a header field
+    SizeUpdateNotAtStart,
+    /// more than two dynamic table size updates started a block
+    TooManySizeUpdates,
+    /// a dynamic table size update was bigger than SETTINGS_HEADER_TABLE_SIZE
+    SizeUpdateTooLarge(usize),
+    /// the decoded header list went over the limit set on the Decoder
+    HeaderListTooLarge,
+    /// the block tried to use the dynamic table on a Decoder
+    /// that only allows the static table
+    DynamicTableDisabled,
+    /// a string literal is not valid UTF-8
+    InvalidUtf8,
+    /// a decoded field is not allowed in HTTP/2
+    /// (this one is a PROTOCOL_ERROR for the stream instead)
+    Protocol(ProtocolError),
+}
+
+impl HpackError {
+    // the code to close the connection (or reset the stream for Protocol) with
+    pub fn error_code(&self) -> ErrorCode {
+        match *self {
+            HpackError::Protocol(_) => ErrorCode::ProtocolError,
+            _                       => ErrorCode::CompressionError,
+        }
+    }
+}
+
+impl fmt::Display for HpackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::HpackError::*;
+        match *self {
+            Integer(ref e)  => write!(f, "{}", e),
+            Huffman(ref e)  => write!(f, "{}", e),
+            TruncatedString => f.write_str("hpack: string literal is shorter than its length"),
+            TruncatedBlock(l) => write!(f, "hpack: fewer octets than the block length of {}", l),
+            ZeroIndex       => f.write_str("hpack: index of 0 was found"),
+            InvalidIndex(i) => write!(f, "hpack: index {} is out of range", i),
+            SizeUpdateNotAtStart    => f.write_str("hpack: dynamic table size update after a header field"),
+            TooManySizeUpdates      => f.write_str("hpack: more than two dynamic table size updates at the start of a block"),
+            SizeUpdateTooLarge(s)   => write!(f, "hpack: dynamic table size update of {} exceeds the protocol maximum", s),
+            HeaderListTooLarge      => f.write_str("hpack: decoded header list is larger than the maximum header list size"),
+            DynamicTableDisabled    => f.write_str("hpack: dynamic table used when only the static table is allowed"),
+            InvalidUtf8             => f.write_str("hpack: string literal is not valid UTF-8"),
+            Protocol(ref e)         => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for HpackError {
+    fn description(&self) -> &str {
+        "hpack error"
+    }
+}
+
+impl From<IntegerError> for HpackError {
+    fn from(e: IntegerError) -> HpackError {
+        HpackError::Integer(e)
+    }
+}
+
+impl From<HuffmanError> for HpackError {
+    fn from(e: HuffmanError) -> HpackError {
+        HpackError::Huffman(e)
+    }
+}
+
+impl From<ProtocolError> for HpackError {
+    fn from(e: ProtocolError) -> HpackError {
+        HpackError::Protocol(e)
+    }
+}
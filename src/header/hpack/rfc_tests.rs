@@ -0,0 +1,278 @@
+//! The examples from RFC 7541 Appendix C
+//!
+//! Each block is decoded and encoded, the octets and the dynamic
+//! table afterwards (in the same layout the RFC lists it) must
+//! match on both sides
+
+use super::decoder::Decoder;
+use super::encoder::{Encoder, IndexingPolicy, HuffmanPolicy};
+use super::HpackError;
+use header::HeaderEntry;
+
+fn headers(fields: &[(&'static str, &'static str)]) -> Vec<HeaderEntry> {
+    fields.iter().map(|&f| f.into()).collect()
+}
+
+// decode block and check it gives fields, then encode fields and
+// check it gives block, both tables must end up as table
+fn check(decoder: &mut Decoder, encoder: &mut Encoder, fields: &[HeaderEntry], block: &[u8], table: &str) {
+    let decoded = decoder.decode(block).unwrap();
+    assert_eq!(decoded, fields);
+    for (d, f) in decoded.iter().zip(fields.iter()) {
+        assert_eq!(d.is_sensitive(), f.is_sensitive());
+    }
+    assert_eq!(decoder.dump_table(), table);
+
+    assert_eq!(encoder.encode(fields), block);
+    assert_eq!(encoder.dump_table(), table);
+}
+
+fn raw_encoder(max_size: usize) -> Encoder {
+    let mut encoder = Encoder::new(max_size, 10);
+    encoder.set_huffman_policy(HuffmanPolicy::Never);
+    encoder
+}
+
+fn huffman_encoder(max_size: usize) -> Encoder {
+    let mut encoder = Encoder::new(max_size, 10);
+    encoder.set_huffman_policy(HuffmanPolicy::Always);
+    encoder
+}
+
+// C.2.1
+#[test]
+fn literal_with_indexing() {
+    let mut decoder = Decoder::new(4096, 10);
+    let mut encoder = raw_encoder(4096);
+
+    check(&mut decoder, &mut encoder,
+        &headers(&[("custom-key", "custom-header")]),
+        b"\x40\x0acustom-key\x0dcustom-header",
+        "[ 62] (s = 55) custom-key: custom-header\n      Table size: 55/4096\n");
+}
+
+// C.2.2
+#[test]
+fn literal_without_indexing() {
+    let mut decoder = Decoder::new(4096, 10);
+    let mut encoder = raw_encoder(4096);
+    encoder.set_indexing_policy(IndexingPolicy::Never);
+
+    check(&mut decoder, &mut encoder,
+        &headers(&[(":path", "/sample/path")]),
+        b"\x04\x0c/sample/path",
+        "      Table size: 0/4096\n");
+}
+
+// C.2.3
+#[test]
+fn literal_never_indexed() {
+    let mut decoder = Decoder::new(4096, 10);
+    let mut encoder = raw_encoder(4096);
+
+    let fields = vec![HeaderEntry::new("password", "secret").with_sensitive(true)];
+    check(&mut decoder, &mut encoder, &fields,
+        b"\x10\x08password\x06secret",
+        "      Table size: 0/4096\n");
+}
+
+// C.2.4
+#[test]
+fn indexed_field() {
+    let mut decoder = Decoder::new(4096, 10);
+    let mut encoder = raw_encoder(4096);
+
+    check(&mut decoder, &mut encoder,
+        &headers(&[(":method", "GET")]),
+        b"\x82",
+        "      Table size: 0/4096\n");
+}
+
+fn request1() -> Vec<HeaderEntry> {
+    headers(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com")])
+}
+
+fn request2() -> Vec<HeaderEntry> {
+    headers(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"),
+        ("cache-control", "no-cache")])
+}
+
+fn request3() -> Vec<HeaderEntry> {
+    headers(&[(":method", "GET"), (":scheme", "https"), (":path", "/index.html"), (":authority", "www.example.com"),
+        ("custom-key", "custom-value")])
+}
+
+const REQUEST1_TABLE: &'static str = "\
+[ 62] (s = 57) :authority: www.example.com
+      Table size: 57/4096
+";
+
+const REQUEST2_TABLE: &'static str = "\
+[ 62] (s = 53) cache-control: no-cache
+[ 63] (s = 57) :authority: www.example.com
+      Table size: 110/4096
+";
+
+const REQUEST3_TABLE: &'static str = "\
+[ 62] (s = 54) custom-key: custom-value
+[ 63] (s = 53) cache-control: no-cache
+[ 64] (s = 57) :authority: www.example.com
+      Table size: 164/4096
+";
+
+// C.3
+#[test]
+fn requests_without_huffman() {
+    let mut decoder = Decoder::new(4096, 10);
+    let mut encoder = raw_encoder(4096);
+
+    check(&mut decoder, &mut encoder, &request1(),
+        b"\x82\x86\x84\x41\x0fwww.example.com",
+        REQUEST1_TABLE);
+    check(&mut decoder, &mut encoder, &request2(),
+        b"\x82\x86\x84\xbe\x58\x08no-cache",
+        REQUEST2_TABLE);
+    check(&mut decoder, &mut encoder, &request3(),
+        b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value",
+        REQUEST3_TABLE);
+}
+
+// C.4
+#[test]
+fn requests_with_huffman() {
+    let mut decoder = Decoder::new(4096, 10);
+    let mut encoder = huffman_encoder(4096);
+
+    check(&mut decoder, &mut encoder, &request1(), &[
+        0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4,
+        0xff],
+        REQUEST1_TABLE);
+    check(&mut decoder, &mut encoder, &request2(), &[
+        0x82, 0x86, 0x84, 0xbe, 0x58, 0x86, 0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf],
+        REQUEST2_TABLE);
+    check(&mut decoder, &mut encoder, &request3(), &[
+        0x82, 0x87, 0x85, 0xbf, 0x40, 0x88, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xa9, 0x7d, 0x7f, 0x89, 0x25,
+        0xa8, 0x49, 0xe9, 0x5b, 0xb8, 0xe8, 0xb4, 0xbf],
+        REQUEST3_TABLE);
+}
+
+fn response1() -> Vec<HeaderEntry> {
+    headers(&[(":status", "302"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+        ("location", "https://www.example.com")])
+}
+
+fn response2() -> Vec<HeaderEntry> {
+    headers(&[(":status", "307"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+        ("location", "https://www.example.com")])
+}
+
+fn response3() -> Vec<HeaderEntry> {
+    headers(&[(":status", "200"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+        ("location", "https://www.example.com"), ("content-encoding", "gzip"),
+        ("set-cookie", "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1")])
+}
+
+const RESPONSE1_TABLE: &'static str = "\
+[ 62] (s = 63) location: https://www.example.com
+[ 63] (s = 65) date: Mon, 21 Oct 2013 20:13:21 GMT
+[ 64] (s = 52) cache-control: private
+[ 65] (s = 42) :status: 302
+      Table size: 222/256
+";
+
+// :status 302 is evicted
+const RESPONSE2_TABLE: &'static str = "\
+[ 62] (s = 42) :status: 307
+[ 63] (s = 63) location: https://www.example.com
+[ 64] (s = 65) date: Mon, 21 Oct 2013 20:13:21 GMT
+[ 65] (s = 52) cache-control: private
+      Table size: 222/256
+";
+
+// several entries are evicted
+const RESPONSE3_TABLE: &'static str = "\
+[ 62] (s = 98) set-cookie: foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1
+[ 63] (s = 52) content-encoding: gzip
+[ 64] (s = 65) date: Mon, 21 Oct 2013 20:13:22 GMT
+      Table size: 215/256
+";
+
+// C.5
+#[test]
+fn responses_without_huffman() {
+    let mut decoder = Decoder::new(256, 10);
+    let mut encoder = raw_encoder(256);
+
+    check(&mut decoder, &mut encoder, &response1(),
+        b"\x48\x03302\x58\x07private\x61\x1dMon, 21 Oct 2013 20:13:21 GMT\x6e\x17https://www.example.com",
+        RESPONSE1_TABLE);
+    check(&mut decoder, &mut encoder, &response2(),
+        b"\x48\x03307\xc1\xc0\xbf",
+        RESPONSE2_TABLE);
+    check(&mut decoder, &mut encoder, &response3(),
+        b"\x88\xc1\x61\x1dMon, 21 Oct 2013 20:13:22 GMT\xc0\x5a\x04gzip\
+          \x77\x38foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1",
+        RESPONSE3_TABLE);
+}
+
+// C.6
+#[test]
+fn responses_with_huffman() {
+    let mut decoder = Decoder::new(256, 10);
+    let mut encoder = huffman_encoder(256);
+
+    check(&mut decoder, &mut encoder, &response1(), &[
+        0x48, 0x82, 0x64, 0x02, 0x58, 0x85, 0xae, 0xc3, 0x77, 0x1a, 0x4b, 0x61, 0x96, 0xd0, 0x7a, 0xbe,
+        0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95, 0x04, 0x0b, 0x81, 0x66, 0xe0, 0x82, 0xa6,
+        0x2d, 0x1b, 0xff, 0x6e, 0x91, 0x9d, 0x29, 0xad, 0x17, 0x18, 0x63, 0xc7, 0x8f, 0x0b, 0x97, 0xc8,
+        0xe9, 0xae, 0x82, 0xae, 0x43, 0xd3],
+        RESPONSE1_TABLE);
+    check(&mut decoder, &mut encoder, &response2(), &[
+        0x48, 0x83, 0x64, 0x0e, 0xff, 0xc1, 0xc0, 0xbf],
+        RESPONSE2_TABLE);
+    check(&mut decoder, &mut encoder, &response3(), &[
+        0x88, 0xc1, 0x61, 0x96, 0xd0, 0x7a, 0xbe, 0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95,
+        0x04, 0x0b, 0x81, 0x66, 0xe0, 0x84, 0xa6, 0x2d, 0x1b, 0xff, 0xc0, 0x5a, 0x83, 0x9b, 0xd9, 0xab,
+        0x77, 0xad, 0x94, 0xe7, 0x82, 0x1d, 0xd7, 0xf2, 0xe6, 0xc7, 0xb3, 0x35, 0xdf, 0xdf, 0xcd, 0x5b,
+        0x39, 0x60, 0xd5, 0xaf, 0x27, 0x08, 0x7f, 0x36, 0x72, 0xc1, 0xab, 0x27, 0x0f, 0xb5, 0x29, 0x1f,
+        0x95, 0x87, 0x31, 0x60, 0x65, 0xc0, 0x03, 0xed, 0x4e, 0xe5, 0xb1, 0x06, 0x3d, 0x50, 0x07],
+        RESPONSE3_TABLE);
+}
+
+// a reset Decoder and Encoder are the same as new ones, even after
+// a connection with a smaller table (C.5) and a different size limit
+#[test]
+fn reset_matches_new() {
+    let mut decoder = Decoder::new(256, 10);
+    let mut encoder = raw_encoder(256);
+
+    decoder.set_max_header_list_size(1000);
+    check(&mut decoder, &mut encoder, &response1(),
+        b"\x48\x03302\x58\x07private\x61\x1dMon, 21 Oct 2013 20:13:21 GMT\x6e\x17https://www.example.com",
+        RESPONSE1_TABLE);
+
+    decoder.reset();
+    encoder.reset();
+    assert_eq!(decoder.dump_table(), "      Table size: 0/4096\n");
+    assert_eq!(encoder.dump_table(), "      Table size: 0/4096\n");
+
+    // C.3 with no size update in front
+    check(&mut decoder, &mut encoder, &request1(),
+        b"\x82\x86\x84\x41\x0fwww.example.com",
+        REQUEST1_TABLE);
+    check(&mut decoder, &mut encoder, &request2(),
+        b"\x82\x86\x84\xbe\x58\x08no-cache",
+        REQUEST2_TABLE);
+    check(&mut decoder, &mut encoder, &request3(),
+        b"\x82\x87\x85\xbf\x40\x0acustom-key\x0ccustom-value",
+        REQUEST3_TABLE);
+
+    // the size limit is back to 4096
+    assert!(decoder.decode(b"\x3f\xe1\x1f").is_ok());
+    assert!(decoder.decode(b"\x3f\xe2\x1f").is_err());
+
+    // but the header list limit is kept, a 1000 octet value goes over it
+    let mut big = b"\x00\x01a\x7f\xe9\x06".to_vec();
+    big.extend_from_slice(&[b'x'; 1000]);
+    assert_eq!(decoder.decode(&big).err(), Some(HpackError::HeaderListTooLarge.into()));
+}
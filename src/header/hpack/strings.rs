@@ -0,0 +1,206 @@
+/// 5.2 String Literal Representation
+/// Header field names and header field values can be represented as string literals. A string literal is encoded as a sequence of octets, either by directly encoding the string literal's octets or by using a Huffman code (see [HUFFMAN]).
+///
+///   0   1   2   3   4   5   6   7
+/// +---+---+---+---+---+---+---+---+
+/// | H |    String Length (7+)     |
+/// +---+---------------------------+
+/// |  String Data (Length octets)  |
+/// +-------------------------------+
+/// Figure 4: String Literal Representation
+///
+/// The string literal representation contains the following fields:
+///
+/// H: A one-bit flag, H, indicating whether or not the octets of the string are Huffman encoded.
+///
+/// String Length: The number of octets used to encode the string literal, encoded as an integer with a 7-bit prefix (see Section 5.1).
+///
+/// String Data: The encoded data of the string literal. If H is '0', then the encoded data is the raw octets of the string literal. If H is '1', then the encoded data is the Huffman encoding of the string literal (see Section 5.2).
+///
+
+use std::iter::Peekable;
+use std::borrow::Cow;
+
+use borrow_iter::BorrowTake;
+
+use super::HpackError;
+use super::integers::{decode_integer, decode_integer_slice, encode_integer_into, PREFIX_7};
+use super::huffman::Huffman;
+
+// read a string literal (length and data) from bts
+// huffman encoded data is decoded
+pub fn decode_string<'a, I: Iterator<Item=&'a u8>>(bts: &mut Peekable<I>) -> Result<Vec<u8>, HpackError> {
+    // get value length and huffman status
+    let is_huffman = match bts.peek() {
+        Some(b) => *b & 0x80 == 0x80,
+        None    => false, // decode_integer gives the error
+    };
+    let length = try!(decode_integer(bts, PREFIX_7)) as usize;
+
+    // count what is really there to catch a cut off literal
+    // (checked before any huffman error since that is the real problem)
+    let mut taken = 0;
+    let value;
+    {
+        let data = bts.borrow_take(length).inspect(|_| taken += 1);
+        if is_huffman {
+            value = Huffman::new().decode(data);
+        }
+        else {
+            value = Ok(data.map(|x|*x).collect());
+        }
+    }
+
+    if taken != length {
+        return Err(HpackError::TruncatedString);
+    }
+    Ok(try!(value))
+}
+
+// slice form of decode_string, also gives back what is left of input
+//
+// raw strings are borrowed straight out of input, only
+// huffman encoded data has to be decoded into a new buffer
+pub fn decode_string_slice(input: &[u8]) -> Result<(Cow<[u8]>, &[u8]), HpackError> {
+    let is_huffman = match input.first() {
+        Some(b) => *b & 0x80 == 0x80,
+        None    => false, // decode_integer_slice gives the error
+    };
+    let (length, rest) = try!(decode_integer_slice(input, PREFIX_7));
+    let length = length as usize;
+    if rest.len() < length {
+        return Err(HpackError::TruncatedString);
+    }
+
+    let (data, rest) = rest.split_at(length);
+    if is_huffman {
+        Ok((Cow::Owned(try!(Huffman::new().decode(data))), rest))
+    }
+    else {
+        Ok((Cow::Borrowed(data), rest))
+    }
+}
+
+// slice form of decode_string that appends the (decoded) string
+// onto out and gives back what is left of input
+pub fn decode_string_into<'a>(input: &'a [u8], out: &mut Vec<u8>) -> Result<&'a [u8], HpackError> {
+    let is_huffman = match input.first() {
+        Some(b) => *b & 0x80 == 0x80,
+        None    => false, // decode_integer_slice gives the error
+    };
+    let (length, rest) = try!(decode_integer_slice(input, PREFIX_7));
+    let length = length as usize;
+    if rest.len() < length {
+        return Err(HpackError::TruncatedString);
+    }
+
+    let (data, rest) = rest.split_at(length);
+    if is_huffman {
+        try!(Huffman::new().decode_into(data, out));
+    }
+    else {
+        out.extend_from_slice(data);
+    }
+    Ok(rest)
+}
+
+// append the string literal for s onto out
+// the H bit is set when huffman is true
+pub fn encode_string(s: &[u8], huffman: bool, out: &mut Vec<u8>) {
+    if huffman {
+        let huff = Huffman::new();
+        encode_integer_into(huff.encoded_len(s) as u32, out, PREFIX_7, 0x80);
+        huff.encode_into(s, out);
+    }
+    else {
+        encode_integer_into(s.len() as u32, out, PREFIX_7, 0);
+        out.extend_from_slice(s);
+    }
+}
+
+#[cfg(test)]
+mod strings_tests {
+    use super::{decode_string, decode_string_slice, decode_string_into, encode_string};
+    use std::borrow::Cow;
+    use header::hpack::HpackError;
+    use header::hpack::integers::IntegerError;
+
+    #[test]
+    fn raw_string() {
+        let mut out = Vec::new();
+        encode_string(b"custom-key", false, &mut out);
+        assert_eq!(out, b"\x0acustom-key".to_vec());
+
+        let decoded = decode_string(&mut out.iter().peekable()).unwrap();
+        assert_eq!(decoded, b"custom-key");
+    }
+
+    #[test]
+    fn huffman_string() {
+        // RFC 7541 C.4.1
+        let encoded = [0x8C, 0xF1, 0xE3, 0xC2, 0xE5, 0xF2, 0x3A, 0x6B, 0xA0, 0xAB, 0x90, 0xF4, 0xFF];
+
+        let mut out = Vec::new();
+        encode_string(b"www.example.com", true, &mut out);
+        assert_eq!(out, encoded);
+
+        let decoded = decode_string(&mut out.iter().peekable()).unwrap();
+        assert_eq!(decoded, b"www.example.com");
+    }
+
+    #[test]
+    fn leaves_following_octets() {
+        let buf = [0x01, 0x61, 0x82];
+        let mut bts = buf.iter().peekable();
+        assert_eq!(decode_string(&mut bts).unwrap(), b"a");
+        assert_eq!(bts.next(), Some(&0x82));
+    }
+
+    #[test]
+    fn decode_slice() {
+        let buf = [0x01, 0x61, 0x82];
+        match decode_string_slice(&buf).unwrap() {
+            (Cow::Borrowed(s), rest) => {
+                assert_eq!(s, b"a");
+                assert!(s.as_ptr() == buf[1..].as_ptr());
+                assert_eq!(rest, &[0x82]);
+            },
+            _ => panic!("raw string was copied"),
+        }
+
+        // RFC 7541 C.4.1
+        let buf = [0x8C, 0xF1, 0xE3, 0xC2, 0xE5, 0xF2, 0x3A, 0x6B, 0xA0, 0xAB, 0x90, 0xF4, 0xFF];
+        let (s, rest) = decode_string_slice(&buf).unwrap();
+        assert_eq!(&*s, b"www.example.com");
+        assert!(rest.is_empty());
+
+        assert_eq!(decode_string_slice(&[0x05, 0x61, 0x62, 0x63]), Err(HpackError::TruncatedString));
+        assert_eq!(decode_string_slice(&[]), Err(HpackError::Integer(IntegerError::Incomplete)));
+    }
+
+    #[test]
+    fn decode_into() {
+        // raw then huffman (RFC 7541 C.4.1) after what is already there
+        let buf = [0x01, 0x61, 0x8C, 0xF1, 0xE3, 0xC2, 0xE5, 0xF2, 0x3A, 0x6B, 0xA0, 0xAB, 0x90, 0xF4, 0xFF, 0x82];
+        let mut out = b"x".to_vec();
+        let rest = decode_string_into(&buf, &mut out).unwrap();
+        let rest = decode_string_into(rest, &mut out).unwrap();
+        assert_eq!(out, b"xawww.example.com".to_vec());
+        assert_eq!(rest, &[0x82]);
+
+        assert_eq!(decode_string_into(&[0x05, 0x61], &mut out), Err(HpackError::TruncatedString));
+    }
+
+    #[test]
+    fn truncated_string() {
+        // says 5 octets but only has 3
+        let buf = [0x05, 0x61, 0x62, 0x63];
+        assert_eq!(decode_string(&mut buf.iter().peekable()), Err(HpackError::TruncatedString));
+
+        let buf = [0x85, 0xF1, 0xE3];
+        assert_eq!(decode_string(&mut buf.iter().peekable()), Err(HpackError::TruncatedString));
+
+        let buf: [u8; 0] = [];
+        assert_eq!(decode_string(&mut buf.iter().peekable()), Err(HpackError::Integer(IntegerError::Incomplete)));
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use header::*;
+
+use super::static_table::{TableEntry, TableMatch, STATIC_TABLE_LEN};
+
+/// The connection private part of the hpack tables
+///
+/// Entries are kept newest first so that local index 0
+/// is always the last inserted entry (hpack index 62).
+/// The size is tracked as defined in RFC 7541 4.1 and the
+/// oldest entries are evicted to stay within max_size
+pub struct DynamicTable {
+    entries: VecDeque<TableEntry>,
+    current_size: usize,
+    max_size: usize,
+}
+
+impl DynamicTable {
+
+    // allocates for an estimated number of entries
+    // to save reallocations
+    pub fn new(max_size: usize, num_entries: usize) -> Self {
+        DynamicTable {
+            entries: VecDeque::with_capacity(num_entries),
+            current_size: 0,
+            max_size: max_size,
+        }
+    }
+
+    // add a new entry, evicting old ones to make room
+    //
+    // if the entry is bigger than the max size on its own
+    // the table ends up empty as the spec intends
+    pub fn insert<A, B>(&mut self, name: A, value: B)
+        where A: Into<EntryInner>, B: Into<EntryInner> {
+        let name = self.share_name(name.into());
+        let entry = TableEntry::new(name, value);
+        let entry_size = entry.size();
+        // first make sure there is room
+        self.evict(entry_size);
+
+        // still need to check if there is room to add the entry
+        // after eviction. If not then leave the table empty
+        if self.current_size + entry_size <= self.max_size {
+            self.current_size += entry_size;
+            self.entries.push_front(entry);
+        }
+    }
+
+    // get an entry by its local index
+    // 0 is the newest entry (hpack index 62)
+    pub fn get(&self, index: usize) -> Option<&TableEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // find the best entry to represent a header with
+    // a full match beats a name only match, otherwise
+    // the newest entry wins
+    //
+    // this is a linear search, the table is expected to be small
+    pub fn find(&self, name: &str, value: &str) -> TableMatch {
+        let mut name_match = TableMatch::None;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if &*entry.0 == name {
+                if &*entry.1 == value {
+                    return TableMatch::NameAndValue(i);
+                }
+                if name_match == TableMatch::None {
+                    name_match = TableMatch::NameOnly(i);
+                }
+            }
+        }
+        name_match
+    }
+
+    // the current size as calculated by the spec
+    pub fn size(&self) -> usize {
+        self.current_size
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn set_max_size(&mut self, new_max_size: usize) {
+        self.max_size = new_max_size;
+        if new_max_size == 0 {
+            // nothing can stay so skip the one at a time eviction
+            self.clear();
+            return;
+        }
+        // run evict without intention of adding a new entry
+        self.evict(0);
+    }
+
+    // drop every entry (and the Rc's with them) but keep max_size
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.current_size = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // the table contents for debugging compression mismatches
+    // in the same layout as the nghttp2 hpack debug output
+    //
+    // [ 62] (s = 42) name: value
+    //       Table size: 42/4096
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let _ = writeln!(out, "[{:3}] (s = {}) {}: {}",
+                i + STATIC_TABLE_LEN + 1, entry.size(), &*entry.0, &*entry.1);
+        }
+        let _ = writeln!(out, "      Table size: {}/{}", self.current_size, self.max_size);
+        out
+    }
+
+    //=========================================
+    // private utility fn
+    //=========================================
+    // evict entries until size can fit into the table
+    // call this before adding as a check because
+    // eviction only occurs if it is needed
+    fn evict(&mut self, size: usize) {
+        while self.current_size + size > self.max_size {
+            let old_entry = self.entries.pop_back();
+            match old_entry {
+                Some(ref e) => {
+                    self.current_size -= e.size();
+                },
+                None => break, // if there are no more entries don't keep trying to make room
+            }
+        }
+    }
+
+    // use the Rc of an entry with the same name if there is one
+    // so repeated names (eg. set-cookie) are only stored once
+    fn share_name(&self, name: EntryInner) -> EntryInner {
+        if let EntryInner::C(_) = name {
+            for entry in &self.entries {
+                if let EntryInner::C(ref rc) = entry.0 {
+                    if rc.as_str() == &*name {
+                        return EntryInner::C(rc.clone());
+                    }
+                }
+            }
+        }
+        name
+    }
+}
+
+#[cfg(test)]
+mod dynamic_table_tests {
+
+    use super::DynamicTable;
+    use super::super::static_table::TableMatch;
+
+    #[test]
+    fn insert_and_get() {
+        let mut table = DynamicTable::new(4096, 10);
+
+        table.insert("name1", "value1".to_string());
+        table.insert("name2", "value2".to_string());
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.size(), 2 * (5 + 6 + 32));
+
+        let newest = table.get(0).unwrap();
+        assert_eq!((&*newest.0, &*newest.1), ("name2", "value2"));
+        let oldest = table.get(1).unwrap();
+        assert_eq!((&*oldest.0, &*oldest.1), ("name1", "value1"));
+        assert!(table.get(2).is_none());
+    }
+
+    #[test]
+    fn eviction_order() {
+        // room for exactly two entries of size 34
+        let mut table = DynamicTable::new(68, 10);
+
+        table.insert("a", "1");
+        table.insert("b", "2");
+        table.insert("c", "3"); // evicts "a"
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.size(), 68);
+        assert_eq!(&*table.get(0).unwrap().0, "c");
+        assert_eq!(&*table.get(1).unwrap().0, "b");
+    }
+
+    #[test]
+    fn oversized_entry_clears_table() {
+        let mut table = DynamicTable::new(40, 10);
+
+        table.insert("a", "1");
+        assert_eq!(table.len(), 1);
+
+        table.insert("too-big", "for the table");
+        assert_eq!(table.len(), 0);
+        assert_eq!(table.size(), 0);
+    }
+
+    #[test]
+    fn shrink_max_size() {
+        let mut table = DynamicTable::new(100, 10);
+
+        table.insert("a", "1");
+        table.insert("b", "2");
+
+        table.set_max_size(34);
+        assert_eq!(table.len(), 1);
+        assert_eq!(&*table.get(0).unwrap().0, "b");
+        assert_eq!(table.max_size(), 34);
+    }
+
+    #[test]
+    fn clear_table() {
+        use std::rc::Rc;
+        use header::EntryInner;
+
+        let mut table = DynamicTable::new(4096, 10);
+        let name = Rc::new("x-custom".to_string());
+        table.insert(name.clone(), "1");
+        table.insert("a", "2");
+        assert_eq!(Rc::strong_count(&name), 2);
+
+        table.clear();
+        assert!(table.is_empty());
+        assert_eq!(table.size(), 0);
+        assert_eq!(table.max_size(), 4096);
+        assert_eq!(Rc::strong_count(&name), 1);
+
+        // size 0 releases everything too
+        table.insert(EntryInner::C(name.clone()), "1");
+        table.set_max_size(0);
+        assert!(table.is_empty());
+        assert_eq!(table.size(), 0);
+        assert_eq!(Rc::strong_count(&name), 1);
+    }
+
+    #[test]
+    fn insert_zero_max_size() {
+        let mut table = DynamicTable::new(0, 10);
+
+        table.insert("a", "1");
+        table.insert("", "");
+        assert!(table.is_empty());
+        assert_eq!(table.size(), 0);
+        assert!(table.get(0).is_none());
+    }
+
+    #[test]
+    fn find_entries() {
+        let mut table = DynamicTable::new(4096, 10);
+
+        table.insert("a", "1");
+        table.insert("b", "2");
+        table.insert("a", "3");
+
+        assert_eq!(table.find("a", "1"), TableMatch::NameAndValue(2));
+        assert_eq!(table.find("b", "2"), TableMatch::NameAndValue(1));
+        // the newest name match is used
+        assert_eq!(table.find("a", "4"), TableMatch::NameOnly(0));
+        assert_eq!(table.find("c", "1"), TableMatch::None);
+    }
+
+    #[test]
+    fn shared_names() {
+        use std::rc::Rc;
+        use header::EntryInner;
+
+        let mut table = DynamicTable::new(4096, 10);
+
+        table.insert("set-cookie".to_string(), "a=1");
+        table.insert("other".to_string(), "x");
+        table.insert("set-cookie".to_string(), "b=2");
+
+        match (&table.get(0).unwrap().0, &table.get(2).unwrap().0) {
+            (&EntryInner::C(ref a), &EntryInner::C(ref b)) => assert!(Rc::ptr_eq(a, b)),
+            _ => panic!("names should be Rc's"),
+        }
+        match (&table.get(0).unwrap().0, &table.get(1).unwrap().0) {
+            (&EntryInner::C(ref a), &EntryInner::C(ref b)) => assert!(!Rc::ptr_eq(a, b)),
+            _ => panic!("names should be Rc's"),
+        }
+        // sharing does not change the spec size
+        assert_eq!(table.size(), (10 + 3 + 32) * 2 + (5 + 1 + 32));
+    }
+
+    #[test]
+    fn dump_entries() {
+        let mut table = DynamicTable::new(4096, 10);
+        assert_eq!(table.dump(), "      Table size: 0/4096\n");
+
+        table.insert("custom-key", "custom-value");
+        table.insert(":path", "/sample/path".to_string());
+
+        assert_eq!(table.dump(),
+            "[ 62] (s = 49) :path: /sample/path\n\
+             [ 63] (s = 54) custom-key: custom-value\n\
+             \x20     Table size: 103/4096\n");
+    }
+}
@@ -1,20 +1,30 @@
-use std::collections::VecDeque;
-
 use header::*;
+use super::HpackError;
 
 mod static_table;
+mod dynamic_table;
 use self::static_table::{StaticTable, TableEntry};
+pub use self::static_table::{TableMatch, STATIC_TABLE_LEN, entry_size, static_names};
+use self::dynamic_table::DynamicTable;
+
+// hpack index of the newest dynamic table entry
+const DYN_START: usize = STATIC_TABLE_LEN + 1;
+
+/// The initial SETTINGS_HEADER_TABLE_SIZE (RFC 7540 6.5.2), the
+/// dynamic table size a new connection starts with
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
 
-/// the dynamic table used during an HTTP2
-/// hpack encryption context
-pub struct Table {
-    dyn_table: VecDeque<TableEntry>,
-    static_table: StaticTable,
-    current_size: usize,
-    max_size: usize,
+/// The combined static and dynamic tables used during an
+/// HTTP2 hpack compression context
+///
+/// Both tables share one index space, 1..=61 is the static
+/// table and 62.. is the dynamic table newest entry first
+pub struct HeaderTable {
+    dyn_table: DynamicTable,
+    static_table: &'static StaticTable,
 }
 
-impl Table {
+impl HeaderTable {
 
     // the table allocates a VecDeque with an estimated number of entries
     // pre allocated to save reallocations
@@ -22,11 +32,37 @@ impl Table {
     // the max_size is the hpack spec size calculated as the sum of octets in
     // the name and value of each entry plus 32
     pub fn new(max_size: usize, num_entries: usize) -> Self {
-        Table {
-            dyn_table: VecDeque::with_capacity(num_entries),
-            static_table: StaticTable::new(),
-            current_size: 0,
-            max_size: max_size,
+        HeaderTable {
+            dyn_table: DynamicTable::new(max_size, num_entries),
+            static_table: StaticTable::shared(),
+        }
+    }
+
+    // look up an entry in the combined index space
+    //
+    // None for index 0 or anything past the end of the dynamic table
+    pub fn get(&self, index: usize) -> Option<TableEntry> {
+        match index {
+            0            => None,
+            i @ 1 ... STATIC_TABLE_LEN => self.static_table.entry(i),
+            i => self.dyn_table.get(i - DYN_START).cloned(),
+        }
+    }
+
+    // find the best entry in the combined index space to
+    // represent a header with, the index is the hpack index
+    //
+    // a full match in either table beats a name only match,
+    // the static table is preferred when both tables match equally
+    pub fn find(&self, name: &str, value: &str) -> TableMatch {
+        let static_match = self.static_table.find(name, value);
+        if let TableMatch::NameAndValue(_) = static_match {
+            return static_match;
+        }
+        match self.dyn_table.find(name, value) {
+            TableMatch::NameAndValue(i) => TableMatch::NameAndValue(i + DYN_START),
+            TableMatch::NameOnly(i) if static_match == TableMatch::None => TableMatch::NameOnly(i + DYN_START),
+            _ => static_match,
         }
     }
 
@@ -36,21 +72,16 @@ impl Table {
     // must first check that there is room and do eviction if needed
     //
     // add an entry using a name entry that already exists in the table
-    pub fn add_entry_id(&mut self, name_id: usize, value: String) -> Result<(), &'static str> {
-        let name_rc;
-        {
-            let entry = try!(self.get_entry(name_id));
-            name_rc = entry.0.clone();
-        }
-        let new_entry = TableEntry::new(name_rc, value);
-        self.add(new_entry);
+    pub fn add_entry_id(&mut self, name_id: usize, value: String) -> Result<(), HpackError> {
+        let name_rc = try!(self.get_entry(name_id)).0;
+        self.dyn_table.insert(name_rc, value);
         Ok(())
     }
 
     // add a completely new entry
-    pub fn add_entry_literal(&mut self, name: String, value: String) {
-        let new_entry = TableEntry::new(name, value);
-        self.add(new_entry);
+    pub fn add_entry_literal<A, B>(&mut self, name: A, value: B)
+        where A: Into<EntryInner>, B: Into<EntryInner> {
+        self.dyn_table.insert(name, value);
     }
     //=========================================
 
@@ -59,92 +90,62 @@ impl Table {
     //
     // This function takes the local index, so the global
     // entry would be 62 but you would pass 0 as the index
-    pub fn get_header_entry(&self, index: usize) -> Result<HeaderEntry, &'static str> {
+    pub fn get_header_entry(&self, index: usize) -> Result<HeaderEntry, HpackError> {
         let entry = try!(self.get_entry(index));
-        Ok(entry.clone().into())
-    }
-
-    // quicker way to get the latest entry put into the dynamic table
-    // useful when adding literals to the table that are going to
-    // be used straight away in a header list
-    pub fn get_dyn_front(&self) -> HeaderEntry {
-        let t = self.num_dyn_entries();
-        debug_assert!(t > 0);
-        let entry = &self.dyn_table[0];
-        entry.clone().into()
+        Ok(entry.into())
     }
 
     // this is usefull for the functions that construct a header
     // with out modifing the dyn_table
-    pub fn get_name_rc(&self, index: usize) -> Result<EntryInner, &'static str> {
+    pub fn get_name_rc(&self, index: usize) -> Result<EntryInner, HpackError> {
         let entry = try!(self.get_entry(index));
-        Ok(entry.0.clone())
+        Ok(entry.0)
     }
 
     pub fn max_size_update(&mut self, new_max_size: usize) {
-        self.max_size = new_max_size;
-        // run evict without intention of adding a new entry
-        self.evict(0);
+        self.dyn_table.set_max_size(new_max_size);
+    }
+
+    // empty the dynamic table for a new connection, the
+    // allocation for the entries is kept
+    pub fn reset(&mut self, max_size: usize) {
+        self.dyn_table.clear();
+        self.dyn_table.set_max_size(max_size);
     }
 
     pub fn num_dyn_entries(&self) -> usize {
         self.dyn_table.len()
     }
 
+    // size of the dynamic table as calculated by the spec
+    pub fn dyn_size(&self) -> usize {
+        self.dyn_table.size()
+    }
+
+    pub fn dyn_max_size(&self) -> usize {
+        self.dyn_table.max_size()
+    }
+
+    // see DynamicTable::dump
+    pub fn dump(&self) -> String {
+        self.dyn_table.dump()
+    }
+
     //=========================================
     // private utility fn
     //=========================================
     // use the index to get the entry from the correct
     // table : static/dynamic
     // the index given starts at 1 (not 0)
-    fn get_entry(&self, index: usize) -> Result<&TableEntry, &'static str> {
-        // get the length of the dynamic table
-        // to make sure indexing is in range
-        let ne = self.dyn_table.len() + 62;
-        // pull result from static or dynamic table
-        // or return error
-        match index {
-            0            => Err("hpack: index of 0 was found"),
-            i @ 1 ... 61 => Ok(&self.static_table[i - 1]),
-            i if i < ne  => Ok(&self.dyn_table[i - 62]),
-            _            => Err("hpack: index is out of range"),
-        }
-    }
-
-    // evict entries until size can fit into the table
-    // call this before adding as a check because
-    // eviction only occurs if it is needed
-    fn evict(&mut self, size: usize) {
-        while self.current_size + size > self.max_size {
-            let old_entry = self.dyn_table.pop_back();
-            match old_entry {
-                Some(ref e) => {
-                    self.current_size -= Self::size_of_entry(e);
-                },
-                None => break, // if there are no more entries don't keep trying to make room
-            }
-        }
-    }
-
-    fn add(&mut self, entry: TableEntry) {
-        let entry_size = Self::size_of_entry(&entry);
-        // first make sure there is room
-        self.evict(entry_size);
-
-        // still need to check if there is room to add the entry
-        // after eviction. If not then leave the table empty
-        // as this is the spec's intended behaviour
-        if self.current_size + entry_size <= self.max_size {
-            self.current_size += entry_size;
-            self.dyn_table.push_front(entry);
+    //
+    // entries are handed out by value, for the static table this
+    // just borrows the static strings and for the dynamic table
+    // the Rc's are shared
+    fn get_entry(&self, index: usize) -> Result<TableEntry, HpackError> {
+        if index == 0 {
+            return Err(HpackError::ZeroIndex);
         }
-        // if there is no room even after emptying the
-        // entire table, then the add results in an empty table
-    }
-
-    // calculate size according to spec
-    fn size_of_entry(entry: &TableEntry) -> usize {
-        entry.0.len() + entry.1.len() + 32
+        self.get(index).ok_or(HpackError::InvalidIndex(index))
     }
 }
 
@@ -152,11 +153,11 @@ impl Table {
 #[cfg(test)]
 mod dyn_table_tests {
 
-    use super::Table;
+    use super::HeaderTable;
 
     #[test]
     fn test_add() {
-        let mut table = Table::new(100, 10);
+        let mut table = HeaderTable::new(100, 10);
 
         table.add_entry_literal("name1".to_string(), "value1".to_string());
         table.add_entry_id(1, "value2".to_string()).unwrap();
@@ -169,7 +170,7 @@ mod dyn_table_tests {
     #[test]
     #[should_panic]
     fn test_evictions() {
-        let mut table = Table::new(37, 10); // test add
+        let mut table = HeaderTable::new(37, 10); // test add
 
         table.add_entry_literal("nm".to_string(), "val".to_string());
         assert_eq!(table.get_header_entry(62).unwrap(), ("nm", "val").into());
@@ -186,7 +187,7 @@ mod dyn_table_tests {
     #[test]
     #[should_panic]
     fn test_max_size_set() {
-        let mut table = Table::new(200, 10);
+        let mut table = HeaderTable::new(200, 10);
 
         table.add_entry_literal("n".to_string(), "v".to_string());
         table.add_entry_id(62, "z".to_string()).unwrap();
@@ -199,3 +200,115 @@ mod dyn_table_tests {
         let entry = table.get_header_entry(62).unwrap(); // panic here
     }
 }
+
+#[cfg(test)]
+mod header_table_tests {
+
+    use super::{HeaderTable, TableMatch};
+
+    #[test]
+    fn combined_index_space() {
+        let mut table = HeaderTable::new(4096, 10);
+
+        assert!(table.get(0).is_none());
+        assert_eq!(&*table.get(2).unwrap().1, "GET");
+        assert!(table.get(62).is_none());
+
+        table.add_entry_literal("first".to_string(), "1".to_string());
+        table.add_entry_literal("second".to_string(), "2".to_string());
+
+        // newest entry is right after the static table
+        assert_eq!(&*table.get(61).unwrap().0, "www-authenticate");
+        assert_eq!(&*table.get(62).unwrap().0, "second");
+        assert_eq!(&*table.get(63).unwrap().0, "first");
+        assert!(table.get(64).is_none());
+    }
+
+    #[test]
+    fn eviction_shifts_index() {
+        // room for two entries of size 34
+        let mut table = HeaderTable::new(68, 10);
+
+        table.add_entry_literal("a".to_string(), "1".to_string());
+        assert_eq!(&*table.get(62).unwrap().0, "a");
+
+        table.add_entry_literal("b".to_string(), "2".to_string());
+        assert_eq!(&*table.get(63).unwrap().0, "a");
+
+        // "a" is evicted, "b" moves up to 63
+        table.add_entry_literal("c".to_string(), "3".to_string());
+        assert_eq!(&*table.get(62).unwrap().0, "c");
+        assert_eq!(&*table.get(63).unwrap().0, "b");
+        assert!(table.get(64).is_none());
+    }
+
+    // the C.5 responses, the four entries of the first response
+    // fill the 256 octet table and the next one evicts the oldest
+    // (the highest index)
+    #[test]
+    fn renumber_after_eviction() {
+        let mut table = HeaderTable::new(256, 10);
+
+        table.add_entry_id(8, "302".to_string()).unwrap();
+        table.add_entry_id(24, "private".to_string()).unwrap();
+        table.add_entry_id(33, "Mon, 21 Oct 2013 20:13:21 GMT".to_string()).unwrap();
+        // every insert pushed the earlier entries up one
+        assert_eq!(&*table.get(62).unwrap().0, "date");
+        assert_eq!(&*table.get(63).unwrap().0, "cache-control");
+        assert_eq!(&*table.get(64).unwrap().1, "302");
+
+        table.add_entry_id(46, "https://www.example.com".to_string()).unwrap();
+        assert_eq!(table.num_dyn_entries(), 4);
+        assert_eq!(table.dyn_size(), 222);
+        assert_eq!(&*table.get(62).unwrap().0, "location");
+        assert_eq!(&*table.get(65).unwrap().1, "302");
+
+        // C.5.2, :status 307 only fits once :status 302 is evicted
+        table.add_entry_id(8, "307".to_string()).unwrap();
+        assert_eq!(table.num_dyn_entries(), 4);
+        assert_eq!(table.dyn_size(), 222);
+        assert_eq!(&*table.get(62).unwrap().1, "307");
+        assert_eq!(&*table.get(63).unwrap().0, "location");
+        assert_eq!(&*table.get(64).unwrap().0, "date");
+        assert_eq!(&*table.get(65).unwrap().0, "cache-control");
+        assert!(table.get(66).is_none());
+        assert_eq!(table.find("cache-control", "private"), TableMatch::NameAndValue(65));
+        // 302 is gone, only the name is left in the static table
+        assert_eq!(table.find(":status", "302"), TableMatch::NameOnly(8));
+
+        // shrinking the table also drops from the highest index
+        table.max_size_update(42 + 63);
+        assert_eq!(table.num_dyn_entries(), 2);
+        assert_eq!(&*table.get(63).unwrap().0, "location");
+        assert!(table.get(64).is_none());
+    }
+
+    #[test]
+    fn find_combined() {
+        let mut table = HeaderTable::new(4096, 10);
+
+        table.add_entry_literal(":method", "PUT".to_string());
+        table.add_entry_literal("custom-key".to_string(), "1".to_string());
+
+        assert_eq!(table.find(":method", "GET"), TableMatch::NameAndValue(2));
+        assert_eq!(table.find(":method", "PUT"), TableMatch::NameAndValue(63));
+        assert_eq!(table.find("custom-key", "1"), TableMatch::NameAndValue(62));
+        // static names are preferred
+        assert_eq!(table.find(":method", "DELETE"), TableMatch::NameOnly(2));
+        assert_eq!(table.find("custom-key", "2"), TableMatch::NameOnly(62));
+        assert_eq!(table.find("other", "1"), TableMatch::None);
+    }
+
+    #[test]
+    fn shared_static_table() {
+        use std::ptr;
+
+        let a = HeaderTable::new(4096, 10);
+        let b = HeaderTable::new(100, 0);
+
+        assert!(ptr::eq(a.static_table, b.static_table));
+        // and the entries are the same static strings
+        assert_eq!(a.get(2).unwrap().0.as_ptr(), b.get(2).unwrap().0.as_ptr());
+        assert_eq!(a.get(61).unwrap().0.as_ptr(), b.get(61).unwrap().0.as_ptr());
+    }
+}
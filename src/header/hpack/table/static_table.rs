@@ -1,6 +1,10 @@
 use std::ops::Index;
+use std::collections::HashMap;
+use std::iter::Enumerate;
+use std::slice;
 
 use header::*;
+use header::validate::ascii_eq_ignore_case;
 
 // this is basically identical to a HeaderEntry
 // but this provides a lower level interface
@@ -12,6 +16,23 @@ impl TableEntry {
         where A: Into<EntryInner>, B: Into<EntryInner> {
         TableEntry ( name.into(), value.into() )
     }
+
+    // the size of the entry as defined in RFC 7541 4.1
+    pub fn size(&self) -> usize {
+        entry_size(&self.0, &self.1)
+    }
+}
+
+// the overhead the spec adds to every entry (an estimate of the
+// bookkeeping an implementation needs), it is fixed by RFC 7541 4.1
+const ENTRY_OVERHEAD: usize = 32;
+
+/// The size of a header field as defined in RFC 7541 4.1, the
+/// octets in the name and value plus 32
+///
+/// This is used for the dynamic table size and the header list size
+pub fn entry_size(name: &str, value: &str) -> usize {
+    name.len() + value.len() + ENTRY_OVERHEAD
 }
 
 impl Clone for TableEntry {
@@ -26,41 +47,204 @@ impl From<TableEntry> for HeaderEntry {
     }
 }
 
-struct StaticInner (Vec<TableEntry>);
-// this is safe to be sync because the StaticTable
-// interface that uses it provides no mutability
-unsafe impl Sync for StaticInner {}
+/// The distinct header names in the static table, in table order
+///
+/// For deciding if a header is a well known one, eg. to choose
+/// how to compress it
+pub fn static_names() -> &'static [&'static str] {
+    &S_NAMES
+}
+
+/// Number of entries in the hpack static table, the
+/// dynamic table starts at hpack index STATIC_TABLE_LEN + 1
+pub const STATIC_TABLE_LEN: usize = 61;
 
 lazy_static! {
-    static ref S_TABLE: StaticInner = {
-        let mut vec = Vec::with_capacity(STATIC_TABLE.len());
-        for i in STATIC_TABLE {
-            vec.push(TableEntry (i.0.into(), i.1.into()));
+    // each distinct name maps to the range [start, end) of the
+    // (0 based) rows that have it. Entries with the same name
+    // are always next to each other in the static table
+    static ref S_NAME_INDEX: HashMap<&'static str, (usize, usize)> = {
+        let mut map: HashMap<&'static str, (usize, usize)> = HashMap::with_capacity(STATIC_TABLE.len());
+        for (i, entry) in STATIC_TABLE.iter().enumerate() {
+            map.entry(entry.0).or_insert((i, i)).1 = i + 1;
         }
-        drun! {{
-            println!("Initializing static table");
-        }}
-        StaticInner ( vec )
+        map
     };
+
+    // the names in table order with the repeats left out
+    static ref S_NAMES: Vec<&'static str> = {
+        let mut names: Vec<&'static str> = Vec::with_capacity(S_NAME_INDEX.len());
+        for entry in STATIC_TABLE {
+            if names.last() != Some(&entry.0) {
+                names.push(entry.0);
+            }
+        }
+        names
+    };
+
+    static ref S_TABLE: StaticTable = StaticTable {
+        entries: STATIC_TABLE,
+        name_index: &S_NAME_INDEX,
+    };
+}
+
+// result of searching a table for a header
+// for the static table the usize is the hpack index (starting at 1)
+// and for the dynamic table it is the local index (0 is newest)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableMatch {
+    NameAndValue(usize),
+    NameOnly(usize),
+    None,
 }
 
 // rather than just the "actual" static table,
-// I use this statically initialized type so the
-// api between the different header tables is
-// the same everywhere
-pub struct StaticTable (&'static Vec<TableEntry>);
+// I use this type so the api between the different
+// header tables is the same everywhere
+//
+// the entries are read straight out of STATIC_TABLE so
+// nothing is allocated, a TableEntry (which the dynamic
+// table needs) is only made on request and just borrows the
+// &'static str
+pub struct StaticTable {
+    entries: &'static [(&'static str, &'static str)],
+    name_index: &'static HashMap<&'static str, (usize, usize)>,
+}
 
 impl StaticTable {
     pub fn new() -> Self {
-        StaticTable ( &S_TABLE.0 )
+        StaticTable {
+            entries: STATIC_TABLE,
+            name_index: &S_NAME_INDEX,
+        }
+    }
+
+    // the one table every HeaderTable borrows, it never
+    // changes so there is no need for more than one
+    pub fn shared() -> &'static StaticTable {
+        &S_TABLE
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // the raw static data for read only lookups
+    pub fn entries(&self) -> &'static [(&'static str, &'static str)] {
+        self.entries
+    }
+
+    // make a TableEntry for the entry at hpack_index
+    // for use with the dynamic table, no allocation happens
+    pub fn entry(&self, hpack_index: usize) -> Option<TableEntry> {
+        self.get(hpack_index).map(|(name, value)| TableEntry::new(name, value))
+    }
+
+    // look up an entry by its hpack index
+    // hpack indices start at 1 (0 is reserved) so this
+    // takes care of the offset into the underlying table
+    //
+    // returns None for 0 or anything past the end of the table
+    pub fn get(&self, hpack_index: usize) -> Option<(&'static str, &'static str)> {
+        if hpack_index == 0 {
+            return None;
+        }
+        self.entries.get(hpack_index - 1).map(|e| *e)
+    }
+
+    // find the best entry to represent a header with
+    // a full match beats a name only match, if there are several
+    // entries with the name but no value match the first is returned
+    //
+    // names in the table are all lowercase, a name with uppercase
+    // letters still matches ignoring case (values are always exact)
+    pub fn find(&self, name: &str, value: &str) -> TableMatch {
+        let (start, end) = match self.name_range(name) {
+            Some(range) => range,
+            None        => return TableMatch::None,
+        };
+        for i in start..end {
+            if self.entries[i].1 == value {
+                return TableMatch::NameAndValue(i + 1);
+            }
+        }
+        TableMatch::NameOnly(start + 1)
+    }
+
+    // hpack index of the first entry with the name, this is
+    // the name index used for literal representations
+    //
+    // names like :status have several entries, the lowest
+    // index is always given so the output is deterministic
+    pub fn find_name(&self, name: &str) -> Option<usize> {
+        self.name_range(name).map(|(start, _)| start + 1)
+    }
+}
+
+impl StaticTable {
+    // the (0 based) rows with the name, see S_NAME_INDEX
+    fn name_range(&self, name: &str) -> Option<(usize, usize)> {
+        match self.name_index.get(name) {
+            Some(range) => Some(*range),
+            None        => self.find_name_ignore_case(name),
+        }
+    }
+
+    // slow path for names that are not lowercase
+    // entries with the same name are next to each other
+    fn find_name_ignore_case(&self, name: &str) -> Option<(usize, usize)> {
+        if !name.bytes().any(|b| b.is_ascii_uppercase()) {
+            return None;
+        }
+        let start = match self.entries.iter().position(|e| ascii_eq_ignore_case(name, e.0)) {
+            Some(start) => start,
+            None        => return None,
+        };
+        let len = self.entries[start..].iter().take_while(|e| e.0 == self.entries[start].0).count();
+        Some((start, start + len))
+    }
+
+    // walk the table in index order, the index given
+    // with each entry is the hpack index (starting at 1)
+    pub fn iter(&self) -> StaticIter {
+        StaticIter { inner: self.entries.iter().enumerate() }
+    }
+}
+
+impl<'a> IntoIterator for &'a StaticTable {
+    type Item = (usize, &'static str, &'static str);
+    type IntoIter = StaticIter;
+
+    fn into_iter(self) -> StaticIter {
+        self.iter()
+    }
+}
+
+// yields (hpack index, name, value) straight from the static data
+pub struct StaticIter {
+    inner: Enumerate<slice::Iter<'static, (&'static str, &'static str)>>,
+}
+
+impl Iterator for StaticIter {
+    type Item = (usize, &'static str, &'static str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(i, &(name, value))| (i + 1, name, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
     }
 }
 
+// row (0 based) access for indices known to be in range, this
+// panics past the end so anything read off the wire must go
+// through get instead
 impl Index<usize> for StaticTable {
-    type Output = TableEntry;
+    type Output = (&'static str, &'static str);
 
-    fn index<'a>(&'a self, _index: usize) -> &'a TableEntry {
-        &self.0[_index]
+    fn index<'a>(&'a self, _index: usize) -> &'a (&'static str, &'static str) {
+        &self.entries[_index]
     }
 }
 
@@ -132,10 +316,195 @@ static STATIC_TABLE: &'static [(&'static str, &'static str)] = &[
 #[cfg(test)]
 mod static_table_tests {
 
-    use super::STATIC_TABLE;
+    use super::{STATIC_TABLE, STATIC_TABLE_LEN, StaticTable, TableMatch, TableEntry, entry_size};
+    use super::static_names;
 
     #[test]
     fn valid_static_table() {
         assert_eq!(STATIC_TABLE.len(), 61);
+        assert_eq!(STATIC_TABLE_LEN, STATIC_TABLE.len());
+        assert_eq!(StaticTable::new().len(), STATIC_TABLE_LEN);
+    }
+
+    // guards against accidental edits to STATIC_TABLE, the values
+    // are from RFC 7541 Appendix A
+    #[test]
+    fn static_table_invariants() {
+        let table = StaticTable::new();
+
+        assert_eq!(table.get(1), Some((":authority", "")));
+        assert_eq!(table.get(2), Some((":method", "GET")));
+        assert_eq!(table.get(3), Some((":method", "POST")));
+        assert_eq!(table.get(4), Some((":path", "/")));
+        assert_eq!(table.get(7), Some((":scheme", "https")));
+        let status: Vec<_> = (8..15).map(|i| table.get(i).unwrap()).collect();
+        assert_eq!(status, vec![(":status", "200"), (":status", "204"), (":status", "206"),
+            (":status", "304"), (":status", "400"), (":status", "404"), (":status", "500")]);
+        assert_eq!(table.get(16), Some(("accept-encoding", "gzip, deflate")));
+
+        for &(name, _) in STATIC_TABLE {
+            assert!(!name.is_empty());
+            assert!(!name.bytes().any(|b| b.is_ascii_uppercase() || b == b' ' || b == b'\t'), "bad name {:?}", name);
+        }
+        // no stray whitespace from transcribing the values
+        for &(_, value) in STATIC_TABLE {
+            assert_eq!(value.trim(), value);
+        }
+    }
+
+    #[test]
+    fn names() {
+        let names = static_names();
+
+        assert!(names.contains(&"content-type"));
+        assert!(names.contains(&":status"));
+        assert!(!names.contains(&"x-custom"));
+        // :method, :path, :scheme have 2 entries and :status 7
+        assert_eq!(names.len(), STATIC_TABLE_LEN - 9);
+        assert_eq!(&names[..3], &[":authority", ":method", ":path"]);
+        assert_eq!(names.last(), Some(&"www-authenticate"));
+    }
+
+    #[test]
+    fn get_by_hpack_index() {
+        let table = StaticTable::new();
+
+        assert_eq!(table.get(0), None);
+        assert_eq!(table.get(1), Some((":authority", "")));
+        assert_eq!(table.get(2), Some((":method", "GET")));
+        assert_eq!(table.get(61), Some(("www-authenticate", "")));
+        assert_eq!(table.get(62), None);
+        assert_eq!(table.get(usize::max_value()), None);
+        assert!(table.entry(62).is_none());
+        assert!(table.entry(usize::max_value()).is_none());
+    }
+
+    #[test]
+    fn entries_are_borrowed() {
+        let table = StaticTable::new();
+
+        // the table is the static data itself
+        assert!(table.entries().as_ptr() == STATIC_TABLE.as_ptr());
+
+        let entry = table.entry(2).unwrap();
+        assert_eq!(&*entry.0, ":method");
+        assert_eq!(&*entry.1, "GET");
+        assert!(entry.0.as_ptr() == STATIC_TABLE[1].0.as_ptr());
+        assert!(table.entry(0).is_none());
+    }
+
+    #[test]
+    fn iterate_entries() {
+        let table = StaticTable::new();
+
+        assert_eq!(table.iter().count(), 61);
+
+        let mut iter = table.iter();
+        assert_eq!(iter.next(), Some((1, ":authority", "")));
+        assert_eq!(iter.next(), Some((2, ":method", "GET")));
+        assert_eq!(iter.last(), Some((61, "www-authenticate", "")));
+
+        // every index agrees with get
+        for (i, name, value) in &table {
+            assert_eq!(table.get(i), Some((name, value)));
+            assert!(name.as_ptr() == STATIC_TABLE[i - 1].0.as_ptr());
+        }
+    }
+
+    #[test]
+    fn find_entries() {
+        let table = StaticTable::new();
+
+        assert_eq!(table.find(":method", "GET"), TableMatch::NameAndValue(2));
+        assert_eq!(table.find(":method", "POST"), TableMatch::NameAndValue(3));
+        assert_eq!(table.find(":method", "PUT"), TableMatch::NameOnly(2));
+        assert_eq!(table.find(":status", "404"), TableMatch::NameAndValue(13));
+        assert_eq!(table.find(":status", "418"), TableMatch::NameOnly(8));
+        assert_eq!(table.find("content-type", "text/html"), TableMatch::NameOnly(31));
+        assert_eq!(table.find("x-custom", "1"), TableMatch::None);
+        // value compare is exact
+        assert_eq!(table.find(":method", "get"), TableMatch::NameOnly(2));
+        // names are not
+        assert_eq!(table.find("Content-Type", "text/html"), TableMatch::NameOnly(31));
+        assert_eq!(table.find(":METHOD", "POST"), TableMatch::NameAndValue(3));
+        assert_eq!(table.find("X-Custom", "1"), TableMatch::None);
+    }
+
+    #[test]
+    fn entry_sizes() {
+        let entry = TableEntry::new("custom-key", String::from("custom-header"));
+        assert_eq!(entry.size(), "custom-key".len() + "custom-header".len() + 32);
+        assert_eq!(entry.size(), 55);
+        assert_eq!(TableEntry::new("", "").size(), 32);
+        assert_eq!(entry_size(":authority", "www.example.com"), 57);
+    }
+
+    #[test]
+    fn find_by_name() {
+        let table = StaticTable::new();
+
+        assert_eq!(table.find_name("content-type"), Some(31));
+        assert_eq!(table.find_name("x-custom"), None);
+        // lowest index when the name has several entries
+        assert_eq!(table.find_name(":status"), Some(8));
+        assert_eq!(table.find_name(":method"), Some(2));
+        assert_eq!(table.find_name("Content-Type"), Some(31));
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod static_table_benches {
+
+    use test::Bencher;
+    use super::{STATIC_TABLE, StaticTable, TableMatch};
+
+    // a typical set of response headers
+    static RESPONSE: &'static [(&'static str, &'static str)] = &[
+        (":status", "200"),
+        ("cache-control", "private, max-age=0"),
+        ("content-encoding", "gzip"),
+        ("content-type", "text/html; charset=utf-8"),
+        ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+        ("expires", "-1"),
+        ("server", "kurisu"),
+        ("set-cookie", "id=a3fWa; Max-Age=2592000"),
+        ("vary", "accept-encoding"),
+        ("x-frame-options", "SAMEORIGIN"),
+    ];
+
+    // the old way of searching for comparison
+    fn find_linear(name: &str, value: &str) -> TableMatch {
+        let mut name_match = TableMatch::None;
+        for (i, entry) in STATIC_TABLE.iter().enumerate() {
+            if entry.0 != name {
+                continue;
+            }
+            if entry.1 == value {
+                return TableMatch::NameAndValue(i + 1);
+            }
+            if name_match == TableMatch::None {
+                name_match = TableMatch::NameOnly(i + 1);
+            }
+        }
+        name_match
+    }
+
+    #[bench]
+    fn find_linear_response(b: &mut Bencher) {
+        b.iter(|| {
+            for &(name, value) in RESPONSE {
+                ::test::black_box(find_linear(name, value));
+            }
+        });
+    }
+
+    #[bench]
+    fn find_indexed_response(b: &mut Bencher) {
+        let table = StaticTable::new();
+        b.iter(|| {
+            for &(name, value) in RESPONSE {
+                ::test::black_box(table.find(name, value));
+            }
+        });
     }
 }
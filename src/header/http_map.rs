@@ -0,0 +1,184 @@
+//! Conversions between header lists and the http crate's HeaderMap
+//! (only with the http feature)
+//!
+//! A HeaderMap can not hold pseudo headers (names starting with ':')
+//! so those are kept to the side, in the order they came in
+
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+
+use std::str;
+
+use header::{HeaderEntry, Decoder, Encoder, ProtocolError};
+use frame::Http2Error;
+
+/// The fields of a header block as a HeaderMap
+/// plus the pseudo headers next to it
+///
+/// The sensitive flag of an entry is kept with
+/// HeaderValue::set_sensitive
+#[derive(Debug, Clone, Default)]
+pub struct HeaderFields {
+    pub pseudo: Vec<HeaderEntry>,
+    pub map: HeaderMap,
+}
+
+impl HeaderFields {
+    pub fn new() -> Self {
+        HeaderFields { pseudo: Vec::new(), map: HeaderMap::new() }
+    }
+
+    // split the entries into pseudo headers and the map
+    //
+    // names are checked the same way the Decoder checks them and
+    // values can not have anything HeaderValue does not allow, octets
+    // above 0x7f (obs-text, eg. UTF-8) are kept as they are
+    pub fn from_entries<I>(entries: I) -> Result<HeaderFields, ProtocolError>
+        where I: IntoIterator<Item=HeaderEntry> {
+        let mut fields = HeaderFields::new();
+        for entry in entries {
+            try!(entry.validate_name());
+            if entry.name().starts_with(':') {
+                fields.pseudo.push(entry);
+                continue;
+            }
+            let name = HeaderName::from_bytes(entry.name().as_bytes()).expect("name was validated");
+            let mut value = try!(HeaderValue::from_bytes(entry.value().as_bytes()).map_err(|_| ProtocolError::InvalidValue));
+            value.set_sensitive(entry.is_sensitive());
+            fields.map.append(name, value);
+        }
+        Ok(fields)
+    }
+
+    // the pseudo headers first (as HTTP/2 requires) then the
+    // map, values with the same name are kept together
+    //
+    // a HeaderValue can hold octets that are not UTF-8, those
+    // are an InvalidValue instead of being changed
+    pub fn to_entries(&self) -> Result<Vec<HeaderEntry>, ProtocolError> {
+        let mut entries = self.pseudo.clone();
+        entries.reserve(self.map.len());
+        for (name, value) in &self.map {
+            let v = try!(str::from_utf8(value.as_bytes()).map_err(|_| ProtocolError::InvalidValue));
+            entries.push(HeaderEntry::new(String::from(name.as_str()), String::from(v)).with_sensitive(value.is_sensitive()));
+        }
+        Ok(entries)
+    }
+
+    // the value of a pseudo header eg. ":path"
+    pub fn pseudo_value(&self, name: &str) -> Option<&str> {
+        self.pseudo.iter().find(|e| e.name() == name).map(|e| e.value())
+    }
+}
+
+impl Decoder {
+    /// Same as decode but the fields come out as HeaderFields
+    pub fn decode_header_map(&mut self, hpack_block: &[u8]) -> Result<HeaderFields, Http2Error> {
+        let entries = try!(self.decode(hpack_block));
+        Ok(try!(HeaderFields::from_entries(entries)))
+    }
+}
+
+impl Encoder {
+    /// Encode HeaderFields, see HeaderFields::to_entries for the order
+    /// and the values that can not be sent
+    pub fn encode_header_map(&mut self, fields: &HeaderFields) -> Result<Vec<u8>, ProtocolError> {
+        let entries = try!(fields.to_entries());
+        Ok(self.encode(&entries))
+    }
+}
+
+#[cfg(test)]
+mod http_map_tests {
+
+    use http::header::{HeaderMap, HeaderValue, CONTENT_TYPE, COOKIE, AUTHORIZATION};
+
+    use header::{Decoder, Encoder, HeaderEntry, HpackError, ProtocolError};
+    use super::HeaderFields;
+
+    fn request() -> Vec<HeaderEntry> {
+        vec![
+            (":method", "GET").into(),
+            (":path", "/index.html").into(),
+            ("content-type", "text/html").into(),
+            ("cookie", "a=1").into(),
+            ("cookie", "b=2").into(),
+            HeaderEntry::new("authorization", "secret-token").with_sensitive(true),
+        ]
+    }
+
+    #[test]
+    fn decode_into_map() {
+        let block = Encoder::new(4096, 10).encode(&request());
+        let fields = Decoder::new(4096, 10).decode_header_map(&block).unwrap();
+
+        assert_eq!(fields.pseudo, vec![(":method", "GET").into(), (":path", "/index.html").into()]);
+        assert_eq!(fields.pseudo_value(":path"), Some("/index.html"));
+        assert_eq!(fields.map.len(), 4);
+        assert_eq!(fields.map[CONTENT_TYPE], "text/html");
+        let cookies: Vec<&HeaderValue> = fields.map.get_all(COOKIE).iter().collect();
+        assert_eq!(cookies, vec!["a=1", "b=2"]);
+        // never indexed on the wire comes out sensitive
+        assert!(fields.map[AUTHORIZATION].is_sensitive());
+        assert!(!fields.map[CONTENT_TYPE].is_sensitive());
+    }
+
+    #[test]
+    fn map_round_trip() {
+        let mut fields = HeaderFields::new();
+        fields.pseudo.push((":status", "200").into());
+        fields.map.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let mut token = HeaderValue::from_static("secret");
+        token.set_sensitive(true);
+        fields.map.insert(AUTHORIZATION, token);
+
+        let mut encoder = Encoder::new(4096, 10);
+        let block = encoder.encode_header_map(&fields).unwrap();
+        // the sensitive value stays out of the table
+        assert_eq!(encoder.dump_table(), "[ 62] (s = 54) content-type: text/plain\n      Table size: 54/4096\n");
+
+        let mut decoder = Decoder::new(4096, 10);
+        let entries = decoder.decode(&block).unwrap();
+        assert_eq!(entries, fields.to_entries().unwrap());
+        assert_eq!(entries[0], (":status", "200").into());
+        assert!(entries[2].is_sensitive());
+
+        let back = HeaderFields::from_entries(entries).unwrap();
+        assert_eq!(back.pseudo, fields.pseudo);
+        assert_eq!(back.map, fields.map);
+        assert!(back.map[AUTHORIZATION].is_sensitive());
+    }
+
+    #[test]
+    fn non_ascii_round_trip() {
+        let entries: Vec<HeaderEntry> = vec![("x-name", "caf\u{e9} \u{2603}").into()];
+        let fields = HeaderFields::from_entries(entries.clone()).unwrap();
+        assert_eq!(fields.map["x-name"].as_bytes(), "caf\u{e9} \u{2603}".as_bytes());
+        assert_eq!(fields.to_entries().unwrap(), entries);
+
+        let mut decoder = Decoder::new(4096, 10);
+        let block = Encoder::new(4096, 10).encode_header_map(&fields).unwrap();
+        assert_eq!(decoder.decode(&block).unwrap(), entries);
+
+        // latin-1 obs-text is fine for HeaderValue but is not UTF-8
+        let mut fields = HeaderFields::new();
+        fields.map.insert("x-name", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        assert_eq!(fields.to_entries().err(), Some(ProtocolError::InvalidValue));
+        assert_eq!(Encoder::new(4096, 10).encode_header_map(&fields).err(), Some(ProtocolError::InvalidValue));
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let bad_name: Vec<HeaderEntry> = vec![("Content-Type", "text/html").into()];
+        assert_eq!(HeaderFields::from_entries(bad_name).err(), Some(ProtocolError::UppercaseName));
+
+        let bad_value: Vec<HeaderEntry> = vec![("x-custom", "a\r\nb").into()];
+        assert_eq!(HeaderFields::from_entries(bad_value).err(), Some(ProtocolError::InvalidValue));
+
+        // raw literal x-custom: a<LF>b
+        let mut decoder = Decoder::new(4096, 10);
+        assert_eq!(decoder.decode_header_map(b"\x00\x08x-custom\x03a\nb").err(),
+            Some(HpackError::Protocol(ProtocolError::InvalidValue).into()));
+
+        assert_eq!(HeaderFields::from_entries(vec![]).unwrap().map, HeaderMap::new());
+    }
+}
@@ -6,8 +6,14 @@
 //! is used
 
 use std::rc::Rc;
+use std::borrow::Cow;
 use std::slice::Iter;
 use std::ops::Deref;
+use std::hash::{Hash, Hasher};
+use std::str::{self, Utf8Error};
+
+use super::validate::{self, ProtocolError};
+use super::hpack::decoder::FieldStore;
 
 // internal type to manage entries from the shared
 // static table and the connection private dynamic table
@@ -63,17 +69,49 @@ impl From<String> for EntryInner {
     }
 }
 
+impl From<Cow<'static, str>> for EntryInner {
+    fn from(c: Cow<'static, str>) -> EntryInner {
+        match c {
+            Cow::Borrowed(r) => EntryInner::R(r),
+            Cow::Owned(c)    => EntryInner::C(Rc::new(c)),
+        }
+    }
+}
+
+impl From<EntryInner> for Cow<'static, str> {
+    // static strings stay borrowed, the string is only copied
+    // when it is still shared (eg. with the dynamic table)
+    fn from(e: EntryInner) -> Cow<'static, str> {
+        match e {
+            EntryInner::R(r) => Cow::Borrowed(r),
+            EntryInner::C(c) => Cow::Owned(Rc::try_unwrap(c).unwrap_or_else(|c| (*c).clone())),
+        }
+    }
+}
+
 /// Header list entry with owed or borrowed string
-#[derive(Debug)]
+///
+/// This is what goes into the Encoder and comes out of the Decoder.
+/// The name and value can be a &'static str (no allocation, this is
+/// what static table hits use) or a String
+///
+/// ```ignore
+/// let entry = HeaderEntry::new("content-type", "text/html");
+/// let secret = HeaderEntry::new("authorization", token).with_sensitive(true);
+/// ```
+#[derive(Debug, Clone)]
 pub struct HeaderEntry {
     name: EntryInner,
     value: EntryInner,
+    // came in (or must go out) as never indexed
+    sensitive: bool,
 }
 
 impl HeaderEntry {
+    // not sensitive by default
     pub fn new<A, B>(name: A, value: B) -> Self
         where A: Into<EntryInner>, B: Into<EntryInner> {
-        HeaderEntry { name: name.into(), value: value.into() }
+        HeaderEntry { name: name.into(), value: value.into(), sensitive: false }
     }
 }
 // turn a tuple into a HeaderEntry from a &str
@@ -83,18 +121,32 @@ impl<A, B> From<(A, B)> for HeaderEntry
     where A: Into<EntryInner>, B: Into<EntryInner> {
 
     fn from(obj: (A, B)) -> HeaderEntry {
-        HeaderEntry { name: obj.0.into(), value: obj.1.into() }
+        HeaderEntry::new(obj.0, obj.1)
     }
 }
 
-// this is mostly for easy debug
+// equal when the name, value and sensitive flag all are, it does
+// not matter if the strings are static or shared with a table
+//
+// names are compared as they are (they are lowercase by the time
+// they are in a HeaderEntry from the Decoder)
 impl PartialEq for HeaderEntry {
     fn eq(&self, other: &HeaderEntry) -> bool {
         self.name() == other.name() && self.value() == other.value()
+            && self.sensitive == other.sensitive
     }
 }
 impl Eq for HeaderEntry {}
 
+// must agree with PartialEq
+impl Hash for HeaderEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name().hash(state);
+        self.value().hash(state);
+        self.sensitive.hash(state);
+    }
+}
+
 impl HeaderEntry {
     pub fn name(&self) -> &str {
         self.name.as_ref()
@@ -102,6 +154,191 @@ impl HeaderEntry {
     pub fn value(&self) -> &str {
         self.value.as_ref()
     }
+
+    // sensitive headers are never put in a dynamic table
+    // and must be forwarded as never indexed
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
+    pub fn set_sensitive(&mut self, sensitive: bool) {
+        self.sensitive = sensitive;
+    }
+    // builder form of set_sensitive
+    pub fn with_sensitive(mut self, sensitive: bool) -> Self {
+        self.sensitive = sensitive;
+        self
+    }
+
+    // check the name is allowed in HTTP/2
+    // lowercase token characters with an optional leading ':'
+    pub fn validate_name(&self) -> Result<(), ProtocolError> {
+        validate::validate_name(self.name())
+    }
+}
+
+/// A decoded header that does not hold on to any connection state
+///
+/// Unlike HeaderEntry nothing is shared with the dynamic table.
+/// Static table hits borrow the &'static str, and with
+/// Decoder::decode_borrowed raw literals borrow the input block,
+/// everything else is owned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BorrowedHeader<'a> {
+    pub name: Cow<'a, str>,
+    pub value: Cow<'a, str>,
+    pub sensitive: bool,
+}
+
+/// A BorrowedHeader that only borrows static table strings
+/// so it can be kept around or sent to other threads
+pub type DecodedHeader = BorrowedHeader<'static>;
+
+impl From<HeaderEntry> for DecodedHeader {
+    fn from(entry: HeaderEntry) -> DecodedHeader {
+        DecodedHeader {
+            name: entry.name.into(),
+            value: entry.value.into(),
+            sensitive: entry.sensitive,
+        }
+    }
+}
+
+// back into an entry for encoding, sensitive is kept so
+// a proxy sends it on never indexed
+impl From<DecodedHeader> for HeaderEntry {
+    fn from(header: DecodedHeader) -> HeaderEntry {
+        HeaderEntry::new(header.name, header.value).with_sensitive(header.sensitive)
+    }
+}
+
+/// One reusable buffer for the decoded fields of a header block
+///
+/// Decoder::decode_arena copies every name and value into the same
+/// buffer instead of making a String for each. Reset it between
+/// requests and the memory is used again. The fields are read back
+/// as BorrowedHeader views into the buffer
+pub struct HeaderArena {
+    // only ever has whole checked strs added to it
+    buf: String,
+    fields: Vec<ArenaField>,
+}
+
+/// Where a name or value went in a HeaderArena
+///
+/// Only the arena makes these, it is the Str of its FieldStore
+#[derive(Debug, Clone, Copy)]
+pub struct ArenaStr(usize, usize);
+
+struct ArenaField {
+    name: ArenaStr,
+    value: ArenaStr,
+    sensitive: bool,
+}
+
+impl HeaderArena {
+    pub fn new() -> Self {
+        HeaderArena::with_capacity(0, 0)
+    }
+
+    // octets is for all of the names and values together
+    pub fn with_capacity(octets: usize, fields: usize) -> Self {
+        HeaderArena { buf: String::with_capacity(octets), fields: Vec::with_capacity(fields) }
+    }
+
+    // forget the fields but keep the memory for the next block
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.fields.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    // octets used by the names and values
+    pub fn octets(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<BorrowedHeader> {
+        self.fields.get(index).map(|f| BorrowedHeader {
+            name: Cow::Borrowed(self.str_at(f.name)),
+            value: Cow::Borrowed(self.str_at(f.value)),
+            sensitive: f.sensitive,
+        })
+    }
+
+    pub fn iter(&self) -> ArenaIter {
+        ArenaIter { arena: self, index: 0 }
+    }
+
+    // copy s in and get where it went
+    fn push_str(&mut self, s: &str) -> ArenaStr {
+        let start = self.buf.len();
+        self.buf.push_str(s);
+        ArenaStr(start, self.buf.len())
+    }
+
+    // the buf only holds whole strs so this can only go wrong
+    // for a range from some other arena, which panics
+    fn str_at(&self, range: ArenaStr) -> &str {
+        &self.buf[range.0..range.1]
+    }
+}
+
+// Decoder::decode_arena, every name and value is copied
+// into the buf once it is known to be UTF-8
+impl<'a> FieldStore<'a> for HeaderArena {
+    type Str = ArenaStr;
+
+    fn decoded(&mut self, octets: &[u8]) -> Result<ArenaStr, Utf8Error> {
+        let s = try!(str::from_utf8(octets));
+        Ok(self.push_str(s))
+    }
+    fn entry(&mut self, entry: EntryInner) -> ArenaStr {
+        self.push_str(&entry)
+    }
+    // the table needs its own copy
+    fn to_entry(&self, s: &ArenaStr) -> EntryInner {
+        String::from(self.str_at(*s)).into()
+    }
+    fn as_str<'s>(&'s self, s: &'s ArenaStr) -> &'s str {
+        self.str_at(*s)
+    }
+    fn push(&mut self, name: ArenaStr, value: ArenaStr, sensitive: bool) {
+        self.fields.push(ArenaField { name: name, value: value, sensitive: sensitive });
+    }
+}
+
+// the fields of a HeaderArena in order
+pub struct ArenaIter<'a> {
+    arena: &'a HeaderArena,
+    index: usize,
+}
+
+impl<'a> Iterator for ArenaIter<'a> {
+    type Item = BorrowedHeader<'a>;
+
+    fn next(&mut self) -> Option<BorrowedHeader<'a>> {
+        let header = self.arena.get(self.index);
+        if header.is_some() {
+            self.index += 1;
+        }
+        header
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderArena {
+    type Item = BorrowedHeader<'a>;
+    type IntoIter = ArenaIter<'a>;
+
+    fn into_iter(self) -> ArenaIter<'a> {
+        self.iter()
+    }
 }
 
 /// Header list to abstract the underlying memory management.
@@ -118,6 +355,10 @@ impl HeaderList {
         self.0.push(entry);
     }
 
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
     // this function is useful for reading the headers that you expect
     // from a request
     pub fn get_value_by_name(&self, _name: &str) -> Option<&str> {
@@ -137,6 +378,48 @@ impl HeaderList {
     }
 }
 
+/// Join the cookie crumbs of a decoded header list back into
+/// a single cookie header (RFC 7540 8.1.2.5)
+///
+/// Encoders may split a cookie into one field per crumb to
+/// compress better, this is not done by the Decoder so the
+/// crumbs are kept unless this is called on the result.
+/// The merged cookie takes the place of the first crumb and
+/// is sensitive if any of the crumbs were
+pub fn merge_cookies(headers: &mut Vec<HeaderEntry>) {
+    let mut value = String::new();
+    let mut sensitive = false;
+    let mut crumbs = 0;
+    for entry in headers.iter().filter(|h| h.name() == "cookie") {
+        if crumbs > 0 {
+            value.push_str("; ");
+        }
+        value.push_str(entry.value());
+        sensitive |= entry.is_sensitive();
+        crumbs += 1;
+    }
+    if crumbs < 2 {
+        return;
+    }
+
+    // nothing before the first crumb is removed so it keeps its index
+    let first = headers.iter().position(|h| h.name() == "cookie").unwrap();
+    let mut i = 0;
+    headers.retain(|h| {
+        let keep = i == first || h.name() != "cookie";
+        i += 1;
+        keep
+    });
+    let name = headers[first].name.clone();
+    headers[first] = HeaderEntry::new(name, value).with_sensitive(sensitive);
+}
+
+impl From<Vec<HeaderEntry>> for HeaderList {
+    fn from(entries: Vec<HeaderEntry>) -> HeaderList {
+        HeaderList(entries)
+    }
+}
+
 #[cfg(test)]
 mod header_list_tests {
 
@@ -158,4 +441,95 @@ mod header_list_tests {
             assert_eq!(entry.value(), "local");
         }
     }
+
+    #[test]
+    fn build_entries() {
+        use header::HeaderEntry;
+
+        let entry = HeaderEntry::new("content-type", "text/html");
+        assert_eq!(entry.name(), "content-type");
+        assert_eq!(entry.value(), "text/html");
+        assert!(!entry.is_sensitive());
+
+        let token = String::from("secret-token");
+        let entry = HeaderEntry::new("authorization", token).with_sensitive(true);
+        assert_eq!(entry.name(), "authorization");
+        assert_eq!(entry.value(), "secret-token");
+        assert!(entry.is_sensitive());
+
+        let mut copy = entry.clone();
+        assert!(copy.is_sensitive());
+        assert_eq!(copy, entry);
+        copy.set_sensitive(false);
+        assert!(!copy.is_sensitive());
+        assert!(copy != entry);
+
+        let entry: HeaderEntry = (String::from("x-custom"), "1").into();
+        assert_eq!((entry.name(), entry.value()), ("x-custom", "1"));
+    }
+
+    #[test]
+    fn entry_eq_and_hash() {
+        use std::collections::HashSet;
+        use std::rc::Rc;
+        use header::HeaderEntry;
+
+        let plain = HeaderEntry::new("authorization", "token");
+        let secret = HeaderEntry::new("authorization", "token").with_sensitive(true);
+        assert!(plain != secret);
+        assert_eq!(secret, HeaderEntry::new("authorization", String::from("token")).with_sensitive(true));
+        // static and shared strings are the same
+        assert_eq!(plain, HeaderEntry::new(Rc::new(String::from("authorization")), "token"));
+        // names are case sensitive
+        assert!(plain != HeaderEntry::new("Authorization", "token"));
+
+        let mut set = HashSet::new();
+        assert!(set.insert(plain.clone()));
+        assert!(set.insert(secret.clone()));
+        assert!(!set.insert(HeaderEntry::new(String::from("authorization"), "token")));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&secret));
+        assert!(!set.contains(&HeaderEntry::new("authorization", "other")));
+    }
+
+    #[test]
+    fn merge_cookie_crumbs() {
+        use header::{HeaderEntry, Encoder, Decoder, merge_cookies};
+
+        let sent: Vec<HeaderEntry> = vec![
+            (":method", "GET").into(),
+            ("cookie", "a=b").into(),
+            ("accept", "*/*").into(),
+            ("cookie", "c=d").into(),
+            HeaderEntry::new("cookie", "e=f").with_sensitive(true),
+        ];
+        let block = Encoder::new(4096, 10).encode(&sent);
+        let mut headers = Decoder::new(4096, 10).decode(&block).unwrap();
+        // the crumbs are kept by default
+        assert_eq!(headers, sent);
+
+        merge_cookies(&mut headers);
+        assert_eq!(headers, vec![
+            (":method", "GET").into(),
+            HeaderEntry::new("cookie", "a=b; c=d; e=f").with_sensitive(true),
+            ("accept", "*/*").into(),
+        ]);
+
+        // a single cookie is left alone
+        let mut headers: Vec<HeaderEntry> = vec![("cookie", "a=b").into(), ("accept", "*/*").into()];
+        merge_cookies(&mut headers);
+        assert_eq!(headers, vec![("cookie", "a=b").into(), ("accept", "*/*").into()]);
+    }
+
+    #[test]
+    fn validate_entry_name() {
+        use header::{HeaderEntry, ProtocolError};
+
+        let entry: HeaderEntry = ("content-type", "text/html").into();
+        assert_eq!(entry.validate_name(), Ok(()));
+        let entry: HeaderEntry = ("Content-Type", "text/html").into();
+        assert_eq!(entry.validate_name(), Err(ProtocolError::UppercaseName));
+        let entry: HeaderEntry = ("content type", "text/html").into();
+        assert_eq!(entry.validate_name(), Err(ProtocolError::InvalidNameChar(b' ')));
+    }
 }
@@ -4,6 +4,16 @@
 
 mod list;
 mod hpack;
+mod validate;
+#[cfg(feature = "http")]
+mod http_map;
 
-pub use self::list::{HeaderEntry, HeaderList, EntryInner};
-pub use self::hpack::decoder::{Decoder};
+pub use self::list::{HeaderEntry, HeaderList, EntryInner, DecodedHeader, BorrowedHeader, HeaderArena,
+    merge_cookies};
+pub use self::hpack::decoder::{Decoder, DecoderBuilder, DecodeIter, Instruction, TraceEvent, peek_size_update};
+pub use self::hpack::encoder::{Encoder, IndexingPolicy, HuffmanPolicy, Representation};
+pub use self::hpack::{HpackError, IntegerError, HuffmanError, static_names};
+pub use self::validate::{ProtocolError, validate_request_headers, validate_response_headers,
+    validate_connection_headers, content_length, DataLengthTracker};
+#[cfg(feature = "http")]
+pub use self::http_map::HeaderFields;
@@ -0,0 +1,370 @@
+//! Checks on header fields that HTTP/2 puts on top of hpack
+//!
+//! RFC 7540 8.1.2
+//! Just as in HTTP/1.x, header field names are strings of ASCII characters that are compared
+//! in a case-insensitive fashion. However, header field names MUST be converted to lowercase
+//! prior to their encoding in HTTP/2. A request or response containing uppercase header field
+//! names MUST be treated as malformed (Section 8.1.2.6).
+
+use std::fmt;
+use std::error::Error;
+use std::borrow::Cow;
+
+use super::HeaderEntry;
+
+/// A header field that makes the request or response malformed
+///
+/// These are stream errors of type PROTOCOL_ERROR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// the name has an uppercase ASCII letter in it
+    UppercaseName,
+    /// the name has a character outside of the token characters
+    InvalidNameChar(u8),
+    /// names can not be empty
+    EmptyName,
+    /// a pseudo header came after a regular header
+    PseudoHeaderAfterRegular,
+    /// a pseudo header that is not defined (or not allowed
+    /// for this kind of message, eg. :status in a request)
+    UnknownPseudoHeader,
+    /// a connection specific header, these do not exist in HTTP/2
+    ConnectionHeader,
+    /// the value has a character that is not allowed (eg. CR, LF or NUL)
+    InvalidValue,
+    /// content-length is not a number or there are several that disagree
+    InvalidContentLength,
+    /// the DATA frames of the stream do not add up to the content-length
+    ContentLengthMismatch,
+}
+
+impl ProtocolError {
+    fn as_str(&self) -> &'static str {
+        use self::ProtocolError::*;
+        match *self {
+            UppercaseName       => "header: field name has uppercase characters",
+            InvalidNameChar(_)  => "header: field name has an invalid character",
+            EmptyName           => "header: field name is empty",
+            PseudoHeaderAfterRegular => "header: pseudo header after a regular header",
+            UnknownPseudoHeader => "header: unknown pseudo header",
+            ConnectionHeader    => "header: connection specific header",
+            InvalidValue        => "header: field value has an invalid character",
+            InvalidContentLength => "header: invalid content-length",
+            ContentLengthMismatch => "header: data length does not match content-length",
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProtocolError::InvalidNameChar(c) => write!(f, "{} (0x{:02x})", self.as_str(), c),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl Error for ProtocolError {
+    fn description(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// a name is made of lowercase token characters (RFC 7230 3.2.6)
+// pseudo header names get their leading ':' and that is the
+// only place a ':' is allowed
+pub fn validate_name(name: &str) -> Result<(), ProtocolError> {
+    let bytes = name.as_bytes();
+    let rest = match bytes.first() {
+        None            => return Err(ProtocolError::EmptyName),
+        Some(&b':')     => &bytes[1..],
+        Some(_)         => bytes,
+    };
+    for &c in rest {
+        match c {
+            b'A' ... b'Z' => return Err(ProtocolError::UppercaseName),
+            b'a' ... b'z' | b'0' ... b'9' => {},
+            b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+'
+                | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {},
+            _ => return Err(ProtocolError::InvalidNameChar(c)),
+        }
+    }
+    Ok(())
+}
+
+// RFC 7540 8.1.2.3 and 8.1.2.4
+pub const REQUEST_PSEUDO_HEADERS: &'static [&'static str] = &[":method", ":scheme", ":authority", ":path"];
+pub const RESPONSE_PSEUDO_HEADERS: &'static [&'static str] = &[":status"];
+
+// all pseudo headers must come before the regular headers
+// and only the ones defined for the message are allowed
+//
+// RFC 7540 8.1.2.1
+pub fn validate_pseudo_headers(headers: &[HeaderEntry], allowed: &[&str]) -> Result<(), ProtocolError> {
+    let mut regular_seen = false;
+    for header in headers {
+        if header.name().starts_with(':') {
+            if regular_seen {
+                return Err(ProtocolError::PseudoHeaderAfterRegular);
+            }
+            if !allowed.contains(&header.name()) {
+                return Err(ProtocolError::UnknownPseudoHeader);
+            }
+        }
+        else {
+            regular_seen = true;
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_request_headers(headers: &[HeaderEntry]) -> Result<(), ProtocolError> {
+    validate_pseudo_headers(headers, REQUEST_PSEUDO_HEADERS)
+}
+
+pub fn validate_response_headers(headers: &[HeaderEntry]) -> Result<(), ProtocolError> {
+    validate_pseudo_headers(headers, RESPONSE_PSEUDO_HEADERS)
+}
+
+// RFC 7540 8.1.2.2
+pub const CONNECTION_HEADERS: &'static [&'static str] = &["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+// connection specific headers make the message malformed,
+// te is the exception as long as it is only "trailers"
+//
+// names are expected to be lowercase already (see validate_name)
+pub fn validate_connection_headers(headers: &[HeaderEntry]) -> Result<(), ProtocolError> {
+    for header in headers {
+        if CONNECTION_HEADERS.contains(&header.name()) {
+            return Err(ProtocolError::ConnectionHeader);
+        }
+        if header.name() == "te" && header.value() != "trailers" {
+            return Err(ProtocolError::ConnectionHeader);
+        }
+    }
+    Ok(())
+}
+
+// the value of the content-length header if there is one
+//
+// it can be repeated (or be a list) as long as every value is the
+// same (RFC 7230 3.3.2), anything else is malformed
+pub fn content_length(headers: &[HeaderEntry]) -> Result<Option<u64>, ProtocolError> {
+    let mut length = None;
+    for header in headers.iter().filter(|h| h.name() == "content-length") {
+        for value in header.value().split(',') {
+            let value = value.trim_matches(|c| c == ' ' || c == '\t');
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ProtocolError::InvalidContentLength);
+            }
+            let value = try!(value.parse().map_err(|_| ProtocolError::InvalidContentLength));
+            match length {
+                Some(l) if l != value => return Err(ProtocolError::InvalidContentLength),
+                _ => length = Some(value),
+            }
+        }
+    }
+    Ok(length)
+}
+
+/// Adds up the DATA frame payloads of a stream to check them
+/// against its content-length (RFC 7540 8.1.2.6)
+///
+/// Without a content-length anything goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataLengthTracker {
+    expected: Option<u64>,
+    received: u64,
+}
+
+impl DataLengthTracker {
+    pub fn new(expected: Option<u64>) -> Self {
+        DataLengthTracker { expected: expected, received: 0 }
+    }
+
+    // expect the content-length of the decoded headers
+    pub fn from_headers(headers: &[HeaderEntry]) -> Result<Self, ProtocolError> {
+        Ok(DataLengthTracker::new(try!(content_length(headers))))
+    }
+
+    // add the data of one DATA frame (the padding does not count)
+    //
+    // going past the content-length is an error right away
+    // rather than at the end of the stream
+    pub fn add(&mut self, len: usize) -> Result<(), ProtocolError> {
+        self.received = self.received.saturating_add(len as u64);
+        match self.expected {
+            Some(expected) if self.received > expected => Err(ProtocolError::ContentLengthMismatch),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    // call on END_STREAM
+    pub fn end_stream(&self) -> Result<(), ProtocolError> {
+        match self.expected {
+            Some(expected) if self.received != expected => Err(ProtocolError::ContentLengthMismatch),
+            _ => Ok(()),
+        }
+    }
+}
+
+// names from the application may not be lowercase yet
+// this compares them against a (lowercase) table name
+pub fn ascii_eq_ignore_case(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+// the name as it must go on the wire, only
+// allocates when there is something to change
+pub fn lowercase_name(name: &str) -> Cow<str> {
+    if name.bytes().any(|b| b.is_ascii_uppercase()) {
+        Cow::Owned(name.to_ascii_lowercase())
+    }
+    else {
+        Cow::Borrowed(name)
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+
+    use std::borrow::Cow;
+    use super::{validate_name, ascii_eq_ignore_case, lowercase_name, ProtocolError};
+
+    #[test]
+    fn ignore_case() {
+        assert!(ascii_eq_ignore_case("Content-Type", "content-type"));
+        assert!(ascii_eq_ignore_case("content-type", "content-type"));
+        assert!(!ascii_eq_ignore_case("content-typ", "content-type"));
+
+        assert_eq!(lowercase_name("Content-Type"), "content-type");
+        match lowercase_name("content-type") {
+            Cow::Borrowed(_) => {},
+            Cow::Owned(_) => panic!("lowercase name was copied"),
+        }
+    }
+
+    #[test]
+    fn valid_names() {
+        assert_eq!(validate_name("content-type"), Ok(()));
+        assert_eq!(validate_name(":authority"), Ok(()));
+        assert_eq!(validate_name("x-custom_header.v2"), Ok(()));
+    }
+
+    #[test]
+    fn invalid_names() {
+        assert_eq!(validate_name("Content-Type"), Err(ProtocolError::UppercaseName));
+        assert_eq!(validate_name("content type"), Err(ProtocolError::InvalidNameChar(b' ')));
+        assert_eq!(validate_name("bad\x01name"), Err(ProtocolError::InvalidNameChar(0x01)));
+        assert_eq!(validate_name("a:b"), Err(ProtocolError::InvalidNameChar(b':')));
+        assert_eq!(validate_name("::a"), Err(ProtocolError::InvalidNameChar(b':')));
+        assert_eq!(validate_name(""), Err(ProtocolError::EmptyName));
+    }
+
+    #[test]
+    fn pseudo_header_order() {
+        use header::HeaderEntry;
+        use super::{validate_request_headers, validate_response_headers};
+
+        let request: Vec<HeaderEntry> = vec![
+            (":method", "GET").into(),
+            (":path", "/").into(),
+            ("accept", "*/*").into(),
+        ];
+        assert_eq!(validate_request_headers(&request), Ok(()));
+        assert_eq!(validate_request_headers(&[]), Ok(()));
+
+        let late: Vec<HeaderEntry> = vec![
+            (":method", "GET").into(),
+            ("accept", "*/*").into(),
+            (":path", "/").into(),
+        ];
+        assert_eq!(validate_request_headers(&late), Err(ProtocolError::PseudoHeaderAfterRegular));
+
+        let unknown: Vec<HeaderEntry> = vec![(":method", "GET").into(), (":foo", "bar").into()];
+        assert_eq!(validate_request_headers(&unknown), Err(ProtocolError::UnknownPseudoHeader));
+
+        // :status is only for responses
+        let response: Vec<HeaderEntry> = vec![(":status", "200").into(), ("server", "kurisu").into()];
+        assert_eq!(validate_request_headers(&response), Err(ProtocolError::UnknownPseudoHeader));
+        assert_eq!(validate_response_headers(&response), Ok(()));
+        assert_eq!(validate_response_headers(&request), Err(ProtocolError::UnknownPseudoHeader));
+    }
+
+    #[test]
+    fn connection_headers() {
+        use header::HeaderEntry;
+        use super::validate_connection_headers;
+
+        let ok: Vec<HeaderEntry> = vec![
+            (":method", "GET").into(),
+            ("te", "trailers").into(),
+            ("accept", "*/*").into(),
+        ];
+        assert_eq!(validate_connection_headers(&ok), Ok(()));
+
+        let connection: Vec<HeaderEntry> = vec![(":method", "GET").into(), ("connection", "keep-alive").into()];
+        assert_eq!(validate_connection_headers(&connection), Err(ProtocolError::ConnectionHeader));
+
+        for name in &["keep-alive", "proxy-connection", "transfer-encoding", "upgrade"] {
+            let headers: Vec<HeaderEntry> = vec![(*name, "x").into()];
+            assert_eq!(validate_connection_headers(&headers), Err(ProtocolError::ConnectionHeader));
+        }
+
+        let te: Vec<HeaderEntry> = vec![("te", "gzip").into()];
+        assert_eq!(validate_connection_headers(&te), Err(ProtocolError::ConnectionHeader));
+        let te: Vec<HeaderEntry> = vec![("te", "trailers, gzip").into()];
+        assert_eq!(validate_connection_headers(&te), Err(ProtocolError::ConnectionHeader));
+    }
+
+    #[test]
+    fn content_length_values() {
+        use header::HeaderEntry;
+        use super::content_length;
+
+        assert_eq!(content_length(&[]), Ok(None));
+        let headers: Vec<HeaderEntry> = vec![(":status", "200").into(), ("content-length", "1234").into()];
+        assert_eq!(content_length(&headers), Ok(Some(1234)));
+
+        // the same value more than once is fine
+        let headers: Vec<HeaderEntry> = vec![("content-length", "5").into(), ("content-length", "5").into()];
+        assert_eq!(content_length(&headers), Ok(Some(5)));
+        let headers: Vec<HeaderEntry> = vec![("content-length", "5, 5").into()];
+        assert_eq!(content_length(&headers), Ok(Some(5)));
+
+        let headers: Vec<HeaderEntry> = vec![("content-length", "5").into(), ("content-length", "6").into()];
+        assert_eq!(content_length(&headers), Err(ProtocolError::InvalidContentLength));
+        for value in &["", "abc", "-1", "+5", "1 2", "5, 6", "99999999999999999999"] {
+            let headers: Vec<HeaderEntry> = vec![("content-length", *value).into()];
+            assert_eq!(content_length(&headers), Err(ProtocolError::InvalidContentLength), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn data_length_tracker() {
+        use header::HeaderEntry;
+        use super::DataLengthTracker;
+
+        let headers: Vec<HeaderEntry> = vec![("content-length", "10").into()];
+        let mut tracker = DataLengthTracker::from_headers(&headers).unwrap();
+        assert_eq!(tracker.add(4), Ok(()));
+        assert_eq!(tracker.end_stream(), Err(ProtocolError::ContentLengthMismatch));
+        assert_eq!(tracker.add(6), Ok(()));
+        assert_eq!(tracker.received(), 10);
+        assert_eq!(tracker.end_stream(), Ok(()));
+        // one more octet is too many
+        assert_eq!(tracker.add(1), Err(ProtocolError::ContentLengthMismatch));
+
+        // no content-length
+        let mut tracker = DataLengthTracker::new(None);
+        assert_eq!(tracker.add(100), Ok(()));
+        assert_eq!(tracker.end_stream(), Ok(()));
+
+        let headers: Vec<HeaderEntry> = vec![("content-length", "1").into(), ("content-length", "2").into()];
+        assert_eq!(DataLengthTracker::from_headers(&headers), Err(ProtocolError::InvalidContentLength));
+    }
+}
@@ -1,8 +1,23 @@
+#![cfg_attr(feature = "bench", feature(test))]
+
 extern crate krs_ssl;
 
+// the hpack integer codec only uses core so it can be
+// lifted out for targets without std (see the no_std feature)
+extern crate core;
+
+#[cfg(all(test, feature = "bench"))]
+extern crate test;
+
+#[cfg(test)]
+extern crate proptest;
+
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(feature = "http")]
+extern crate http;
+
 #[macro_use]
 mod krserr;
 
@@ -97,7 +112,11 @@ fn handle_client<T: Read + Write + Debug>(mut stream: T) {
                     println!("{:?}", frame);
                     let hf: HeadersFrame = frame.into();
                     let mut dec = Decoder::new(4096, 20);
-                    let res = dec.get_header_list(hf.get_header_data().header_block_fragment);
+                    let h_data = match hf.get_header_data() {
+                        Ok(h_data) => h_data,
+                        Err(e) => { println!("{}", e); continue; },
+                    };
+                    let res = dec.get_header_list(h_data.header_block_fragment);
 
                     match res {
                         Ok(hl) => {